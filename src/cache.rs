@@ -0,0 +1,239 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::{LazyLock, Mutex, MutexGuard};
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use url::Url;
+
+use crate::settings;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct CacheSettings {
+    /// Maximum size of each of the memory and disk caches in megabytes, 0 disables caching
+    pub size_mb: u32,
+    /// How long a response stays fresh in minutes
+    pub expiry_minutes: u32,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        Self {
+            size_mb: 32,
+            expiry_minutes: 60,
+        }
+    }
+}
+
+impl CacheSettings {
+    fn max_bytes(&self) -> usize {
+        self.size_mb as usize * 1024 * 1024
+    }
+
+    // Don't let a single large download push everything else out
+    fn max_entry_bytes(&self) -> usize {
+        self.max_bytes() / 4
+    }
+
+    fn is_fresh(&self, fetched: i64) -> bool {
+        OffsetDateTime::now_utc().unix_timestamp() - fetched < self.expiry_minutes as i64 * 60
+    }
+}
+
+#[derive(Clone)]
+pub struct CachedResponse {
+    pub content_type: String,
+    pub content: Vec<u8>,
+    fetched: i64,
+}
+
+#[derive(Default)]
+struct Cache {
+    settings: CacheSettings,
+    entries: HashMap<String, CachedResponse>,
+    /// URLs from least to most recently used
+    order: VecDeque<String>,
+    size: usize,
+}
+
+impl Cache {
+    fn touch(&mut self, url: &str) {
+        self.order.retain(|u| u != url);
+        self.order.push_back(url.to_string());
+    }
+
+    fn remove(&mut self, url: &str) {
+        if let Some(entry) = self.entries.remove(url) {
+            self.size -= entry.content.len();
+        }
+        self.order.retain(|u| u != url);
+    }
+
+    fn insert(&mut self, url: &str, response: CachedResponse) {
+        self.remove(url);
+        self.size += response.content.len();
+        self.entries.insert(url.to_string(), response);
+        self.touch(url);
+        while self.size > self.settings.max_bytes() {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            self.remove(&oldest);
+        }
+    }
+}
+
+static CACHE: LazyLock<Mutex<Cache>> = LazyLock::new(|| {
+    Mutex::new(Cache {
+        settings: settings::settings().cache,
+        ..Default::default()
+    })
+});
+
+fn cache() -> MutexGuard<'static, Cache> {
+    CACHE.lock().expect("Failed to lock cache mutex")
+}
+
+/// Applies new limits, saving them is left to the main settings
+pub fn set_settings(settings: CacheSettings) {
+    let mut cache = cache();
+    cache.settings = settings;
+    // Shrink down to the new limit straight away
+    let urls: Vec<String> = cache.order.iter().cloned().collect();
+    for url in urls {
+        if cache.size <= settings.max_bytes() {
+            break;
+        }
+        cache.remove(&url);
+    }
+    drop(cache);
+    prune_disk(&settings);
+}
+
+fn disk_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("breeze"))
+}
+
+fn disk_path(url: &str) -> Option<PathBuf> {
+    let mut hasher = DefaultHasher::new();
+    url.hash(&mut hasher);
+    disk_dir().map(|dir| dir.join(format!("{:016x}", hasher.finish())))
+}
+
+// Entries on disk are the URL, content type and fetch time on their own lines, then the body
+fn read_disk(url: &str) -> Option<CachedResponse> {
+    let data = fs::read(disk_path(url)?).ok()?;
+    let mut parts = data.splitn(4, |b| *b == b'\n');
+    let stored_url = parts.next()?;
+    let content_type = String::from_utf8_lossy(parts.next()?).to_string();
+    let fetched = String::from_utf8_lossy(parts.next()?).parse().ok()?;
+    let content = parts.next().unwrap_or_default().to_vec();
+    // Hashes can collide, so check this is really the same page
+    (stored_url == url.as_bytes()).then_some(CachedResponse {
+        content_type,
+        content,
+        fetched,
+    })
+}
+
+fn write_disk(url: &str, response: &CachedResponse) -> std::io::Result<()> {
+    let Some(path) = disk_path(url) else {
+        return Ok(());
+    };
+    fs::create_dir_all(path.parent().unwrap())?;
+    let mut data =
+        format!("{}\n{}\n{}\n", url, response.content_type, response.fetched).into_bytes();
+    data.extend_from_slice(&response.content);
+    fs::write(path, data)
+}
+
+// Removes the least recently written entries until the disk cache fits its limit
+fn prune_disk(settings: &CacheSettings) {
+    let Some(entries) = disk_dir().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return;
+    };
+    let mut files: Vec<_> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let metadata = e.metadata().ok()?;
+            Some((metadata.modified().ok()?, metadata.len() as usize, e.path()))
+        })
+        .collect();
+    files.sort();
+    let mut size: usize = files.iter().map(|(_, len, _)| len).sum();
+    for (_, len, path) in files {
+        if size <= settings.max_bytes() {
+            break;
+        }
+        if fs::remove_file(path).is_ok() {
+            size -= len;
+        }
+    }
+}
+
+/// Returns a fresh cached response for the URL from memory or disk
+pub fn get(url: &Url) -> Option<CachedResponse> {
+    let url = url.as_str();
+    let mut cache = cache();
+    let settings = cache.settings;
+    if let Some(entry) = cache.entries.get(url) {
+        if settings.is_fresh(entry.fetched) {
+            let entry = entry.clone();
+            cache.touch(url);
+            return Some(entry);
+        }
+        cache.remove(url);
+    }
+
+    let entry = read_disk(url).filter(|entry| settings.is_fresh(entry.fetched))?;
+    if entry.content.len() <= settings.max_entry_bytes() {
+        cache.insert(url, entry.clone());
+    }
+    Some(entry)
+}
+
+/// Caches a response, only in memory if it may be personal to whoever asked for it
+pub fn insert(url: &Url, content_type: &str, content: &[u8], identified: bool) {
+    // Answers to queries and to requests made with a certificate stay off the disk
+    let to_disk = url.query().is_none() && !identified;
+    let url = url.as_str();
+    let mut cache = cache();
+    let settings = cache.settings;
+    if content.len() > settings.max_entry_bytes() {
+        return;
+    }
+    let response = CachedResponse {
+        content_type: content_type.to_string(),
+        content: content.to_vec(),
+        fetched: OffsetDateTime::now_utc().unix_timestamp(),
+    };
+    if to_disk {
+        if let Err(e) = write_disk(url, &response) {
+            println!("Failed to write {} to the disk cache: {}", url, e);
+        }
+    }
+    cache.insert(url, response);
+    drop(cache);
+    prune_disk(&settings);
+}
+
+pub fn remove(url: &Url) {
+    cache().remove(url.as_str());
+    if let Some(path) = disk_path(url.as_str()) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+pub fn clear() {
+    let mut cache = cache();
+    cache.entries.clear();
+    cache.order.clear();
+    cache.size = 0;
+    if let Some(dir) = disk_dir() {
+        let _ = fs::remove_dir_all(dir);
+    }
+}
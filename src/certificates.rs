@@ -0,0 +1,727 @@
+use std::fs;
+
+use eframe::egui::{
+    Button, CollapsingHeader, ComboBox, Context, DragValue, Grid, Key, Modal, RichText, ScrollArea,
+    SidePanel, TextEdit, Ui,
+};
+use poll_promise::Promise;
+use url::Url;
+
+use crate::db::{
+    add_binding, delete_profile, get_bindings, get_profile, remove_binding, rename_profile,
+    set_active_profile,
+};
+use crate::handlers::Protocol;
+use crate::profile::{
+    binding_for, change_passphrase, keys_encrypted, keys_locked, unlock, Binding, KeyType, Profile,
+};
+use crate::{Breeze, NavigationHint};
+
+/// State for the certificate manager, where client certificate profiles are created,
+/// imported, exported and bound to the sites they are used on
+pub struct Certificates {
+    selected: Option<String>,
+    bindings: Vec<Binding>,
+    should_refresh: bool,
+    status: String,
+    // Creating
+    new_name: String,
+    key_type: KeyType,
+    days: i64,
+    generate_job: Option<Promise<Result<Profile, String>>>,
+    // Importing
+    import_name: String,
+    import_path: String,
+    import_key_path: String,
+    import_password: String,
+    // Managing the selected profile
+    rename_to: String,
+    export_path: String,
+    export_password: String,
+    bind_host: String,
+    bind_path: String,
+    confirm_delete: bool,
+    // Key encryption
+    show_unlock: bool,
+    passphrase: String,
+    new_passphrase: String,
+    confirm_passphrase: String,
+}
+
+impl Default for Certificates {
+    fn default() -> Self {
+        Self {
+            selected: None,
+            bindings: Vec::new(),
+            should_refresh: true,
+            status: String::new(),
+            new_name: String::new(),
+            key_type: KeyType::available()[0],
+            days: 1825,
+            generate_job: None,
+            import_name: String::new(),
+            import_path: String::new(),
+            import_key_path: String::new(),
+            import_password: String::new(),
+            rename_to: String::new(),
+            export_path: String::new(),
+            export_password: String::new(),
+            bind_host: String::new(),
+            bind_path: "/".to_string(),
+            confirm_delete: false,
+            show_unlock: keys_locked(),
+            passphrase: String::new(),
+            new_passphrase: String::new(),
+            confirm_passphrase: String::new(),
+        }
+    }
+}
+
+impl Certificates {
+    fn select(&mut self, name: Option<String>) {
+        self.rename_to = name.clone().unwrap_or_default();
+        self.confirm_delete = false;
+        self.selected = name;
+    }
+
+    fn poll_generate_job(&mut self) -> bool {
+        let Some(job) = &self.generate_job else {
+            return false;
+        };
+        let Some(result) = job.ready() else {
+            return false;
+        };
+        match result {
+            Ok(profile) => {
+                self.status = format!("Created {}", profile.name);
+                self.new_name.clear();
+                self.select(Some(profile.name.clone()));
+            }
+            Err(e) => self.status = e.clone(),
+        }
+        self.generate_job = None;
+        true
+    }
+
+    fn generate(&mut self) {
+        let name = self.new_name.clone();
+        let key_type = self.key_type;
+        let days = self.days;
+        // Large RSA keys take a while to find, so don't hold up the interface
+        self.status = format!("Generating a {} key...", key_type.label());
+        self.generate_job = Some(Promise::spawn_thread("certificate", move || {
+            Profile::generate(name, key_type, days)
+        }));
+    }
+
+    fn import(&mut self) -> Result<Profile, String> {
+        let read = |path: &str| {
+            fs::read(path.trim()).map_err(|e| format!("Failed to read {}: {}", path.trim(), e))
+        };
+        let data = read(&self.import_path)?;
+        let key_data = match self.import_key_path.trim() {
+            "" => None,
+            path => Some(read(path)?),
+        };
+        Profile::import(
+            self.import_name.clone(),
+            &data,
+            key_data.as_deref(),
+            &self.import_password,
+        )
+    }
+
+    fn unlock(&mut self) -> bool {
+        match unlock(&self.passphrase) {
+            Ok(()) => {
+                self.status = "Keys unlocked".to_string();
+                self.passphrase.clear();
+                self.show_unlock = false;
+                true
+            }
+            Err(e) => {
+                self.status = e;
+                false
+            }
+        }
+    }
+
+    fn export(&self, profile: &Profile) -> Result<String, String> {
+        let path = self.export_path.trim();
+        if path.is_empty() {
+            return Err("Choose a file to export to".to_string());
+        }
+        let lower = path.to_lowercase();
+        let data = if lower.ends_with(".p12") || lower.ends_with(".pfx") {
+            profile.to_pkcs12(&self.export_password)?
+        } else {
+            profile.to_pem().into_bytes()
+        };
+        fs::write(path, data).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        Ok(format!("Exported {} to {}", profile.name, path))
+    }
+}
+
+fn render_details(ui: &mut Ui, profile: &Profile) {
+    let details = match profile.details() {
+        Ok(details) => details,
+        Err(e) => {
+            ui.label(format!("Unable to read certificate: {}", e));
+            return;
+        }
+    };
+    Grid::new("certificate_details")
+        .num_columns(2)
+        .show(ui, |ui| {
+            ui.label("Subject");
+            ui.label(details.subject.as_str());
+            ui.end_row();
+            ui.label("Key");
+            ui.label(details.key_type.as_str());
+            ui.end_row();
+            ui.label("Expires");
+            let date = details.expires.date().to_string();
+            if details.expired() {
+                ui.label(RichText::new(format!("{} (expired)", date)).strong());
+            } else {
+                ui.label(date);
+            }
+            ui.end_row();
+        });
+}
+
+fn render_profile(ui: &mut Ui, breeze: &mut Breeze, index: usize) {
+    let certificates = &mut breeze.certificates;
+    let profile = &breeze.profiles[index];
+    ui.heading(&profile.name);
+    render_details(ui, profile);
+    ui.add_space(8.0);
+
+    if profile.active {
+        ui.label("Mail is sent as this profile");
+    } else if ui.button("Send mail as this profile").clicked() {
+        match set_active_profile(profile.name.clone()) {
+            Ok(()) => breeze.should_update_profiles = true,
+            Err(e) => certificates.status = e.to_string(),
+        }
+    }
+    ui.separator();
+
+    // Sites the certificate is presented to, no other site sees it
+    ui.label(RichText::new("Used on").strong());
+    let mut removed = None;
+    let bindings = certificates
+        .bindings
+        .iter()
+        .filter(|binding| binding.profile == profile.name);
+    for binding in bindings {
+        ui.horizontal(|ui| {
+            ui.label(format!("{}{}", binding.host, binding.path));
+            if ui
+                .small_button("✕")
+                .on_hover_text("Stop using here")
+                .clicked()
+            {
+                removed = Some(binding.clone());
+            }
+        });
+    }
+    if let Some(binding) = removed {
+        if let Err(e) = remove_binding(&binding) {
+            certificates.status = e.to_string();
+        }
+        certificates.should_refresh = true;
+    }
+    ui.horizontal(|ui| {
+        ui.add(
+            TextEdit::singleline(&mut certificates.bind_host)
+                .hint_text("Host")
+                .desired_width(160.0),
+        );
+        ui.add(
+            TextEdit::singleline(&mut certificates.bind_path)
+                .hint_text("Path")
+                .desired_width(120.0),
+        );
+        if ui.button("Add").clicked() {
+            let host = certificates.bind_host.trim().to_lowercase();
+            let path = certificates.bind_path.trim();
+            if host.is_empty() || !path.starts_with('/') {
+                certificates.status = "Bindings need a host and a path starting with /".to_string();
+            } else {
+                let binding = Binding {
+                    profile: profile.name.clone(),
+                    host,
+                    path: path.to_string(),
+                };
+                if let Err(e) = add_binding(&binding) {
+                    certificates.status = e.to_string();
+                }
+                certificates.should_refresh = true;
+            }
+        }
+        if let Some(host) = breeze.current_url.host_str() {
+            if ui.button(format!("Use on {}", host)).clicked() {
+                certificates.bind_host = host.to_string();
+                certificates.bind_path = "/".to_string();
+            }
+        }
+    });
+    ui.separator();
+
+    ui.horizontal(|ui| {
+        ui.add(TextEdit::singleline(&mut certificates.rename_to).desired_width(160.0));
+        let new_name = certificates.rename_to.trim();
+        let taken = breeze.profiles.iter().any(|p| p.name == new_name);
+        if ui
+            .add_enabled(!new_name.is_empty() && !taken, Button::new("Rename"))
+            .clicked()
+        {
+            match rename_profile(&profile.name, new_name) {
+                Ok(()) => {
+                    certificates.selected = Some(new_name.to_string());
+                    breeze.should_update_profiles = true;
+                    certificates.should_refresh = true;
+                }
+                Err(e) => certificates.status = e.to_string(),
+            }
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.add(
+            TextEdit::singleline(&mut certificates.export_path)
+                .hint_text("identity.pem or identity.p12")
+                .desired_width(240.0),
+        );
+        ui.add(
+            TextEdit::singleline(&mut certificates.export_password)
+                .hint_text("PKCS#12 password")
+                .password(true)
+                .desired_width(120.0),
+        );
+        if ui.button("Export").clicked() {
+            certificates.status = certificates.export(profile).unwrap_or_else(|e| e);
+        }
+    });
+    ui.add_space(8.0);
+    if certificates.confirm_delete {
+        ui.horizontal(|ui| {
+            ui.label("Delete this certificate for good?");
+            if ui.button("Delete").clicked() {
+                match delete_profile(&profile.name) {
+                    Ok(()) => {
+                        certificates.status = format!("Deleted {}", profile.name);
+                        certificates.select(None);
+                        breeze.should_update_profiles = true;
+                        certificates.should_refresh = true;
+                    }
+                    Err(e) => certificates.status = e.to_string(),
+                }
+            }
+            if ui.button("Cancel").clicked() {
+                certificates.confirm_delete = false;
+            }
+        });
+    } else if ui.button("Delete").clicked() {
+        certificates.confirm_delete = true;
+    }
+}
+
+fn render_new(ui: &mut Ui, breeze: &mut Breeze) {
+    let certificates = &mut breeze.certificates;
+    CollapsingHeader::new("New certificate").show(ui, |ui| {
+        Grid::new("new_certificate").num_columns(2).show(ui, |ui| {
+            ui.label("Name");
+            ui.text_edit_singleline(&mut certificates.new_name);
+            ui.end_row();
+            ui.label("Key");
+            ComboBox::from_id_salt("key_type")
+                .selected_text(certificates.key_type.label())
+                .show_ui(ui, |ui| {
+                    for key_type in KeyType::available() {
+                        ui.selectable_value(
+                            &mut certificates.key_type,
+                            *key_type,
+                            key_type.label(),
+                        );
+                    }
+                });
+            ui.end_row();
+            ui.label("Valid for");
+            ui.add(
+                DragValue::new(&mut certificates.days)
+                    .range(1..=36500)
+                    .suffix(" days"),
+            );
+            ui.end_row();
+        });
+        let busy = certificates.generate_job.is_some();
+        if ui.add_enabled(!busy, Button::new("Create")).clicked() {
+            certificates.generate();
+        }
+    });
+    CollapsingHeader::new("Import certificate").show(ui, |ui| {
+        Grid::new("import_certificate")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Name");
+                ui.text_edit_singleline(&mut certificates.import_name);
+                ui.end_row();
+                ui.label("Certificate");
+                ui.add(
+                    TextEdit::singleline(&mut certificates.import_path)
+                        .hint_text("PEM file or PKCS#12 archive"),
+                );
+                ui.end_row();
+                ui.label("Key");
+                ui.add(
+                    TextEdit::singleline(&mut certificates.import_key_path)
+                        .hint_text("If kept in a separate PEM file"),
+                );
+                ui.end_row();
+                ui.label("Password");
+                ui.add(
+                    TextEdit::singleline(&mut certificates.import_password)
+                        .hint_text("For PKCS#12 archives")
+                        .password(true),
+                );
+                ui.end_row();
+            });
+        if ui.button("Import").clicked() {
+            match certificates.import() {
+                Ok(profile) => {
+                    certificates.status = format!("Imported {}", profile.name);
+                    certificates.import_name.clear();
+                    certificates.import_password.clear();
+                    certificates.select(Some(profile.name));
+                    breeze.should_update_profiles = true;
+                }
+                Err(e) => certificates.status = e,
+            }
+        }
+    });
+}
+
+fn render_passphrase(ui: &mut Ui, breeze: &mut Breeze) {
+    let certificates = &mut breeze.certificates;
+    CollapsingHeader::new("Key encryption").show(ui, |ui| {
+        if keys_locked() {
+            ui.label("Your keys are encrypted, enter the passphrase to use them.");
+            ui.horizontal(|ui| {
+                ui.add(TextEdit::singleline(&mut certificates.passphrase).password(true));
+                if ui.button("Unlock").clicked() && certificates.unlock() {
+                    breeze.should_update_profiles = true;
+                }
+            });
+            return;
+        }
+        let encrypted = keys_encrypted();
+        if encrypted {
+            ui.label("Private keys are encrypted with a passphrase.");
+        } else {
+            ui.label("Private keys are stored unencrypted.");
+        }
+        Grid::new("passphrase").num_columns(2).show(ui, |ui| {
+            ui.label("New passphrase");
+            ui.add(TextEdit::singleline(&mut certificates.new_passphrase).password(true));
+            ui.end_row();
+            ui.label("Confirm");
+            ui.add(TextEdit::singleline(&mut certificates.confirm_passphrase).password(true));
+            ui.end_row();
+        });
+        ui.horizontal(|ui| {
+            let matching = !certificates.new_passphrase.is_empty()
+                && certificates.new_passphrase == certificates.confirm_passphrase;
+            let label = if encrypted {
+                "Change passphrase"
+            } else {
+                "Encrypt keys"
+            };
+            if ui.add_enabled(matching, Button::new(label)).clicked() {
+                certificates.status = match change_passphrase(Some(&certificates.new_passphrase)) {
+                    Ok(()) => "Keys encrypted".to_string(),
+                    Err(e) => e,
+                };
+                certificates.new_passphrase.clear();
+                certificates.confirm_passphrase.clear();
+            }
+            if encrypted && ui.button("Remove passphrase").clicked() {
+                certificates.status = match change_passphrase(None) {
+                    Ok(()) => "Keys are no longer encrypted".to_string(),
+                    Err(e) => e,
+                };
+            }
+        });
+    });
+}
+
+/// Asks for the passphrase on startup when stored keys are encrypted
+pub fn render_unlock(ctx: &Context, breeze: &mut Breeze) {
+    let certificates = &mut breeze.certificates;
+    if !certificates.show_unlock {
+        return;
+    }
+    Modal::new("unlock".into()).show(ctx, |ui| {
+        ui.label("Enter your passphrase to unlock your client certificates");
+        let field = ui.add(TextEdit::singleline(&mut certificates.passphrase).password(true));
+        field.request_focus();
+        if !certificates.status.is_empty() {
+            ui.label(&certificates.status);
+        }
+        ui.horizontal(|ui| {
+            let submitted = field.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter));
+            if (ui.button("Unlock").clicked() || submitted) && certificates.unlock() {
+                breeze.should_update_profiles = true;
+            }
+            if ui.button("Not now").clicked() {
+                certificates.show_unlock = false;
+                certificates.status.clear();
+            }
+        });
+    });
+}
+
+pub fn render_certificates(ui: &mut Ui, _ctx: &Context, breeze: &mut Breeze) {
+    let certificates = &mut breeze.certificates;
+    if certificates.poll_generate_job() {
+        breeze.should_update_profiles = true;
+    }
+    if certificates.should_refresh {
+        certificates.should_refresh = false;
+        certificates.bindings = get_bindings().unwrap_or_default();
+    }
+
+    ui.label(&certificates.status);
+    ui.separator();
+
+    let mut selection = None;
+    SidePanel::left("certificate_list")
+        .default_width(200.0)
+        .show_inside(ui, |ui| {
+            ScrollArea::vertical().show(ui, |ui| {
+                if breeze.profiles.is_empty() {
+                    ui.label("No certificates");
+                }
+                for profile in &breeze.profiles {
+                    let selected = certificates.selected.as_ref() == Some(&profile.name);
+                    if ui.selectable_label(selected, &profile.name).clicked() {
+                        selection = Some(profile.name.clone());
+                    }
+                }
+            });
+        });
+    if selection.is_some() {
+        certificates.select(selection);
+    }
+
+    ScrollArea::vertical().show(ui, |ui| {
+        let selected = breeze.certificates.selected.as_ref();
+        if let Some(index) = breeze
+            .profiles
+            .iter()
+            .position(|p| Some(&p.name) == selected)
+        {
+            render_profile(ui, breeze, index);
+            ui.separator();
+        }
+        render_new(ui, breeze);
+        render_passphrase(ui, breeze);
+    });
+}
+
+/// Why a site turned the request away, as Gemini's 60, 61 and 62 statuses say
+#[derive(Clone, Copy, PartialEq)]
+pub enum CertificateProblem {
+    Required,
+    NotAuthorized,
+    NotValid,
+}
+
+#[derive(Clone, PartialEq)]
+enum Choice {
+    Existing(String),
+    New,
+    Regenerate(String),
+}
+
+/// A page that asked for a client certificate, waiting for the user to pick which to send
+pub struct CertificateRequest {
+    url: Url,
+    problem: CertificateProblem,
+    /// The profile that was presented, if any
+    current: Option<String>,
+    choice: Choice,
+    new_name: String,
+    path: String,
+    job: Option<Promise<Result<Profile, String>>>,
+    error: String,
+}
+
+impl CertificateRequest {
+    pub fn new(url: Url, problem: CertificateProblem) -> Self {
+        let bindings = get_bindings().unwrap_or_default();
+        let binding = binding_for(&bindings, &url);
+        let current = binding.map(|binding| binding.profile.clone());
+        // Replace the binding that was used rather than adding one it would take precedence over
+        let path = binding.map_or("/".to_string(), |binding| binding.path.clone());
+        let choice = match (&current, problem) {
+            (Some(current), CertificateProblem::NotValid) => Choice::Regenerate(current.clone()),
+            _ => Choice::New,
+        };
+        Self {
+            new_name: url.host_str().unwrap_or_default().to_string(),
+            path,
+            url,
+            problem,
+            current,
+            choice,
+            job: None,
+            error: String::new(),
+        }
+    }
+
+    fn message(&self) -> String {
+        let host = self.url.host_str().unwrap_or_default();
+        let current = self.current.as_deref().unwrap_or("your certificate");
+        match self.problem {
+            CertificateProblem::Required => format!("{} asks for a client certificate.", host),
+            CertificateProblem::NotAuthorized => {
+                format!(
+                    "{} isn't authorized to view this page on {}.",
+                    current, host
+                )
+            }
+            CertificateProblem::NotValid => {
+                format!(
+                    "{} rejected {} as invalid, it may have expired.",
+                    host, current
+                )
+            }
+        }
+    }
+
+    fn start(&mut self) {
+        let key_type = KeyType::available()[0];
+        let job = match self.choice.clone() {
+            Choice::New => {
+                let name = self.new_name.clone();
+                Promise::spawn_thread("certificate", move || {
+                    Profile::generate(name, key_type, 1825)
+                })
+            }
+            Choice::Regenerate(name) => Promise::spawn_thread("certificate", move || {
+                Profile::regenerate(name, key_type, 1825)
+            }),
+            Choice::Existing(name) => {
+                Promise::from_ready(get_profile(&name).map_err(|e| e.to_string()))
+            }
+        };
+        self.job = Some(job);
+    }
+
+    /// Once a profile is ready, binds it to the page's host and path and returns its name
+    fn poll(&mut self) -> Option<String> {
+        let result = self.job.as_ref()?.ready()?;
+        let name = match result {
+            Ok(profile) => profile.name.clone(),
+            Err(e) => {
+                self.error = e.clone();
+                self.job = None;
+                return None;
+            }
+        };
+        self.job = None;
+        let path = match self.path.trim() {
+            path if path.starts_with('/') => path.to_string(),
+            path => format!("/{}", path),
+        };
+        let binding = Binding {
+            profile: name.clone(),
+            host: self.url.host_str().unwrap_or_default().to_string(),
+            path,
+        };
+        match add_binding(&binding) {
+            Ok(()) => Some(name),
+            Err(e) => {
+                self.error = e.to_string();
+                None
+            }
+        }
+    }
+}
+
+pub fn render_certificate_request(ctx: &Context, breeze: &mut Breeze) {
+    let Some(request) = &mut breeze.certificate_request else {
+        return;
+    };
+    let mut close = false;
+    Modal::new("certificate_request".into()).show(ctx, |ui| {
+        ui.set_max_width(420.0);
+        ui.label(request.message());
+        ui.add_space(8.0);
+        let busy = request.job.is_some();
+        ui.add_enabled_ui(!busy, |ui| {
+            for profile in &breeze.profiles {
+                if request.current.as_ref() == Some(&profile.name)
+                    && request.problem != CertificateProblem::Required
+                {
+                    continue;
+                }
+                let choice = Choice::Existing(profile.name.clone());
+                let label = format!("Use {}", profile.name);
+                ui.radio_value(&mut request.choice, choice, label);
+            }
+            if let Some(current) = &request.current {
+                if request.problem == CertificateProblem::NotValid {
+                    let choice = Choice::Regenerate(current.clone());
+                    let label = format!("Regenerate {}", current);
+                    ui.radio_value(&mut request.choice, choice, label);
+                }
+            }
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut request.choice, Choice::New, "Create a new certificate");
+                if request.choice == Choice::New {
+                    ui.text_edit_singleline(&mut request.new_name);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "Use it on {}",
+                    request.url.host_str().unwrap_or_default()
+                ));
+                ui.add(TextEdit::singleline(&mut request.path).desired_width(160.0));
+            });
+        });
+        if !request.error.is_empty() {
+            ui.label(RichText::new(&request.error).strong());
+        }
+        ui.add_space(8.0);
+        ui.horizontal(|ui| {
+            if busy {
+                ui.spinner();
+            } else if ui.button("Continue").clicked() {
+                request.error.clear();
+                request.start();
+            }
+            if ui.button("Cancel").clicked() {
+                close = true;
+            }
+        });
+    });
+
+    // Retry the page now that there is a certificate for it
+    if let Some(name) = request.poll() {
+        println!("Retrying {} with {}", request.url, name);
+        breeze.navigation_hint.set(Some(NavigationHint {
+            url: request.url.to_string(),
+            protocol: Protocol::from_url(&request.url),
+            add_to_history: false,
+        }));
+        breeze.should_update_profiles = true;
+        breeze.certificates.should_refresh = true;
+        close = true;
+    }
+    if close {
+        breeze.certificate_request = None;
+    }
+}
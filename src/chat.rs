@@ -0,0 +1,630 @@
+use eframe::egui::{
+    self, Align, Checkbox, Context, DragValue, Grid, Label, Layout, Modal, RichText, ScrollArea,
+    SidePanel, TextEdit, TopBottomPanel, Ui,
+};
+use time::OffsetDateTime;
+
+use crate::db::{
+    append_scrollback, delete_irc_server, get_irc_servers, get_scrollback, rename_irc_server,
+    save_irc_server,
+};
+use crate::handlers::{link_text, segment_urls, Protocol};
+use crate::irc::{parse_input, Connection, Event, Message, ServerConfig};
+use crate::{Breeze, NavigationHint};
+
+// Buffer holding server notices and numerics, rather than a channel or query
+const SERVER_BUFFER: &str = "*";
+const SCROLLBACK_LIMIT: u32 = 500;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineKind {
+    Message,
+    Action,
+    Notice,
+    Info,
+}
+
+impl From<u8> for LineKind {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => LineKind::Message,
+            1 => LineKind::Action,
+            2 => LineKind::Notice,
+            _ => LineKind::Info,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ChatLine {
+    pub timestamp: i64,
+    pub kind: LineKind,
+    pub nick: String,
+    pub text: String,
+}
+
+impl ChatLine {
+    fn new(kind: LineKind, nick: &str, text: &str) -> Self {
+        Self {
+            timestamp: OffsetDateTime::now_utc().unix_timestamp(),
+            kind,
+            nick: nick.to_string(),
+            text: text.to_string(),
+        }
+    }
+}
+
+struct Buffer {
+    name: String,
+    lines: Vec<ChatLine>,
+    unread: bool,
+    mentioned: bool,
+}
+
+// Whether `text` contains `nick` as a whole word
+fn mentions(text: &str, nick: &str) -> bool {
+    !nick.is_empty()
+        && text
+            .split(|c: char| !(c.is_alphanumeric() || "-_[]\\`^{}|".contains(c)))
+            .any(|word| word.eq_ignore_ascii_case(nick))
+}
+
+struct Network {
+    config: ServerConfig,
+    connection: Option<Connection>,
+    nick: String,
+    buffers: Vec<Buffer>,
+}
+
+impl Network {
+    fn new(config: ServerConfig) -> Self {
+        let mut network = Self {
+            nick: config.nick.clone(),
+            config,
+            connection: None,
+            buffers: vec![],
+        };
+        network.buffer(SERVER_BUFFER);
+        for (buffer, line) in
+            get_scrollback(&network.config.name, SCROLLBACK_LIMIT).unwrap_or_default()
+        {
+            network.buffer(&buffer).lines.push(line);
+        }
+        network
+    }
+
+    fn buffer(&mut self, name: &str) -> &mut Buffer {
+        let index = match self
+            .buffers
+            .iter()
+            .position(|b| b.name.eq_ignore_ascii_case(name))
+        {
+            Some(index) => index,
+            None => {
+                self.buffers.push(Buffer {
+                    name: name.to_string(),
+                    lines: Vec::new(),
+                    unread: false,
+                    mentioned: false,
+                });
+                self.buffers.len() - 1
+            }
+        };
+        &mut self.buffers[index]
+    }
+
+    fn push_line(&mut self, buffer: &str, line: ChatLine) {
+        let _ = append_scrollback(&self.config.name, buffer, &line);
+        let mentioned = line.kind != LineKind::Info
+            && line.nick != self.nick
+            && mentions(&line.text, &self.nick);
+        let buffer = self.buffer(buffer);
+        buffer.unread = true;
+        buffer.mentioned |= mentioned;
+        buffer.lines.push(line);
+    }
+
+    fn info(&mut self, buffer: &str, text: &str) {
+        self.push_line(buffer, ChatLine::new(LineKind::Info, "", text));
+    }
+
+    fn handle_event(&mut self, event: Event) {
+        match event {
+            Event::Message(message) => self.handle_message(message),
+            Event::Status(status) => self.info(SERVER_BUFFER, &status),
+            Event::Disconnected(reason) => {
+                self.connection = None;
+                self.info(SERVER_BUFFER, &format!("Disconnected: {}", reason));
+            }
+        }
+    }
+
+    fn handle_message(&mut self, message: Message) {
+        let nick = message.source_nick().unwrap_or_default().to_string();
+        let params = &message.params;
+        match (message.command.as_str(), params.as_slice()) {
+            ("PRIVMSG" | "NOTICE", [target, text, ..]) => {
+                let from_user = message.prefix.as_ref().is_some_and(|p| p.contains('!'));
+                let buffer = if !from_user {
+                    SERVER_BUFFER.to_string()
+                } else if target.eq_ignore_ascii_case(&self.nick) {
+                    nick.clone()
+                } else {
+                    target.clone()
+                };
+                let action = text
+                    .strip_prefix("\x01ACTION ")
+                    .map(|action| action.trim_end_matches('\x01'));
+                let (kind, text) = match action {
+                    Some(action) => (LineKind::Action, action),
+                    // Other CTCP requests are not supported
+                    None if text.starts_with('\x01') => return,
+                    None if message.command == "NOTICE" => (LineKind::Notice, text.as_str()),
+                    None => (LineKind::Message, text.as_str()),
+                };
+                self.push_line(&buffer, ChatLine::new(kind, &nick, text));
+            }
+            ("JOIN", [channel, ..]) => {
+                self.info(channel, &format!("{} joined {}", nick, channel));
+            }
+            ("PART", [channel, rest @ ..]) => {
+                let reason = rest
+                    .first()
+                    .map(|r| format!(" ({})", r))
+                    .unwrap_or_default();
+                self.info(channel, &format!("{} left {}{}", nick, channel, reason));
+            }
+            ("KICK", [channel, kicked, rest @ ..]) => {
+                let reason = rest
+                    .first()
+                    .map(|r| format!(" ({})", r))
+                    .unwrap_or_default();
+                self.info(
+                    channel,
+                    &format!("{} was kicked by {}{}", kicked, nick, reason),
+                );
+            }
+            ("NICK", [new_nick, ..]) => {
+                if nick.eq_ignore_ascii_case(&self.nick) {
+                    self.nick = new_nick.clone();
+                }
+                self.info(
+                    SERVER_BUFFER,
+                    &format!("{} is now known as {}", nick, new_nick),
+                );
+            }
+            ("QUIT", rest) => {
+                let reason = rest
+                    .first()
+                    .map(|r| format!(" ({})", r))
+                    .unwrap_or_default();
+                self.info(SERVER_BUFFER, &format!("{} quit{}", nick, reason));
+            }
+            ("TOPIC", [channel, topic, ..]) => {
+                self.info(channel, &format!("{} set the topic: {}", nick, topic));
+            }
+            ("332", [_, channel, topic, ..]) => {
+                self.info(channel, &format!("Topic: {}", topic));
+            }
+            // Our nickname as the server registered it
+            ("001", [registered_nick, rest @ ..]) => {
+                self.nick = registered_nick.clone();
+                self.info(SERVER_BUFFER, &rest.join(" "));
+            }
+            ("PING" | "PONG" | "CAP" | "AUTHENTICATE", _) => {}
+            // Numerics lead with our nickname, which isn't worth repeating
+            (command, [_, rest @ ..]) if command.chars().all(|c| c.is_ascii_digit()) => {
+                self.info(SERVER_BUFFER, &rest.join(" "));
+            }
+            (command, rest) => {
+                self.info(SERVER_BUFFER, &format!("{} {}", command, rest.join(" ")));
+            }
+        }
+    }
+
+    fn connect(&mut self) {
+        self.nick = self.config.nick.clone();
+        self.info(
+            SERVER_BUFFER,
+            &format!("Connecting to {}:{}", self.config.host, self.config.port),
+        );
+        self.connection = Some(Connection::connect(self.config.clone()));
+    }
+
+    // SASL passwords aren't saved, so one is asked for before the first connection
+    fn needs_password(&self) -> bool {
+        !self.config.sasl_username.is_empty() && self.config.sasl_password.is_empty()
+    }
+
+    fn disconnect(&self) {
+        if let Some(connection) = &self.connection {
+            connection.send(Message::new("QUIT", &["Leaving"]));
+        }
+    }
+
+    // Sends a line typed into a buffer, echoing our own messages since servers don't
+    fn submit(&mut self, buffer: &str, input: &str) {
+        let target = (buffer != SERVER_BUFFER).then_some(buffer);
+        let messages = match parse_input(input, target) {
+            Ok(messages) => messages,
+            Err(error) => {
+                self.info(buffer, &error);
+                return;
+            }
+        };
+        let Some(connection) = &self.connection else {
+            self.info(buffer, "Not connected");
+            return;
+        };
+        for message in &messages {
+            connection.send(message.clone());
+        }
+        let nick = self.nick.clone();
+        for message in messages {
+            if let ("PRIVMSG", [target, text]) = (message.command.as_str(), &message.params[..]) {
+                let line = match text
+                    .strip_prefix("\x01ACTION ")
+                    .map(|action| action.trim_end_matches('\x01'))
+                {
+                    Some(action) => ChatLine::new(LineKind::Action, &nick, action),
+                    None => ChatLine::new(LineKind::Message, &nick, text),
+                };
+                self.push_line(target, line);
+            }
+        }
+    }
+}
+
+pub struct Chat {
+    networks: Vec<Network>,
+    /// The network index and buffer name on display
+    selected: Option<(usize, String)>,
+    input: String,
+    /// The server being added or edited, along with its saved name when editing
+    server_form: Option<(Option<String>, ServerConfig)>,
+    /// The network being connected to and the SASL password typed in so far
+    password_prompt: Option<(usize, String)>,
+}
+
+impl Chat {
+    pub fn new() -> Self {
+        let networks = get_irc_servers()
+            .unwrap_or_default()
+            .into_iter()
+            .map(Network::new)
+            .collect::<Vec<_>>();
+        let selected = (!networks.is_empty()).then(|| (0, SERVER_BUFFER.to_string()));
+        Self {
+            networks,
+            selected,
+            input: String::new(),
+            server_form: None,
+            password_prompt: None,
+        }
+    }
+
+    /// Processes anything received from connected servers
+    pub fn poll(&mut self) {
+        for network in &mut self.networks {
+            let events = network
+                .connection
+                .as_ref()
+                .map(Connection::poll)
+                .unwrap_or_default();
+            for event in events {
+                network.handle_event(event);
+            }
+        }
+    }
+
+    fn save_server(&mut self, saved_name: Option<String>, config: ServerConfig) {
+        // A renamed network keeps its row and scrollback rather than gaining a duplicate
+        let name = saved_name.unwrap_or_else(|| config.name.clone());
+        if name != config.name {
+            let _ = rename_irc_server(&name, &config.name);
+        }
+        let _ = save_irc_server(&config);
+        match self.networks.iter_mut().find(|n| n.config.name == name) {
+            Some(network) => network.config = config,
+            None => {
+                self.networks.push(Network::new(config));
+                self.selected = Some((self.networks.len() - 1, SERVER_BUFFER.to_string()));
+            }
+        }
+    }
+
+    fn remove_server(&mut self, index: usize) {
+        let network = self.networks.remove(index);
+        network.disconnect();
+        let _ = delete_irc_server(&network.config.name);
+        self.selected = (!self.networks.is_empty()).then(|| (0, SERVER_BUFFER.to_string()));
+    }
+}
+
+pub fn render_line(ui: &mut Ui, line: &ChatLine, own_nick: &str, breeze: &Breeze) {
+    let time = OffsetDateTime::from_unix_timestamp(line.timestamp)
+        .map(|t| format!("{:02}:{:02}", t.hour(), t.minute()))
+        .unwrap_or_default();
+    let highlighted =
+        line.kind != LineKind::Info && line.nick != own_nick && mentions(&line.text, own_nick);
+    let text_color = if highlighted {
+        Some(ui.visuals().warn_fg_color)
+    } else if line.kind == LineKind::Info {
+        Some(ui.visuals().weak_text_color())
+    } else {
+        None
+    };
+
+    ui.horizontal_wrapped(|ui| {
+        ui.spacing_mut().item_spacing.x = 0.0;
+        ui.label(RichText::new(format!("{} ", time)).monospace().weak());
+        let prefix = match line.kind {
+            LineKind::Message => format!("<{}> ", line.nick),
+            LineKind::Action => format!("* {} ", line.nick),
+            LineKind::Notice => format!("-{}- ", line.nick),
+            LineKind::Info => "-- ".to_string(),
+        };
+        ui.label(RichText::new(prefix).strong());
+        for segment in segment_urls(&line.text) {
+            let mut text = RichText::new(&segment.text);
+            if let Some(color) = text_color {
+                text = text.color(color);
+            }
+            // Only smolnet links can be opened in the browser view
+            let Some(url) = segment
+                .url
+                .filter(|url| Protocol::from_url(url) != Protocol::Unknown)
+            else {
+                ui.label(text);
+                continue;
+            };
+            let link_text = link_text(breeze, text, url.as_str());
+            let link = ui.add(Label::new(link_text).sense(egui::Sense::click()));
+            if link.hovered() {
+                ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
+                *breeze.status_text.borrow_mut() = url.to_string();
+            }
+            if link.clicked() {
+                breeze.url.set(url.to_string());
+                breeze.navigation_hint.set(Some(NavigationHint {
+                    url: url.to_string(),
+                    protocol: Protocol::from_url(&url),
+                    add_to_history: true,
+                }));
+            }
+        }
+    });
+}
+
+pub fn render_chat(ui: &mut Ui, ctx: &Context, breeze: &mut Breeze) {
+    let chat = &mut breeze.chat;
+
+    let mut remove = None;
+    SidePanel::left("chat_networks")
+        .default_width(180.0)
+        .show_inside(ui, |ui| {
+            if ui.button("➕ Add server").clicked() {
+                chat.server_form = Some((
+                    None,
+                    ServerConfig {
+                        port: 6697,
+                        tls: true,
+                        ..Default::default()
+                    },
+                ));
+            }
+            ui.separator();
+            ScrollArea::vertical().show(ui, |ui| {
+                for (index, network) in chat.networks.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label(RichText::new(&network.config.name).strong());
+                        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                            if network.connection.is_some() {
+                                if ui.small_button("⏹").on_hover_text("Disconnect").clicked() {
+                                    network.disconnect();
+                                }
+                            } else if ui.small_button("▶").on_hover_text("Connect").clicked() {
+                                if network.needs_password() {
+                                    chat.password_prompt = Some((index, String::new()));
+                                } else {
+                                    network.connect();
+                                }
+                            }
+                            ui.menu_button("…", |ui| {
+                                if ui.button("Edit").clicked() {
+                                    chat.server_form = Some((
+                                        Some(network.config.name.clone()),
+                                        network.config.clone(),
+                                    ));
+                                    ui.close_menu();
+                                }
+                                if ui.button("Remove").clicked() {
+                                    remove = Some(index);
+                                    ui.close_menu();
+                                }
+                            });
+                        });
+                    });
+                    for buffer in &mut network.buffers {
+                        let selected = chat
+                            .selected
+                            .as_ref()
+                            .is_some_and(|(n, b)| *n == index && *b == buffer.name);
+                        let name = if buffer.name == SERVER_BUFFER {
+                            "server"
+                        } else {
+                            &buffer.name
+                        };
+                        let mut label = RichText::new(format!("  {}", name));
+                        if buffer.mentioned && !selected {
+                            label = label.color(ui.visuals().warn_fg_color).strong();
+                        } else if buffer.unread && !selected {
+                            label = label.strong();
+                        }
+                        if ui.selectable_label(selected, label).clicked() {
+                            chat.selected = Some((index, buffer.name.clone()));
+                        }
+                    }
+                }
+            });
+        });
+    if let Some(index) = remove {
+        chat.password_prompt = None;
+        chat.remove_server(index);
+    }
+
+    let Some((network_index, buffer_name)) = chat.selected.clone() else {
+        ui.label("Add a server to start chatting.");
+        render_server_form(ctx, chat);
+        render_password_prompt(ctx, chat);
+        return;
+    };
+
+    let mut submitted = None;
+    TopBottomPanel::bottom("chat_input").show_inside(ui, |ui| {
+        let input = ui.add_sized(
+            [ui.available_width(), 20.0],
+            TextEdit::singleline(&mut chat.input)
+                .hint_text("Message, or /join, /part, /msg, /me, /nick"),
+        );
+        if input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+            if !chat.input.trim().is_empty() {
+                submitted = Some(std::mem::take(&mut chat.input));
+            }
+            input.request_focus();
+        }
+    });
+    if let Some(input) = submitted {
+        chat.networks[network_index].submit(&buffer_name, &input);
+    }
+
+    let network = &mut breeze.chat.networks[network_index];
+    let own_nick = network.nick.clone();
+    let buffer = network.buffer(&buffer_name);
+    buffer.unread = false;
+    buffer.mentioned = false;
+
+    let network = &breeze.chat.networks[network_index];
+    let Some(buffer) = network.buffers.iter().find(|b| b.name == buffer_name) else {
+        return;
+    };
+    ScrollArea::vertical()
+        .auto_shrink(false)
+        .stick_to_bottom(true)
+        .show(ui, |ui| {
+            for line in &buffer.lines {
+                render_line(ui, line, &own_nick, breeze);
+            }
+        });
+
+    render_server_form(ctx, &mut breeze.chat);
+    render_password_prompt(ctx, &mut breeze.chat);
+}
+
+fn render_server_form(ctx: &Context, chat: &mut Chat) {
+    let Some((saved_name, form)) = &mut chat.server_form else {
+        return;
+    };
+    let mut close = false;
+    let mut save = None;
+    Modal::new("irc_server".into()).show(ctx, |ui| {
+        ui.heading("IRC server");
+        Grid::new("irc_server_form").num_columns(2).show(ui, |ui| {
+            ui.label("Name:");
+            ui.text_edit_singleline(&mut form.name);
+            ui.end_row();
+            ui.label("Host:");
+            ui.text_edit_singleline(&mut form.host);
+            ui.end_row();
+            ui.label("Port:");
+            ui.add(DragValue::new(&mut form.port));
+            ui.end_row();
+            ui.label("");
+            ui.add(Checkbox::new(&mut form.tls, "Use TLS"));
+            ui.end_row();
+            ui.label("Nickname:");
+            ui.text_edit_singleline(&mut form.nick);
+            ui.end_row();
+            ui.label("SASL username:");
+            ui.text_edit_singleline(&mut form.sasl_username);
+            ui.end_row();
+            ui.label("SASL password:");
+            ui.add(
+                TextEdit::singleline(&mut form.sasl_password)
+                    .password(true)
+                    .hint_text("Asked for when connecting"),
+            )
+            .on_hover_text("Not saved, only kept until Breeze is closed");
+            ui.end_row();
+            ui.label("Autojoin:");
+            ui.add(TextEdit::singleline(&mut form.autojoin).hint_text("#channel #another"));
+            ui.end_row();
+        });
+        ui.horizontal(|ui| {
+            let name_taken = chat
+                .networks
+                .iter()
+                .any(|n| n.config.name == form.name && Some(&n.config.name) != saved_name.as_ref());
+            let valid = !form.name.trim().is_empty()
+                && !name_taken
+                && !form.host.trim().is_empty()
+                && !form.nick.trim().is_empty()
+                && form.nick.chars().all(|c| !c.is_whitespace());
+            if ui.add_enabled(valid, egui::Button::new("Save")).clicked() {
+                save = Some((saved_name.clone(), form.clone()));
+                close = true;
+            }
+            if ui.button("Cancel").clicked() {
+                close = true;
+            }
+        });
+    });
+    if close {
+        chat.server_form = None;
+    }
+    if let Some((saved_name, config)) = save {
+        chat.save_server(saved_name, config);
+    }
+}
+
+fn render_password_prompt(ctx: &Context, chat: &mut Chat) {
+    let Some((index, password)) = &mut chat.password_prompt else {
+        return;
+    };
+    let Some(network) = chat.networks.get_mut(*index) else {
+        chat.password_prompt = None;
+        return;
+    };
+    let mut close = false;
+    let mut connect = false;
+    Modal::new("irc_password".into()).show(ctx, |ui| {
+        ui.heading(format!("Connect to {}", network.config.name));
+        ui.label(format!(
+            "SASL password for {}:",
+            network.config.sasl_username
+        ));
+        let input = ui.add(TextEdit::singleline(password).password(true));
+        input.request_focus();
+        let entered = input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(!password.is_empty(), egui::Button::new("Connect"))
+                .clicked()
+                || (entered && !password.is_empty())
+            {
+                connect = true;
+            }
+            if ui.button("Cancel").clicked() {
+                close = true;
+            }
+        });
+    });
+    if connect {
+        network.config.sasl_password = std::mem::take(password);
+        network.connect();
+        close = true;
+    }
+    if close {
+        chat.password_prompt = None;
+    }
+}
@@ -0,0 +1,204 @@
+use std::io::Write;
+use std::str::FromStr;
+
+use clap::Args;
+use url::Url;
+
+use crate::db::get_profile;
+use crate::handlers::{parse_content, parse_content_type, Protocol};
+use crate::networking::{
+    fetch, fetch_file, is_plaintext_url, request_for, set_client_profile, GeminiStatus,
+    GuppyStatus, ScorpionStatus, ServerStatus, SpartanStatus, TextProtocolStatus,
+};
+
+/// Fetch a URL and print it without opening a window.
+///
+/// Exits with 0 on success, 1 if the request could not be made, 3 when the server asks for
+/// input, 4 on a redirect, 5 on a temporary failure, 6 on a permanent failure and 7 when a
+/// client certificate is needed.
+#[derive(Args)]
+pub struct FetchArgs {
+    url: String,
+    /// Fetch using this protocol instead of the one named by the URL's scheme
+    #[arg(short, long)]
+    protocol: Option<String>,
+    /// Present this profile's client certificate instead of any bound to the URL
+    #[arg(long)]
+    profile: Option<String>,
+    /// Print the response body exactly as received
+    #[arg(long, conflicts_with = "status")]
+    raw: bool,
+    /// Only print the status and meta
+    #[arg(long)]
+    status: bool,
+}
+
+// A protocol-independent summary of a response status, for scripts
+fn summarize(status: &ServerStatus) -> (i32, &'static str, String) {
+    match status {
+        ServerStatus::Gemini(status) => match status {
+            GeminiStatus::Success(m) => (0, "success", m.clone()),
+            GeminiStatus::InputExpected(m, _) => (3, "input", m.clone()),
+            GeminiStatus::TemporaryRedirect(m) | GeminiStatus::PermanentRedirect(m) => {
+                (4, "redirect", m.clone())
+            }
+            GeminiStatus::TemporaryFailure(m)
+            | GeminiStatus::ServerUnavailable(m)
+            | GeminiStatus::CGIError(m)
+            | GeminiStatus::ProxyError(m)
+            | GeminiStatus::SlowDown(m) => (5, "temporary-failure", m.clone()),
+            GeminiStatus::PermanentFailure(m)
+            | GeminiStatus::NotFound(m)
+            | GeminiStatus::Gone(m)
+            | GeminiStatus::ProxyRequestRefused(m)
+            | GeminiStatus::BadRequest(m) => (6, "permanent-failure", m.clone()),
+            GeminiStatus::RequiresClientCertificate
+            | GeminiStatus::CertificateNotAuthorized
+            | GeminiStatus::CertificateNotValid => (7, "certificate-required", String::new()),
+        },
+        ServerStatus::Scorpion(status) => match status {
+            ScorpionStatus::OK
+            | ScorpionStatus::PartialOK
+            | ScorpionStatus::ReadyNewFile
+            | ScorpionStatus::ReadyModifyFile
+            | ScorpionStatus::ReadyOther
+            | ScorpionStatus::AcceptedNewFile
+            | ScorpionStatus::AcceptedFileModified
+            | ScorpionStatus::AcceptedOther => (0, "success", String::new()),
+            ScorpionStatus::Interactive | ScorpionStatus::InputRequired => {
+                (3, "input", String::new())
+            }
+            ScorpionStatus::TemporaryRedirect(m) | ScorpionStatus::PermanentRedirect(m) => {
+                (4, "redirect", m.clone())
+            }
+            ScorpionStatus::TemporaryError
+            | ScorpionStatus::DownForMaintenance
+            | ScorpionStatus::DynamicFileError
+            | ScorpionStatus::ProxyError
+            | ScorpionStatus::TemporarilyLockedFile => (5, "temporary-failure", String::new()),
+            ScorpionStatus::SlowDown(m) => (5, "temporary-failure", m.clone()),
+            ScorpionStatus::PermanentError(m)
+            | ScorpionStatus::FileNotFound(m)
+            | ScorpionStatus::FileRemoved(m) => (6, "permanent-failure", m.clone()),
+            ScorpionStatus::ProxyRequestRefused
+            | ScorpionStatus::Forbidden
+            | ScorpionStatus::EditConflict
+            | ScorpionStatus::BadRequest => (6, "permanent-failure", String::new()),
+            ScorpionStatus::CredentialsRequired
+            | ScorpionStatus::RequiresClientCertificate
+            | ScorpionStatus::CertificateNotAuthorized
+            | ScorpionStatus::CertificateNotValid => (7, "certificate-required", String::new()),
+        },
+        ServerStatus::Guppy(status) => match status {
+            GuppyStatus::Success(m) => (0, "success", m.clone()),
+            GuppyStatus::Input(m) => (3, "input", m.clone()),
+            GuppyStatus::Redirect(m) => (4, "redirect", m.clone()),
+            GuppyStatus::Error(m) => (6, "permanent-failure", m.clone()),
+        },
+        ServerStatus::Spartan(status) => match status {
+            SpartanStatus::Success(m) => (0, "success", m.clone()),
+            SpartanStatus::Redirect(m) => (4, "redirect", m.clone()),
+            SpartanStatus::ServerError(m) => (5, "temporary-failure", m.clone()),
+            SpartanStatus::ClientError(m) => (6, "permanent-failure", m.clone()),
+        },
+        ServerStatus::TextProtocol(status) => match status {
+            TextProtocolStatus::OK(m) => (0, "success", m.clone()),
+            TextProtocolStatus::Redirect(m) => (4, "redirect", m.clone()),
+            TextProtocolStatus::NOK(m) => (6, "permanent-failure", m.clone()),
+        },
+        ServerStatus::_Success(m) => (0, "success", m.clone()),
+    }
+}
+
+/// Runs `breeze fetch`, returning the process exit code
+pub fn run_fetch(args: FetchArgs) -> i32 {
+    let url = match Url::from_str(&args.url) {
+        Ok(url) => url,
+        Err(e) => {
+            eprintln!("Invalid URL {}: {}", args.url, e);
+            return 1;
+        }
+    };
+    let protocol = match &args.protocol {
+        Some(name) => Protocol::from_str(name),
+        None => Protocol::from_url(&url),
+    };
+    match protocol {
+        Protocol::Unknown => {
+            eprintln!("Unsupported protocol for {}", url);
+            return 1;
+        }
+        Protocol::Misfin | Protocol::Nps | Protocol::Titan | Protocol::Plaintext => {
+            eprintln!("{:?} URLs can't be fetched", protocol);
+            return 1;
+        }
+        _ => {}
+    }
+    if protocol != Protocol::File && url.host_str().is_none_or(str::is_empty) {
+        eprintln!("{} has no host to fetch from", url);
+        return 1;
+    }
+    // A profile that can't be presented would otherwise quietly fetch without a certificate
+    if let Some(name) = &args.profile {
+        match get_profile(name) {
+            Ok(_) => {}
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                eprintln!("No profile named {}", name);
+                return 1;
+            }
+            Err(e) => {
+                eprintln!("Can't use profile {}: {}", name, e);
+                return 1;
+            }
+        }
+    }
+    set_client_profile(args.profile);
+
+    let response = if protocol == Protocol::File {
+        fetch_file(&url)
+    } else {
+        let (request_body, ssl) = request_for(&url, protocol);
+        fetch(&url, &request_body, ssl, protocol)
+    };
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    let (code, kind, meta) = summarize(&response.status);
+    let status_line = format!("{} {}", kind, meta).trim_end().to_string();
+    if args.status {
+        println!("{}", status_line);
+        return code;
+    }
+    if code != 0 {
+        eprintln!("{}", status_line);
+        return code;
+    }
+
+    if args.raw {
+        let mut stdout = std::io::stdout();
+        if let Err(e) = stdout
+            .write_all(&response.content)
+            .and_then(|_| stdout.flush())
+        {
+            eprintln!("{}", e);
+            return 1;
+        }
+        return code;
+    }
+
+    let plaintext = is_plaintext_url(&url, protocol);
+    let document = match protocol {
+        // As in the browser, these are parsed according to their content type
+        Protocol::File | Protocol::TextProtocol => {
+            parse_content_type(&response.content, &meta, plaintext, protocol)
+        }
+        _ => parse_content(&response.content, plaintext, protocol),
+    };
+    print!("{}", document.to_text(&url));
+    code
+}
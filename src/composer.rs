@@ -0,0 +1,421 @@
+use std::fs;
+use std::path::Path;
+
+use eframe::egui::{
+    Button, ComboBox, Context, Frame, RichText, ScrollArea, TextEdit, TopBottomPanel, Ui, Vec2,
+};
+use poll_promise::Promise;
+use url::Url;
+
+use crate::document::Document;
+use crate::handlers::render::render_document;
+use crate::handlers::{gemtext, gopher, Protocol};
+use crate::networking::{upload, GeminiStatus, ServerResponse, ServerStatus, SpartanStatus};
+use crate::{Breeze, NavigationHint};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Gemtext,
+    Gophermap,
+}
+
+impl Format {
+    fn label(&self) -> &str {
+        match self {
+            Format::Gemtext => "Gemtext",
+            Format::Gophermap => "Gophermap",
+        }
+    }
+
+    fn mime(&self) -> &str {
+        match self {
+            Format::Gemtext => "text/gemini",
+            Format::Gophermap => "application/gopher-menu",
+        }
+    }
+
+    fn from_path(path: &Path) -> Option<Format> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".gmi") || name.ends_with(".gemini") {
+            Some(Format::Gemtext)
+        } else if name == "gophermap" || name.ends_with(".gophermap") || name.ends_with(".gph") {
+            Some(Format::Gophermap)
+        } else {
+            None
+        }
+    }
+}
+
+/// Checks each line of a Gophermap has the display string, selector, host and port columns
+pub fn validate_gophermap(text: &str) -> Vec<(usize, String)> {
+    let mut problems = Vec::new();
+    for (number, line) in text.lines().enumerate().map(|(i, l)| (i + 1, l)) {
+        if line == "." || line.is_empty() {
+            continue;
+        }
+        let item_type = line.chars().next().unwrap_or_default();
+        let columns = line[item_type.len_utf8()..].split('\t').collect::<Vec<_>>();
+        if columns.len() < 4 {
+            problems.push((
+                number,
+                format!("expected 4 tab separated columns, found {}", columns.len()),
+            ));
+            continue;
+        }
+        // Informational lines conventionally carry placeholder host and port values
+        if item_type == 'i' || item_type == '3' {
+            continue;
+        }
+        let host = columns[2];
+        if host.is_empty() || host.contains(char::is_whitespace) {
+            problems.push((number, format!("invalid host \"{}\"", host)));
+        }
+        if columns[3].trim().parse::<u16>().is_err() {
+            problems.push((number, format!("invalid port \"{}\"", columns[3])));
+        }
+    }
+    problems
+}
+
+struct PublishJob {
+    destination: Url,
+    promise: Promise<Result<ServerResponse, String>>,
+}
+
+pub struct Composer {
+    format: Format,
+    text: String,
+    /// Character index of the cursor, where helpers insert new lines
+    cursor: usize,
+    file_path: String,
+    publish_url: String,
+    titan_token: String,
+    preview: Document,
+    previewed_text: Option<String>,
+    problems: Vec<(usize, String)>,
+    publish_job: Option<PublishJob>,
+    /// Where the last publish ended up, so it can be opened in the browser
+    published_url: Option<Url>,
+    status: String,
+}
+
+impl Default for Composer {
+    fn default() -> Self {
+        Self {
+            format: Format::Gemtext,
+            text: String::new(),
+            cursor: 0,
+            file_path: String::new(),
+            publish_url: String::new(),
+            titan_token: String::new(),
+            preview: Document::default(),
+            previewed_text: None,
+            problems: Vec::new(),
+            publish_job: None,
+            published_url: None,
+            status: String::new(),
+        }
+    }
+}
+
+impl Composer {
+    /// Prepares to publish to a Titan URL, used when navigating to one
+    pub fn publish_to(&mut self, url: &Url) {
+        self.publish_url = url.to_string();
+    }
+
+    fn refresh_preview(&mut self) {
+        if self.previewed_text.as_ref() == Some(&self.text) {
+            return;
+        }
+        match self.format {
+            Format::Gemtext => {
+                self.preview = gemtext::parse(self.text.as_bytes(), false);
+                self.problems.clear();
+            }
+            Format::Gophermap => {
+                self.preview = gopher::parse(self.text.as_bytes(), false, false);
+                self.problems = validate_gophermap(&self.text);
+            }
+        }
+        self.previewed_text = Some(self.text.clone());
+    }
+
+    // Inserts a snippet on a new line after the one the cursor is on
+    fn insert_line(&mut self, snippet: &str) {
+        let cursor = self
+            .text
+            .char_indices()
+            .nth(self.cursor)
+            .map_or(self.text.len(), |(i, _)| i);
+        let line_end = self.text[cursor..]
+            .find('\n')
+            .map_or(self.text.len(), |i| cursor + i);
+        let insertion = if self.text.is_empty() {
+            snippet.to_string()
+        } else {
+            format!("\n{}", snippet)
+        };
+        self.text.insert_str(line_end, &insertion);
+        self.cursor = self.text[..line_end + insertion.len()].chars().count();
+    }
+
+    fn open(&mut self) {
+        let path = Path::new(self.file_path.trim());
+        match fs::read_to_string(path) {
+            Ok(text) => {
+                self.text = text;
+                self.cursor = 0;
+                if let Some(format) = Format::from_path(path) {
+                    self.format = format;
+                }
+                self.previewed_text = None;
+                self.status = format!("Opened {}", path.display());
+            }
+            Err(e) => self.status = format!("Failed to open {}: {}", path.display(), e),
+        }
+    }
+
+    fn save(&mut self) {
+        let path = Path::new(self.file_path.trim());
+        self.status = match fs::write(path, &self.text) {
+            Ok(()) => format!("Saved {}", path.display()),
+            Err(e) => format!("Failed to save {}: {}", path.display(), e),
+        };
+    }
+
+    fn publish(&mut self) {
+        let destination = match Url::parse(self.publish_url.trim()) {
+            Ok(url) if url.host_str().is_some() => url,
+            _ => {
+                self.status = "Enter a titan:// or spartan:// URL to publish to".to_string();
+                return;
+            }
+        };
+        let data = self.text.clone().into_bytes();
+        let protocol = Protocol::from_url(&destination);
+        let (request_line, ssl) = match protocol {
+            Protocol::Titan => {
+                let mut request = format!(
+                    "{};mime={};size={}",
+                    destination.as_str().trim_end_matches(';'),
+                    self.format.mime(),
+                    data.len()
+                );
+                if !self.titan_token.trim().is_empty() {
+                    request.push_str(&format!(";token={}", self.titan_token.trim()));
+                }
+                (request, true)
+            }
+            Protocol::Spartan => {
+                let mut path = destination.path().to_string();
+                if path.is_empty() {
+                    path = "/".to_string();
+                }
+                let host = destination.host_str().unwrap_or_default();
+                (format!("{} {} {}", host, path, data.len()), false)
+            }
+            _ => {
+                self.status = "Publishing is only supported over Titan or Spartan".to_string();
+                return;
+            }
+        };
+
+        self.status = format!("Publishing to {}...", destination);
+        self.published_url = None;
+        let url = destination.clone();
+        let promise = Promise::spawn_thread("publish", move || {
+            upload(&url, &request_line, &data, ssl, protocol)
+        });
+        self.publish_job = Some(PublishJob {
+            destination,
+            promise,
+        });
+    }
+
+    fn poll_publish_job(&mut self) {
+        let Some(job) = &self.publish_job else { return };
+        let Some(result) = job.promise.ready() else {
+            return;
+        };
+        let resolve = |target: &str| job.destination.join(target.trim()).ok();
+        self.status = match result {
+            Ok(response) => match &response.status {
+                ServerStatus::Gemini(GeminiStatus::Success(_))
+                | ServerStatus::Spartan(SpartanStatus::Success(_)) => {
+                    // A successful upload is usually viewable at the same path over Gemini
+                    let mut published = job.destination.clone();
+                    if published.scheme() == "titan" {
+                        let path = published.path().split(';').next().unwrap_or("/").to_string();
+                        published = Url::parse(&format!(
+                            "gemini://{}{}",
+                            published.host_str().unwrap_or_default(),
+                            path
+                        ))
+                        .unwrap_or(published);
+                    }
+                    self.published_url = Some(published);
+                    "Published successfully".to_string()
+                }
+                ServerStatus::Gemini(GeminiStatus::TemporaryRedirect(target))
+                | ServerStatus::Gemini(GeminiStatus::PermanentRedirect(target))
+                | ServerStatus::Spartan(SpartanStatus::Redirect(target)) => {
+                    self.published_url = resolve(target);
+                    format!("Published, the server redirected to {}", target.trim())
+                }
+                ServerStatus::Gemini(GeminiStatus::RequiresClientCertificate)
+                | ServerStatus::Gemini(GeminiStatus::CertificateNotAuthorized)
+                | ServerStatus::Gemini(GeminiStatus::CertificateNotValid) => {
+                    "The server requires an authorized client certificate, check your active profile"
+                        .to_string()
+                }
+                status => format!("Publishing failed: {:?}", status),
+            },
+            Err(error) => format!("Publishing failed: {}", error),
+        };
+        self.publish_job = None;
+    }
+}
+
+pub fn render_composer(ui: &mut Ui, _ctx: &Context, breeze: &mut Breeze) {
+    let composer = &mut breeze.composer;
+    composer.poll_publish_job();
+
+    ui.horizontal(|ui| {
+        ComboBox::from_id_salt("composer_format")
+            .selected_text(composer.format.label())
+            .show_ui(ui, |ui| {
+                for format in [Format::Gemtext, Format::Gophermap] {
+                    if ui
+                        .selectable_value(&mut composer.format, format, format.label())
+                        .clicked()
+                    {
+                        composer.previewed_text = None;
+                    }
+                }
+            });
+        ui.separator();
+        match composer.format {
+            Format::Gemtext => {
+                if ui.button("Link").clicked() {
+                    composer.insert_line("=> gemini://example.org/ Link text");
+                }
+                if ui.button("Heading").clicked() {
+                    composer.insert_line("# Heading");
+                }
+                if ui.button("Subheading").clicked() {
+                    composer.insert_line("## Subheading");
+                }
+                if ui.button("List item").clicked() {
+                    composer.insert_line("* List item");
+                }
+                if ui.button("Quote").clicked() {
+                    composer.insert_line("> Quote");
+                }
+                if ui.button("Preformatted").clicked() {
+                    composer.insert_line("```alt text\n\n```");
+                }
+            }
+            Format::Gophermap => {
+                if ui.button("Menu link").clicked() {
+                    composer.insert_line("1Menu\t/selector\texample.org\t70");
+                }
+                if ui.button("Text link").clicked() {
+                    composer.insert_line("0Document\t/file.txt\texample.org\t70");
+                }
+                if ui.button("Search").clicked() {
+                    composer.insert_line("7Search\t/search\texample.org\t70");
+                }
+                if ui.button("Info").clicked() {
+                    composer.insert_line("iInformation\t\tnull.host\t1");
+                }
+            }
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.label("File:");
+        ui.add(TextEdit::singleline(&mut composer.file_path).desired_width(240.0));
+        let has_path = !composer.file_path.trim().is_empty();
+        if ui.add_enabled(has_path, Button::new("Open")).clicked() {
+            composer.open();
+        }
+        if ui.add_enabled(has_path, Button::new("Save")).clicked() {
+            composer.save();
+        }
+        ui.separator();
+        ui.label("Publish to:");
+        ui.add(
+            TextEdit::singleline(&mut composer.publish_url)
+                .hint_text("titan:// or spartan:// URL")
+                .desired_width(240.0),
+        );
+        if composer.publish_url.starts_with("titan://") {
+            ui.add(
+                TextEdit::singleline(&mut composer.titan_token)
+                    .hint_text("Token")
+                    .desired_width(80.0),
+            );
+        }
+        let publishable = matches!(
+            Protocol::from_str(&composer.publish_url),
+            Protocol::Titan | Protocol::Spartan
+        ) && composer.publish_job.is_none();
+        if ui
+            .add_enabled(publishable, Button::new("Publish"))
+            .clicked()
+        {
+            composer.publish();
+        }
+    });
+    ui.separator();
+
+    TopBottomPanel::bottom("composer_status").show_inside(ui, |ui| {
+        ui.horizontal(|ui| {
+            ui.label(&composer.status);
+            if let Some(url) = &composer.published_url {
+                if ui.link(url.as_str()).clicked() {
+                    breeze.url.set(url.to_string());
+                    breeze.navigation_hint.set(Some(NavigationHint {
+                        url: url.to_string(),
+                        protocol: Protocol::from_url(url),
+                        add_to_history: true,
+                    }));
+                }
+            }
+        });
+        for (line, problem) in &composer.problems {
+            ui.label(
+                RichText::new(format!("Line {}: {}", line, problem))
+                    .color(ui.visuals().warn_fg_color),
+            );
+        }
+    });
+
+    composer.refresh_preview();
+    ui.columns(2, |columns| {
+        ScrollArea::vertical()
+            .id_salt("composer_editor")
+            .auto_shrink(false)
+            .show(&mut columns[0], |ui| {
+                let composer = &mut breeze.composer;
+                let output = TextEdit::multiline(&mut composer.text)
+                    .code_editor()
+                    .desired_width(f32::INFINITY)
+                    .desired_rows(30)
+                    .show(ui);
+                if let Some(range) = output.cursor_range {
+                    composer.cursor = range.primary.ccursor.index;
+                }
+            });
+        ScrollArea::both()
+            .id_salt("composer_preview")
+            .auto_shrink(false)
+            .show(&mut columns[1], |ui| {
+                Frame::new()
+                    .inner_margin(Vec2::new(8.0, 8.0))
+                    .show(ui, |ui| {
+                        render_document(ui, breeze, &breeze.composer.preview)
+                    });
+            });
+    });
+}
@@ -1,93 +1,442 @@
-use std::sync::LazyLock;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex, MutexGuard};
 
-use native_tls::Identity;
-use rusqlite::{params, Connection, Error, Result};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, Error, OptionalExtension, Result, Row};
 
-use crate::profile::Profile;
+use crate::chat::ChatLine;
+use crate::irc::ServerConfig;
+use crate::mail::MailMessage;
+use crate::profile::{Binding, Profile};
 
-const DB: LazyLock<Connection> = LazyLock::new(|| {
-    let db = Connection::open("breeze.db").unwrap();
-    match db.execute("CREATE TABLE IF NOT EXISTS profiles (name TEXT PRIMARY KEY, cert TEXT, key TEXT, active BOOLEAN)", ()) {
-    Ok(_) => (),
-    Err(e) => panic!("Failed to create table: {}", e),
-  }
-    db
+// Where the database lives, set from the command line before it is first opened
+static DATA_DIR: LazyLock<Mutex<Option<PathBuf>>> = LazyLock::new(|| Mutex::new(None));
+
+static DB: LazyLock<Mutex<Connection>> = LazyLock::new(|| {
+    let path = database_path();
+    let mut db = match Connection::open(&path) {
+        Ok(db) => db,
+        Err(e) => panic!("Failed to open database {}: {}", path.display(), e),
+    };
+    match migrate(&mut db) {
+        Ok(_) => (),
+        Err(e) => panic!("Failed to migrate database: {}", e),
+    }
+    Mutex::new(db)
 });
 
+// Each entry brings the schema up one version, existing databases only run the ones they lack
+const MIGRATIONS: &[&str] = &[
+    // Tables from before the schema was versioned, which older databases already have
+    "CREATE TABLE IF NOT EXISTS profiles (name TEXT PRIMARY KEY, cert TEXT, key TEXT, active BOOLEAN);
+     CREATE TABLE IF NOT EXISTS mail (id INTEGER PRIMARY KEY AUTOINCREMENT, folder TEXT, address TEXT, subject TEXT, body TEXT, timestamp INTEGER, read BOOLEAN);
+     CREATE TABLE IF NOT EXISTS irc_servers (name TEXT PRIMARY KEY, host TEXT, port INTEGER, tls BOOLEAN, nick TEXT, sasl_username TEXT, autojoin TEXT);
+     CREATE TABLE IF NOT EXISTS irc_scrollback (id INTEGER PRIMARY KEY AUTOINCREMENT, network TEXT, buffer TEXT, timestamp INTEGER, kind INTEGER, nick TEXT, text TEXT);
+     CREATE TABLE IF NOT EXISTS visited (url TEXT PRIMARY KEY, last_visited INTEGER, visits INTEGER);
+     CREATE TABLE IF NOT EXISTS redirects (url TEXT PRIMARY KEY, target TEXT);
+     CREATE TABLE IF NOT EXISTS bindings (host TEXT, path TEXT, profile TEXT, PRIMARY KEY (host, path));",
+    // Private keys encrypted with a passphrase
+    "ALTER TABLE profiles ADD COLUMN encrypted BOOLEAN NOT NULL DEFAULT 0;",
+];
+
+pub fn set_data_dir(dir: PathBuf) {
+    *DATA_DIR.lock().expect("Failed to lock data directory") = Some(dir);
+}
+
+fn database_path() -> PathBuf {
+    let dir = DATA_DIR.lock().unwrap().clone();
+    // Tests get a database of their own rather than the user's
+    let dir = if cfg!(test) {
+        std::env::temp_dir().join(format!("breeze-test-{}", std::process::id()))
+    } else {
+        dir.or_else(|| dirs::data_dir().map(|dir| dir.join("breeze")))
+            .unwrap_or_else(|| PathBuf::from("."))
+    };
+    if let Err(e) = fs::create_dir_all(&dir) {
+        eprintln!("Failed to create {}: {}", dir.display(), e);
+    }
+    let path = dir.join("breeze.db");
+
+    // Earlier versions kept the database in the working directory, move it over the first time.
+    // This goes to stderr as it can happen while fetching from the command line
+    let old_path = Path::new("breeze.db");
+    if !cfg!(test) && !path.exists() && old_path.exists() {
+        let moved = fs::rename(old_path, &path)
+            .or_else(|_| fs::copy(old_path, &path).and_then(|_| fs::remove_file(old_path)));
+        match moved {
+            Ok(()) => eprintln!("Moved {} to {}", old_path.display(), path.display()),
+            Err(e) => eprintln!("Failed to move {}: {}", old_path.display(), e),
+        }
+    }
+    path
+}
+
+/// Brings a database's schema up to date, recording the version reached
+pub fn migrate(db: &mut Connection) -> Result<usize, Error> {
+    db.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL);",
+        (),
+    )?;
+    let version = db
+        .query_row("SELECT MAX(version) FROM schema_version;", [], |row| {
+            row.get::<_, Option<usize>>(0)
+        })?
+        .unwrap_or(0);
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = db.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.execute("DELETE FROM schema_version;", ())?;
+        tx.execute(
+            "INSERT INTO schema_version (version) VALUES (?);",
+            [index + 1],
+        )?;
+        tx.commit()?;
+    }
+    Ok(MIGRATIONS.len())
+}
+
+fn db() -> MutexGuard<'static, Connection> {
+    DB.lock().expect("Failed to lock database mutex")
+}
+
 struct ProfileEntry {
     name: String,
     cert: String,
     key: String,
     active: bool,
+    encrypted: bool,
 }
 
-pub fn new_profile(name: String, cert: String, key: String) -> Result<(), Error> {
-    let count =
-        match DB.query_row::<u32, _, _>("SELECT COUNT(*) FROM profiles;", [], |row| row.get(0)) {
-            Ok(count) => count,
-            Err(e) => return Err(e),
-        };
-
-    match DB.execute(
-        "INSERT INTO profiles (name, cert, key, active) VALUES (?, ?, ?, ?);",
-        (&name, &cert, &key, count == 0),
-    ) {
-        Ok(_) => Ok(()),
-        Err(e) => Err(e),
+const PROFILE_COLUMNS: &str = "name, cert, key, active, encrypted";
+
+impl ProfileEntry {
+    fn from_row(row: &Row) -> Result<Self, Error> {
+        Ok(ProfileEntry {
+            name: row.get(0)?,
+            cert: row.get(1)?,
+            key: row.get(2)?,
+            active: row.get(3)?,
+            encrypted: row.get(4)?,
+        })
+    }
+
+    // Fails only if the key is encrypted and hasn't been unlocked
+    fn into_profile(self) -> Result<Profile, Error> {
+        Profile::from_stored(self.name, self.cert, self.key, self.encrypted, self.active)
+            .map_err(|e| Error::FromSqlConversionFailure(2, Type::Text, e.into()))
     }
 }
 
+pub fn new_profile(name: &str, cert: &str, key: &str, encrypted: bool) -> Result<(), Error> {
+    let db = db();
+    let count =
+        db.query_row::<u32, _, _>("SELECT COUNT(*) FROM profiles;", [], |row| row.get(0))?;
+
+    db.execute(
+        "INSERT INTO profiles (name, cert, key, active, encrypted) VALUES (?, ?, ?, ?, ?);",
+        (name, cert, key, count == 0, encrypted),
+    )?;
+    Ok(())
+}
+
 pub fn get_default_profile() -> Result<Profile, Error> {
-    let profile = DB.query_row::<ProfileEntry, _, _>(
-        "SELECT * FROM profiles WHERE active = 1;",
+    db().query_row(
+        &format!("SELECT {} FROM profiles WHERE active = 1;", PROFILE_COLUMNS),
         (),
-        |row| {
-            Ok(ProfileEntry {
-                name: row.get(0)?,
-                cert: row.get(1)?,
-                key: row.get(2)?,
-                active: row.get(3)?,
-            })
-        },
-    )?;
+        ProfileEntry::from_row,
+    )?
+    .into_profile()
+}
 
-    let identity = Identity::from_pkcs8(profile.cert.as_bytes(), profile.key.as_bytes()).unwrap();
-    Ok(Profile {
-        name: profile.name,
-        identity,
-        active: profile.active,
-    })
+pub fn get_profile(name: &str) -> Result<Profile, Error> {
+    db().query_row(
+        &format!("SELECT {} FROM profiles WHERE name = ?;", PROFILE_COLUMNS),
+        [name],
+        ProfileEntry::from_row,
+    )?
+    .into_profile()
 }
 
+/// Every profile that can be used, leaving out those with keys still locked by a passphrase
 pub fn get_all_profiles() -> Result<Vec<Profile>, Error> {
-    let db = DB;
+    let db = db();
+    let mut stmt = db.prepare(&format!("SELECT {} FROM profiles;", PROFILE_COLUMNS))?;
+    let profile_rows = stmt.query_map(params![], ProfileEntry::from_row)?;
     let mut profiles = Vec::new();
-    let mut stmt = db.prepare("SELECT * FROM profiles;")?;
-    let profile_rows = stmt.query_map(params![], |row| {
-        Ok(ProfileEntry {
-            name: row.get(0)?,
-            cert: row.get(1)?,
-            key: row.get(2)?,
-            active: row.get(3)?,
-        })
-    })?;
     for row in profile_rows {
-        let profile = row?;
-        let identity =
-            Identity::from_pkcs8(profile.cert.as_bytes(), profile.key.as_bytes()).unwrap();
-        profiles.push(Profile {
-            name: profile.name,
-            identity,
-            active: profile.active,
-        });
+        match row?.into_profile() {
+            Ok(profile) => profiles.push(profile),
+            Err(e) => println!("Skipping profile: {}", e),
+        }
     }
     Ok(profiles)
 }
 
 pub fn set_active_profile(name: String) -> Result<(), Error> {
-    DB.execute(
+    db().execute(
         "UPDATE profiles SET active = (CASE WHEN name = ? THEN true ELSE false END);",
         [name],
     )?;
     Ok(())
 }
+
+pub fn update_profile_certificate(
+    name: &str,
+    cert: &str,
+    key: &str,
+    encrypted: bool,
+) -> Result<(), Error> {
+    db().execute(
+        "UPDATE profiles SET cert = ?, key = ?, encrypted = ? WHERE name = ?;",
+        (cert, key, encrypted, name),
+    )?;
+    Ok(())
+}
+
+/// Each profile's name, private key as stored and whether it is encrypted
+pub fn get_stored_keys() -> Result<Vec<(String, String, bool)>, Error> {
+    let db = db();
+    let mut stmt = db.prepare("SELECT name, key, encrypted FROM profiles;")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+    rows.collect()
+}
+
+/// Replaces every profile's stored key at once, so a passphrase change can't half happen
+pub fn set_stored_keys(keys: &[(String, String, bool)]) -> Result<(), Error> {
+    let mut db = db();
+    let tx = db.transaction()?;
+    for (name, key, encrypted) in keys {
+        tx.execute(
+            "UPDATE profiles SET key = ?, encrypted = ? WHERE name = ?;",
+            (key, encrypted, name),
+        )?;
+    }
+    tx.commit()
+}
+
+pub fn rename_profile(name: &str, new_name: &str) -> Result<(), Error> {
+    let mut db = db();
+    let tx = db.transaction()?;
+    tx.execute(
+        "UPDATE profiles SET name = ? WHERE name = ?;",
+        [new_name, name],
+    )?;
+    tx.execute(
+        "UPDATE bindings SET profile = ? WHERE profile = ?;",
+        [new_name, name],
+    )?;
+    tx.commit()
+}
+
+pub fn delete_profile(name: &str) -> Result<(), Error> {
+    let mut db = db();
+    let tx = db.transaction()?;
+    tx.execute("DELETE FROM profiles WHERE name = ?;", [name])?;
+    tx.execute("DELETE FROM bindings WHERE profile = ?;", [name])?;
+    tx.commit()
+}
+
+/// Binds a profile to a host and path, replacing whichever was bound there before
+pub fn add_binding(binding: &Binding) -> Result<(), Error> {
+    db().execute(
+        "INSERT INTO bindings (host, path, profile) VALUES (?1, ?2, ?3) ON CONFLICT(host, path) DO UPDATE SET profile = ?3;",
+        (&binding.host, &binding.path, &binding.profile),
+    )?;
+    Ok(())
+}
+
+pub fn remove_binding(binding: &Binding) -> Result<(), Error> {
+    db().execute(
+        "DELETE FROM bindings WHERE host = ? AND path = ?;",
+        [&binding.host, &binding.path],
+    )?;
+    Ok(())
+}
+
+pub fn get_bindings() -> Result<Vec<Binding>, Error> {
+    let db = db();
+    let mut stmt = db.prepare("SELECT profile, host, path FROM bindings ORDER BY host, path;")?;
+    let rows = stmt.query_map([], |row| {
+        Ok(Binding {
+            profile: row.get(0)?,
+            host: row.get(1)?,
+            path: row.get(2)?,
+        })
+    })?;
+    rows.collect()
+}
+
+pub fn new_message(
+    folder: &str,
+    address: &str,
+    subject: &str,
+    body: &str,
+    timestamp: i64,
+) -> Result<(), Error> {
+    db().execute(
+        "INSERT INTO mail (folder, address, subject, body, timestamp, read) VALUES (?, ?, ?, ?, ?, ?);",
+        (folder, address, subject, body, timestamp, folder == "sent"),
+    )?;
+    Ok(())
+}
+
+pub fn get_messages(folder: &str) -> Result<Vec<MailMessage>, Error> {
+    let db = db();
+    let mut stmt = db.prepare(
+        "SELECT id, address, subject, body, timestamp, read FROM mail WHERE folder = ? ORDER BY timestamp DESC;",
+    )?;
+    let rows = stmt.query_map([folder], |row| {
+        Ok(MailMessage {
+            id: row.get(0)?,
+            address: row.get(1)?,
+            subject: row.get(2)?,
+            body: row.get(3)?,
+            timestamp: row.get(4)?,
+            read: row.get(5)?,
+        })
+    })?;
+    rows.collect()
+}
+
+pub fn mark_message_read(id: i64) -> Result<(), Error> {
+    db().execute("UPDATE mail SET read = true WHERE id = ?;", [id])?;
+    Ok(())
+}
+
+pub fn delete_message(id: i64) -> Result<(), Error> {
+    db().execute("DELETE FROM mail WHERE id = ?;", [id])?;
+    Ok(())
+}
+
+/// Saves everything but the SASL password, which is asked for when connecting
+pub fn save_irc_server(config: &ServerConfig) -> Result<(), Error> {
+    db().execute(
+        "INSERT OR REPLACE INTO irc_servers (name, host, port, tls, nick, sasl_username, autojoin) VALUES (?, ?, ?, ?, ?, ?, ?);",
+        (
+            &config.name,
+            &config.host,
+            config.port,
+            config.tls,
+            &config.nick,
+            &config.sasl_username,
+            &config.autojoin,
+        ),
+    )?;
+    Ok(())
+}
+
+pub fn get_irc_servers() -> Result<Vec<ServerConfig>, Error> {
+    let db = db();
+    let mut stmt = db
+        .prepare("SELECT name, host, port, tls, nick, sasl_username, autojoin FROM irc_servers;")?;
+    let rows = stmt.query_map([], |row| {
+        Ok(ServerConfig {
+            name: row.get(0)?,
+            host: row.get(1)?,
+            port: row.get(2)?,
+            tls: row.get(3)?,
+            nick: row.get(4)?,
+            sasl_username: row.get(5)?,
+            sasl_password: String::new(),
+            autojoin: row.get(6)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// Renames a network along with its scrollback
+pub fn rename_irc_server(name: &str, new_name: &str) -> Result<(), Error> {
+    let mut db = db();
+    let tx = db.transaction()?;
+    tx.execute(
+        "UPDATE irc_servers SET name = ? WHERE name = ?;",
+        [new_name, name],
+    )?;
+    tx.execute(
+        "UPDATE irc_scrollback SET network = ? WHERE network = ?;",
+        [new_name, name],
+    )?;
+    tx.commit()
+}
+
+pub fn delete_irc_server(name: &str) -> Result<(), Error> {
+    let db = db();
+    db.execute("DELETE FROM irc_servers WHERE name = ?;", [name])?;
+    db.execute("DELETE FROM irc_scrollback WHERE network = ?;", [name])?;
+    Ok(())
+}
+
+pub fn append_scrollback(network: &str, buffer: &str, line: &ChatLine) -> Result<(), Error> {
+    db().execute(
+        "INSERT INTO irc_scrollback (network, buffer, timestamp, kind, nick, text) VALUES (?, ?, ?, ?, ?, ?);",
+        (network, buffer, line.timestamp, line.kind as u8, &line.nick, &line.text),
+    )?;
+    Ok(())
+}
+
+/// Returns the most recent `limit` lines of each buffer on a network, oldest first
+pub fn get_scrollback(network: &str, limit: u32) -> Result<Vec<(String, ChatLine)>, Error> {
+    let db = db();
+    let mut stmt = db.prepare(
+        "SELECT buffer, timestamp, kind, nick, text FROM (SELECT *, ROW_NUMBER() OVER (PARTITION BY buffer ORDER BY id DESC) AS n FROM irc_scrollback WHERE network = ?) WHERE n <= ? ORDER BY id;",
+    )?;
+    let rows = stmt.query_map((network, limit), |row| {
+        Ok((
+            row.get(0)?,
+            ChatLine {
+                timestamp: row.get(1)?,
+                kind: row.get::<_, u8>(2)?.into(),
+                nick: row.get(3)?,
+                text: row.get(4)?,
+            },
+        ))
+    })?;
+    rows.collect()
+}
+
+pub fn add_visited(url: &str, timestamp: i64) -> Result<(), Error> {
+    db().execute(
+        "INSERT INTO visited (url, last_visited, visits) VALUES (?1, ?2, 1) ON CONFLICT(url) DO UPDATE SET last_visited = ?2, visits = visits + 1;",
+        (url, timestamp),
+    )?;
+    Ok(())
+}
+
+pub fn get_visited() -> Result<Vec<String>, Error> {
+    let db = db();
+    let mut stmt = db.prepare("SELECT url FROM visited;")?;
+    let rows = stmt.query_map([], |row| row.get(0))?;
+    rows.collect()
+}
+
+pub fn set_redirect(url: &str, target: &str) -> Result<(), Error> {
+    db().execute(
+        "INSERT INTO redirects (url, target) VALUES (?1, ?2) ON CONFLICT(url) DO UPDATE SET target = ?2;",
+        (url, target),
+    )?;
+    Ok(())
+}
+
+/// Drops remembered redirects from or to a page
+pub fn delete_redirects(url: &str) -> Result<(), Error> {
+    db().execute(
+        "DELETE FROM redirects WHERE url = ?1 OR target = ?1;",
+        [url],
+    )?;
+    Ok(())
+}
+
+pub fn clear_redirects() -> Result<(), Error> {
+    db().execute("DELETE FROM redirects;", ())?;
+    Ok(())
+}
+
+pub fn get_redirect(url: &str) -> Result<Option<String>, Error> {
+    db().query_row(
+        "SELECT target FROM redirects WHERE url = ?;",
+        [url],
+        |row| row.get(0),
+    )
+    .optional()
+}
@@ -0,0 +1,106 @@
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, ToSocketAddrs};
+use std::sync::{LazyLock, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::settings;
+
+/// How long a failed lookup is remembered, kept short so a flaky network recovers quickly
+const FAILED_LOOKUP_TTL: Duration = Duration::from_secs(30);
+
+struct Lookup {
+    result: Result<Vec<IpAddr>, String>,
+    expires: Instant,
+}
+
+static DNS_CACHE: LazyLock<Mutex<HashMap<String, Lookup>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Hosts being looked up ahead of time, so each is only looked up once
+static PREFETCHING: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+fn cached(host: &str) -> Option<Result<Vec<IpAddr>, String>> {
+    let cache = DNS_CACHE.lock().expect("Failed to lock DNS cache");
+    cache
+        .get(host)
+        .filter(|lookup| lookup.expires > Instant::now())
+        .map(|lookup| lookup.result.clone())
+}
+
+/// Looks up the addresses of a host, from the cache while the answer is still fresh
+pub fn resolve(host: &str) -> Result<Vec<IpAddr>, String> {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(vec![ip]);
+    }
+    if let Some(result) = cached(host) {
+        return result;
+    }
+
+    // The lock isn't held while looking up, other hosts shouldn't have to wait on this one
+    let result = (host, 0)
+        .to_socket_addrs()
+        .map_err(|e| format!("Could not resolve {}: {}", host, e))
+        .and_then(|addrs| {
+            let ips: Vec<IpAddr> = addrs.map(|addr| addr.ip()).collect();
+            if ips.is_empty() {
+                Err(format!("{} has no addresses", host))
+            } else {
+                Ok(ips)
+            }
+        });
+    let ttl = match result {
+        Ok(_) => Duration::from_secs(settings::settings().dns_cache_minutes as u64 * 60),
+        Err(_) => FAILED_LOOKUP_TTL,
+    };
+    if !ttl.is_zero() {
+        let lookup = Lookup {
+            result: result.clone(),
+            expires: Instant::now() + ttl,
+        };
+        let mut cache = DNS_CACHE.lock().expect("Failed to lock DNS cache");
+        cache.insert(host.to_string(), lookup);
+    }
+    result
+}
+
+/// Drops a host's cached addresses, e.g. after none of them could be reached
+pub fn forget(host: &str) {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let mut cache = DNS_CACHE.lock().expect("Failed to lock DNS cache");
+    cache.remove(host);
+}
+
+/// Looks up a host in the background so following a link to it doesn't have to wait
+pub fn prefetch(host: &str) {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() || host.parse::<IpAddr>().is_ok() || cached(host).is_some() {
+        return;
+    }
+    let mut prefetching = PREFETCHING.lock().expect("Failed to lock DNS prefetch set");
+    if !prefetching.insert(host.to_string()) {
+        return;
+    }
+    drop(prefetching);
+    let host = host.to_string();
+    thread::spawn(move || {
+        let _ = resolve(&host);
+        let mut prefetching = PREFETCHING.lock().expect("Failed to lock DNS prefetch set");
+        prefetching.remove(&host);
+    });
+}
+
+/// Orders addresses for connecting, alternating between IPv6 and IPv4 starting with IPv6 so
+/// a broken path for either family doesn't hold up the other
+pub fn connection_order(ips: &[IpAddr]) -> Vec<IpAddr> {
+    let (mut v6, mut v4): (Vec<IpAddr>, Vec<IpAddr>) = ips.iter().partition(|ip| ip.is_ipv6());
+    v6.reverse();
+    v4.reverse();
+    let mut ordered = Vec::with_capacity(ips.len());
+    while !v6.is_empty() || !v4.is_empty() {
+        ordered.extend(v6.pop());
+        ordered.extend(v4.pop());
+    }
+    ordered
+}
@@ -0,0 +1,121 @@
+use url::Url;
+
+/// A run of text that is either plain or a URL
+pub struct TextSegment {
+    pub text: String,
+    pub url: Option<Url>,
+}
+
+pub struct Link {
+    /// The target as written in the page, resolved against the page's URL when followed
+    pub url: String,
+    /// Text to show for the link, the URL itself is shown when this is empty
+    pub label: String,
+    /// Shown before the label, e.g. "=>" or a Gopher item type icon
+    pub marker: Option<String>,
+    /// The target should be displayed as plain text whatever its protocol
+    pub plaintext: bool,
+}
+
+impl Link {
+    pub fn new(url: &str, label: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            label: label.to_string(),
+            marker: None,
+            plaintext: false,
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        if self.label.is_empty() {
+            &self.url
+        } else {
+            &self.label
+        }
+    }
+
+    // Formats the link for plain-text output, resolving it against the page's URL where possible
+    fn to_text(&self, prefix: &str, base: &Url) -> String {
+        let target = base
+            .join(&self.url)
+            .map_or(self.url.clone(), |url| url.to_string());
+        let label = self.label();
+        if prefix.is_empty() && (label == self.url || label == target) {
+            format!("=> {}", target)
+        } else {
+            format!("=> {}{} <{}>", prefix, label, target)
+        }
+    }
+}
+
+pub enum Block {
+    /// A heading from level 1 to 6
+    Heading(usize, String),
+    Paragraph(Vec<TextSegment>),
+    Link(Link),
+    ListItem(String),
+    Quote(String),
+    Preformatted {
+        text: String,
+        alt_text: Option<String>,
+    },
+    /// A text field whose contents are sent as the query of the link's URL
+    Input(Link),
+    Image(Link),
+}
+
+impl Block {
+    /// A paragraph without any links
+    pub fn text(text: &str) -> Self {
+        Block::Paragraph(vec![TextSegment {
+            text: text.to_string(),
+            url: None,
+        }])
+    }
+}
+
+/// A page as produced by one of the protocol parsers, ready to be rendered or exported
+#[derive(Default)]
+pub struct Document {
+    pub blocks: Vec<Block>,
+    /// Body text is set in the monospace font
+    pub monospace: bool,
+    /// Every line gets a column for its link marker, as in Gopher menus
+    pub gutter: bool,
+}
+
+impl Document {
+    /// A document holding unformatted text, such as a .txt file or an error message
+    pub fn plain(text: &str) -> Self {
+        Self {
+            blocks: vec![Block::text(text)],
+            monospace: true,
+            gutter: false,
+        }
+    }
+
+    /// Renders the page as plain text, with links resolved against `base`
+    pub fn to_text(&self, base: &Url) -> String {
+        let mut text = String::new();
+        for block in &self.blocks {
+            let rendered = match block {
+                Block::Heading(level, heading) => match level {
+                    1 => format!("{}\n{}", heading, "=".repeat(heading.chars().count())),
+                    2 => format!("{}\n{}", heading, "-".repeat(heading.chars().count())),
+                    _ => heading.clone(),
+                },
+                Block::Paragraph(segments) => segments.iter().map(|s| s.text.as_str()).collect(),
+                Block::Link(link) => link.to_text("", base),
+                Block::ListItem(item) => format!("* {}", item),
+                Block::Quote(quote) => format!("> {}", quote),
+                Block::Preformatted { text, .. } => text.clone(),
+                Block::Input(link) => link.to_text("[input] ", base),
+                Block::Image(link) => link.to_text("[image] ", base),
+            };
+            text.push_str(&rendered);
+            text.push('\n');
+        }
+        text
+    }
+}
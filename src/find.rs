@@ -0,0 +1,246 @@
+use std::cell::Cell;
+use std::ops::Range;
+
+use eframe::egui::text::{CCursor, LayoutJob};
+use eframe::egui::{
+    Align, Checkbox, FontSelection, Key, Label, Layout, Response, RichText, TextEdit, Ui,
+    WidgetText,
+};
+use regex::{Regex, RegexBuilder};
+
+/// A search to bring back when returning to a page
+#[derive(Clone, Debug)]
+pub struct FindSelection {
+    query: String,
+    case_sensitive: bool,
+    regex: bool,
+    current: usize,
+}
+
+/// State of the find bar shown above page content
+#[derive(Default)]
+pub struct Find {
+    pub open: bool,
+    query: String,
+    case_sensitive: bool,
+    regex: bool,
+    matcher: Option<Regex>,
+    error: Option<String>,
+    request_focus: bool,
+    /// Index of the selected match in render order
+    current: Cell<usize>,
+    /// Matches counted so far while rendering the current frame
+    seen: Cell<usize>,
+    /// Matches counted over the whole of the last rendered frame
+    total: Cell<usize>,
+    /// Whether the page is being rendered, other documents such as the mail reader aren't searched
+    rendering_page: Cell<bool>,
+    scroll_to_current: Cell<bool>,
+}
+
+impl Find {
+    pub fn open(&mut self) {
+        self.open = true;
+        self.request_focus = true;
+        self.update_matcher();
+    }
+
+    pub fn close(&mut self) {
+        self.open = false;
+        self.matcher = None;
+        self.total.set(0);
+    }
+
+    /// The search showing on the current page, if any
+    pub fn selection(&self) -> Option<FindSelection> {
+        (self.open && !self.query.is_empty()).then(|| FindSelection {
+            query: self.query.clone(),
+            case_sensitive: self.case_sensitive,
+            regex: self.regex,
+            current: self.current.get(),
+        })
+    }
+
+    /// Puts the find bar back how it was left, the page's scroll offset is restored separately
+    pub fn restore(&mut self, selection: Option<FindSelection>) {
+        let Some(selection) = selection else {
+            self.close();
+            return;
+        };
+        self.open = true;
+        self.query = selection.query;
+        self.case_sensitive = selection.case_sensitive;
+        self.regex = selection.regex;
+        self.update_matcher();
+        self.current.set(selection.current);
+        self.scroll_to_current.set(false);
+    }
+
+    fn update_matcher(&mut self) {
+        self.error = None;
+        self.current.set(0);
+        self.scroll_to_current.set(true);
+        if self.query.is_empty() {
+            self.matcher = None;
+            return;
+        }
+        let pattern = if self.regex {
+            self.query.clone()
+        } else {
+            regex::escape(&self.query)
+        };
+        match RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .build()
+        {
+            Ok(matcher) => self.matcher = Some(matcher),
+            Err(e) => {
+                self.matcher = None;
+                self.error = Some(e.to_string());
+            }
+        }
+    }
+
+    fn step(&self, forward: bool) {
+        let total = self.total.get();
+        if total == 0 {
+            return;
+        }
+        let current = self.current.get();
+        let next = if forward {
+            (current + 1) % total
+        } else {
+            (current + total - 1) % total
+        };
+        self.current.set(next);
+        self.scroll_to_current.set(true);
+    }
+
+    /// Resets the match counter, call before rendering the page
+    pub fn begin(&self) {
+        self.seen.set(0);
+        self.rendering_page.set(true);
+    }
+
+    /// Records how many matches the page had, call after rendering the page
+    pub fn end(&self) {
+        self.rendering_page.set(false);
+        let total = self.seen.get();
+        self.total.set(total);
+        if self.current.get() >= total {
+            self.current.set(0);
+        }
+    }
+
+    fn matches(&self, text: &str) -> Vec<Range<usize>> {
+        match &self.matcher {
+            Some(matcher) if self.open && self.rendering_page.get() => matcher
+                .find_iter(text)
+                .filter(|m| !m.is_empty())
+                .map(|m| m.range())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Adds a label built by `label`, highlighting any matches in `text` and scrolling to the
+    /// selected one when it has just changed
+    pub fn add(
+        &self,
+        ui: &mut Ui,
+        text: RichText,
+        label: impl FnOnce(WidgetText) -> Label,
+    ) -> Response {
+        let matches = self.matches(text.text());
+        if matches.is_empty() {
+            return ui.add(label(text.into()));
+        }
+
+        let first = self.seen.get();
+        self.seen.set(first + matches.len());
+        let current = self.current.get();
+        let contains_current = (first..first + matches.len()).contains(&current);
+
+        let mut base = LayoutJob::default();
+        let string = text.text().to_string();
+        text.append_to(&mut base, ui.style(), FontSelection::Default, Align::Center);
+        let format = base.sections[0].format.clone();
+        let highlight = ui.visuals().selection.bg_fill;
+
+        let mut job = LayoutJob::default();
+        let mut position = 0;
+        let mut current_start = 0;
+        for (index, range) in matches.into_iter().enumerate() {
+            job.append(&string[position..range.start], 0.0, format.clone());
+            let mut matched = format.clone();
+            matched.background = if first + index == current {
+                current_start = string[..range.start].chars().count();
+                ui.visuals().warn_fg_color
+            } else {
+                highlight
+            };
+            job.append(&string[range.clone()], 0.0, matched);
+            position = range.end;
+        }
+        job.append(&string[position..], 0.0, format);
+
+        let response = ui.add(label(job.clone().into()));
+        if contains_current && self.scroll_to_current.take() {
+            // Lay the text out again to find where in a long label the match sits
+            job.wrap.max_width = response.rect.width() + 1.0;
+            let galley = ui.fonts(|fonts| fonts.layout_job(job));
+            let rect = galley
+                .pos_from_ccursor(CCursor::new(current_start))
+                .translate(response.rect.min.to_vec2());
+            ui.scroll_to_rect(rect, Some(Align::Center));
+        }
+        response
+    }
+
+    pub fn render_bar(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            let query = ui.add(TextEdit::singleline(&mut self.query).hint_text("Find in page"));
+            if self.request_focus {
+                query.request_focus();
+                self.request_focus = false;
+            }
+            let mut changed = query.changed();
+            if query.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter)) {
+                let backwards = ui.input(|i| i.modifiers.shift);
+                self.step(!backwards);
+                query.request_focus();
+            }
+            if ui.button("▲").on_hover_text("Previous match").clicked() {
+                self.step(false);
+            }
+            if ui.button("▼").on_hover_text("Next match").clicked() {
+                self.step(true);
+            }
+            changed |= ui
+                .add(Checkbox::new(&mut self.case_sensitive, "Match case"))
+                .changed();
+            changed |= ui.add(Checkbox::new(&mut self.regex, "Regex")).changed();
+            if changed {
+                self.update_matcher();
+            }
+
+            if let Some(error) = &self.error {
+                ui.colored_label(ui.visuals().error_fg_color, "Invalid regex")
+                    .on_hover_text(error);
+            } else if self.matcher.is_some() {
+                let total = self.total.get();
+                if total == 0 {
+                    ui.label("No matches");
+                } else {
+                    ui.label(format!("{} of {}", self.current.get() + 1, total));
+                }
+            }
+
+            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                if ui.button("✕").clicked() || ui.input(|i| i.key_pressed(Key::Escape)) {
+                    self.close();
+                }
+            });
+        });
+    }
+}
@@ -1,31 +1,53 @@
-use eframe::egui::{RichText, Ui};
+use percent_encoding::percent_decode_str;
+use url::Url;
 
-use crate::Breeze;
+use crate::document::{Block, Document};
 
-use super::ProtocolHandler;
+use super::segment_urls;
 
-pub struct Finger {
-    current_page_contents: String,
-}
+/// Builds the RFC 1288 query line for a finger URL.
+///
+/// Supports `finger://host/user`, `finger://user@host`, forwarded queries such as
+/// `finger://user@host1@host2`, and verbose queries either as `finger://host//W user`,
+/// `finger://host/w/user` or `finger://user@host/W`.
+pub fn finger_query(url: &Url) -> String {
+    let decode = |s: &str| percent_decode_str(s).decode_utf8_lossy().to_string();
+    let path = decode(url.path());
+    let path = path.strip_prefix('/').unwrap_or(&path);
 
-// Ignoring this clippy warning for now in case I decide to add link support
-#[allow(clippy::derivable_impls)]
-impl Default for Finger {
-    fn default() -> Self {
-        Self {
-            current_page_contents: String::new(),
-        }
-    }
-}
+    let (verbose, user) = if !url.username().is_empty() {
+        let verbose = path.eq_ignore_ascii_case("w") || path.eq_ignore_ascii_case("/w");
+        (verbose, decode(url.username()))
+    } else if let Some(user) = path.strip_prefix("/W").or(path.strip_prefix("/w")) {
+        (true, user.trim().to_string())
+    } else if let Some(user) = path.strip_prefix("W/").or(path.strip_prefix("w/")) {
+        (true, user.to_string())
+    } else {
+        (false, path.to_string())
+    };
 
-impl ProtocolHandler for Finger {
-    fn parse_content(&mut self, response: &[u8], _: bool) {
-        let response = String::from_utf8_lossy(response);
-        self.current_page_contents = response.to_string();
+    match (verbose, user.is_empty()) {
+        (true, true) => "/W".to_string(),
+        (true, false) => format!("/W {}", user),
+        (false, _) => user,
     }
+}
 
-    fn render_page(&self, ui: &mut Ui, _: &Breeze) {
-        let text = RichText::new(&self.current_page_contents).size(14.0);
-        ui.monospace(text);
+pub fn parse(response: &[u8], plaintext: bool) -> Document {
+    let response = String::from_utf8_lossy(response);
+    let blocks = response
+        .lines()
+        .map(|line| {
+            if plaintext {
+                Block::text(line)
+            } else {
+                Block::Paragraph(segment_urls(line))
+            }
+        })
+        .collect();
+    Document {
+        blocks,
+        monospace: true,
+        gutter: false,
     }
 }
@@ -1,236 +1,74 @@
-use std::cell::Cell;
+use crate::document::{Block, Document, Link};
 
-use eframe::egui::{self, Color32, Label, RichText, TextEdit, Ui, Vec2};
-
-use crate::{Breeze, NavigationHint};
-
-use super::{Protocol, ProtocolHandler};
-
-#[derive(Clone, Copy, Debug, PartialEq)]
-#[allow(clippy::upper_case_acronyms)]
-enum LineType {
-    Text,
-    Link,
-    Heading1,
-    Heading2,
-    Heading3,
-    List,
-    Quote,
-    PreformatToggle,
-    // Spartan Additions
-    Prompt,
+fn parse_link(content: &str) -> Link {
+    let content = content.trim();
+    let (path, label) = match content.split_once(char::is_whitespace) {
+        Some((path, label)) => (path, label.trim()),
+        None => (content, ""),
+    };
+    Link {
+        plaintext: path.ends_with(".txt"),
+        ..Link::new(path, label)
+    }
 }
 
-impl LineType {
-    fn from_str(s: &str) -> LineType {
-        if s.starts_with("=>") {
-            LineType::Link
-        } else if s.starts_with("###") {
-            LineType::Heading3
-        } else if s.starts_with("##") {
-            LineType::Heading2
-        } else if s.starts_with("#") {
-            LineType::Heading1
-        } else if s.starts_with(">") {
-            LineType::Quote
-        } else if s.starts_with("```") {
-            LineType::PreformatToggle
-        } else if s.starts_with("*") {
-            LineType::List
-        } else if s.starts_with("=:") {
-            LineType::Prompt
-        } else {
-            LineType::Text
-        }
+fn parse_line(line: &str) -> Block {
+    if let Some(link) = line.strip_prefix("=>") {
+        Block::Link(parse_link(link))
+    } else if let Some(prompt) = line.strip_prefix("=:") {
+        // Spartan prompt lines link to a resource that takes input
+        Block::Input(parse_link(prompt))
+    } else if let Some(heading) = line.strip_prefix("###") {
+        Block::Heading(3, heading.trim_start().to_string())
+    } else if let Some(heading) = line.strip_prefix("##") {
+        Block::Heading(2, heading.trim_start().to_string())
+    } else if let Some(heading) = line.strip_prefix("#") {
+        Block::Heading(1, heading.trim_start().to_string())
+    } else if let Some(quote) = line.strip_prefix(">") {
+        Block::Quote(quote.trim_start().to_string())
+    } else if let Some(item) = line.strip_prefix("* ") {
+        Block::ListItem(item.trim_start().to_string())
+    } else {
+        Block::text(line)
     }
 }
 
-struct GemtextLine {
-    line_type: LineType,
-    content: String,
-    path: Option<String>,
-    preformatted: bool,
-    prompt_string: Cell<String>,
+fn preformatted(alt_text: String, lines: Vec<&str>) -> Block {
+    Block::Preformatted {
+        text: lines.join("\n"),
+        alt_text: (!alt_text.is_empty()).then_some(alt_text),
+    }
 }
 
-impl GemtextLine {
-    fn from_str(s: &str, plaintext: bool, gemtext: &mut Gemtext) -> Self {
-        if plaintext || s.is_empty() {
-            // Treat every line as an informational one
-            return Self {
-                line_type: LineType::Text,
-                content: s.to_string(),
-                path: None,
-                preformatted: gemtext.preformat_line,
-                prompt_string: Cell::new("".to_string()),
-            };
-        }
+pub fn parse(response: &[u8], plaintext: bool) -> Document {
+    let response = String::from_utf8_lossy(response);
+    if plaintext {
+        let lines: Vec<&str> = response.lines().filter(|line| line != &".").collect();
+        return Document::plain(&lines.join("\n"));
+    }
 
-        let line_type = LineType::from_str(s);
-        if line_type == LineType::PreformatToggle {
-            gemtext.preformat_line = !gemtext.preformat_line;
-        }
-        let (content, path) = if gemtext.preformat_line && line_type != LineType::PreformatToggle {
-            (s.to_string(), None)
-        } else {
-            match line_type {
-                LineType::Link => {
-                    let content = s[2..].trim().to_string();
-                    match content.split_once(char::is_whitespace) {
-                        Some((path, display_string)) => {
-                            (display_string.trim().to_string(), Some(path.to_string()))
-                        }
-                        None => (content.clone(), Some(content)),
-                    }
-                }
-                LineType::Text => (s.to_string(), None),
-                LineType::PreformatToggle => ("".to_string(), None),
-                LineType::Prompt => {
-                    let content = s[2..].trim().to_string();
-                    match content.split_once(char::is_whitespace) {
-                        Some((path, display_string)) => {
-                            (display_string.trim().to_string(), Some(path.to_string()))
-                        }
-                        None => (content.clone(), Some(content)),
-                    }
-                }
-                _ => (s.to_string(), None),
+    let mut blocks = Vec::new();
+    // Alt text and lines of the preformatted block currently being read, if any
+    let mut block: Option<(String, Vec<&str>)> = None;
+    for line in response.lines() {
+        if let Some(toggle) = line.strip_prefix("```") {
+            match block.take() {
+                Some((alt_text, lines)) => blocks.push(preformatted(alt_text, lines)),
+                None => block = Some((toggle.trim().to_string(), Vec::new())),
             }
-        };
-
-        Self {
-            line_type,
-            content,
-            path,
-            preformatted: gemtext.preformat_line,
-            prompt_string: Cell::new("".to_string()),
+        } else if let Some((_, lines)) = &mut block {
+            lines.push(line);
+        } else if line != "." {
+            // A lone dot marks EOF for protocols that borrow Gemtext
+            blocks.push(parse_line(line));
         }
     }
-}
-
-#[derive(Default)]
-pub struct Gemtext {
-    current_page_contents: Vec<GemtextLine>,
-    preformat_line: bool,
-}
-
-impl ProtocolHandler for Gemtext {
-    fn parse_content(&mut self, response: &[u8], plaintext: bool) {
-        let response = String::from_utf8_lossy(response);
-        self.preformat_line = false; // Reset preformat flag on new page load
-        if plaintext {
-            let lines: Vec<&str> = response.lines().filter(|line| line != &".").collect();
-            let gemtext_line = GemtextLine::from_str(&lines.join("\n"), plaintext, self);
-            self.current_page_contents = vec![gemtext_line];
-            return;
-        }
-        self.current_page_contents = response
-            .lines()
-            .filter_map(|line| {
-                if line == "." {
-                    // EOF
-                    None
-                } else {
-                    Some(GemtextLine::from_str(line, plaintext, self))
-                }
-            })
-            .collect();
+    // An unterminated block runs to the end of the document
+    if let Some((alt_text, lines)) = block {
+        blocks.push(preformatted(alt_text, lines));
     }
-
-    fn render_page(&self, ui: &mut Ui, breeze: &Breeze) {
-        ui.style_mut().spacing.item_spacing = Vec2::new(0.0, -1.0);
-        for line in &self.current_page_contents {
-            ui.horizontal(|ui| {
-                if line.preformatted && line.line_type != LineType::PreformatToggle {
-                    let mut padded_text = line.content.clone();
-                    let padding_needed = 120_usize.saturating_sub(padded_text.len());
-                    padded_text.push_str(&" ".repeat(padding_needed));
-                    let text = RichText::new(&padded_text).code().size(14.0);
-                    ui.add_sized([120.0, 16.0], egui::Label::new(text).extend());
-                } else {
-                    match line.line_type {
-                        LineType::Text => {
-                            let text = RichText::new(&line.content).size(14.0);
-                            let label = egui::Label::new(text).wrap_mode(egui::TextWrapMode::Wrap);
-                            ui.add(label);
-                        }
-                        LineType::Heading1 => {
-                            let content = line.content.replace("# ", "");
-                            ui.label(RichText::new(&content).size(24.0));
-                        }
-                        LineType::Heading2 => {
-                            let content = line.content.replace("## ", "");
-                            ui.label(RichText::new(&content).size(22.0));
-                        }
-                        LineType::Heading3 => {
-                            let content = line.content.replace("### ", "");
-                            ui.label(RichText::new(&content).size(20.0));
-                        }
-                        LineType::Link => {
-                            let link_text = RichText::new(&line.content)
-                                .color(Color32::BLUE)
-                                .underline()
-                                .size(14.0);
-                            let path = line.path.clone().expect("Gemtext link line without path!");
-                            let current_url = breeze.current_url.clone();
-                            let current_url = current_url.join(&path).unwrap();
-
-                            let link = ui.add(Label::new(link_text).sense(egui::Sense::hover()));
-                            if link.hovered() {
-                                ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
-                                *breeze.status_text.borrow_mut() = current_url.to_string();
-                            }
-                            if link.clicked() {
-                                breeze.url.set(current_url.to_string());
-                                let hint = if path.ends_with(".txt") {
-                                    Protocol::Plaintext
-                                } else {
-                                    Protocol::from_url(&current_url)
-                                };
-                                breeze.navigation_hint.set(Some(NavigationHint {
-                                    url: current_url.to_string(),
-                                    protocol: hint,
-                                    add_to_history: true,
-                                }));
-                            }
-                        }
-                        LineType::Quote => {
-                            ui.horizontal(|ui| {
-                                ui.label(RichText::new("| ").size(14.0));
-                                ui.label(RichText::new(&line.content).italics().size(14.0))
-                            });
-                        }
-                        LineType::List => {
-                            let content = line.content.replace("*", "•");
-                            ui.label(RichText::new(content).size(14.0));
-                        }
-                        LineType::PreformatToggle => {}
-                        LineType::Prompt => {
-                            let mut current_prompt = line.prompt_string.take();
-                            ui.add(TextEdit::singleline(&mut current_prompt));
-                            line.prompt_string.replace(current_prompt.clone());
-                            if ui.button("Submit").clicked() {
-                                let path =
-                                    line.path.clone().expect("Gemtext link line without path!");
-                                let current_url = breeze.current_url.clone();
-                                let mut current_url = current_url.join(&path).unwrap();
-                                current_url.set_query(Some(&current_prompt));
-                                breeze.url.set(current_url.to_string());
-                                let hint = if path.ends_with(".txt") {
-                                    Protocol::Plaintext
-                                } else {
-                                    Protocol::from_url(&current_url)
-                                };
-                                breeze.navigation_hint.set(Some(NavigationHint {
-                                    url: current_url.to_string(),
-                                    protocol: hint,
-                                    add_to_history: true,
-                                }));
-                            }
-                        }
-                    }
-                }
-            });
-        }
+    Document {
+        blocks,
+        ..Default::default()
     }
 }
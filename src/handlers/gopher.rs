@@ -1,11 +1,4 @@
-use std::cell::Cell;
-
-use eframe::egui::{self, Color32, Label, RichText, TextEdit, Ui};
-use url::Url;
-
-use crate::{Breeze, NavigationHint};
-
-use super::{Protocol, ProtocolHandler};
+use crate::document::{Block, Document, Link};
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[allow(clippy::upper_case_acronyms)]
@@ -79,168 +72,63 @@ impl LineType {
             LineType::Error => "⚠",
             LineType::Search => "🔍",
             LineType::HTML => "🌐",
+            LineType::GIFFile | LineType::ImageFile | LineType::BitmapImage | LineType::PNGFile => {
+                "🖼"
+            }
             _ => " ",
         }
     }
 }
 
-struct GopherLine {
-    line_type: LineType,
-    user_display_string: String,
-    selector: String,
-    hostname: String,
-    port: u16,
-    is_link: bool,
-    search_string: Cell<String>,
-}
+// A menu line is the item type character followed by tab separated display string, selector,
+// hostname and port
+fn parse_line(line: &str, secure: bool) -> Block {
+    let (line_type, content) = line.split_at_checked(1).unwrap_or(("i", line));
+    let components: Vec<&str> = content.split('\t').collect();
+    if components.len() < 4 {
+        // Malformed or EOF, just insert a blank line
+        return Block::text("");
+    }
+    let (display_string, selector, hostname) = (components[0], components[1], components[2]);
+    if line_type == "i" {
+        return Block::text(display_string);
+    }
 
-impl GopherLine {
-    fn from_str(s: &str, plaintext: bool) -> Self {
-        if plaintext {
-            // Treat every line as an informational one
-            return Self {
-                line_type: LineType::Informational,
-                user_display_string: s.to_string(),
-                selector: "".to_string(),
-                hostname: "".to_string(),
-                port: 0,
-                is_link: false,
-                search_string: Cell::new("".to_string()),
-            };
-        }
-        let (line_type, content) = match s.split_at_checked(1) {
-            Some((line_type, content)) => (line_type, content),
-            None => ("i", s),
+    let url = if display_string.contains("://") {
+        display_string.to_string()
+    } else {
+        let port = match components[3].parse().unwrap_or(0) {
+            70 => "".to_string(),
+            port => format!(":{}", port),
         };
-        let components = content.split("\t").collect::<Vec<&str>>();
-        if components.len() == 1 {
-            // EOF, just insert a blank line
-            return Self {
-                line_type: LineType::Informational,
-                user_display_string: "".to_string(),
-                selector: "".to_string(),
-                hostname: "".to_string(),
-                port: 0,
-                is_link: false,
-                search_string: Cell::new("".to_string()),
-            };
-        }
-
-        let user_display_string = components[0].to_string();
-        let selector = components[1].to_string();
-        let hostname = components[2].to_string();
-        let port = components[3].parse().unwrap_or(0);
-        let is_link = !matches!(line_type, "i" | "7");
-
-        Self {
-            line_type: LineType::from_str(line_type),
-            user_display_string,
-            selector,
-            hostname,
-            port,
-            is_link,
-            search_string: Cell::new("".to_string()),
+        let scheme = if secure { "gophers" } else { "gopher" };
+        format!("{}://{}{}{}", scheme, hostname, port, selector)
+    };
+    let line_type = LineType::from_str(line_type);
+    let link = Link {
+        marker: Some(line_type.icon().to_string()),
+        plaintext: line_type == LineType::Text,
+        ..Link::new(&url, display_string)
+    };
+    match line_type {
+        LineType::Search => Block::Input(link),
+        LineType::GIFFile | LineType::ImageFile | LineType::BitmapImage | LineType::PNGFile => {
+            Block::Image(link)
         }
+        _ => Block::Link(link),
     }
 }
 
-#[derive(Default)]
-pub struct Gopher {
-    current_page_contents: Vec<GopherLine>,
-}
-
-impl ProtocolHandler for Gopher {
-    fn parse_content(&mut self, response: &[u8], plaintext: bool) {
-        let response = String::from_utf8_lossy(response);
-        if plaintext {
-            let lines: Vec<&str> = response.lines().filter(|line| line != &".").collect();
-            let gopher_line = GopherLine::from_str(&lines.join("\n"), plaintext);
-            self.current_page_contents = vec![gopher_line];
-            return;
-        }
-        self.current_page_contents = response
-            .lines()
-            .filter_map(|line| {
-                if line == "." {
-                    // EOF
-                    None
-                } else {
-                    Some(GopherLine::from_str(line, plaintext))
-                }
-            })
-            .collect();
+pub fn parse(response: &[u8], plaintext: bool, secure: bool) -> Document {
+    let response = String::from_utf8_lossy(response);
+    // A lone dot marks EOF
+    let lines = response.lines().filter(|line| line != &".");
+    if plaintext {
+        return Document::plain(&lines.collect::<Vec<&str>>().join("\n"));
     }
-
-    fn render_page(&self, ui: &mut Ui, breeze: &Breeze) {
-        for line in &self.current_page_contents {
-            ui.horizontal(|ui| {
-                ui.add_sized(
-                    [16.0, 16.0],
-                    Label::new(RichText::new(line.line_type.icon()).monospace()),
-                );
-                if line.line_type == LineType::Search {
-                    let mut current_search = line.search_string.take();
-                    ui.add(TextEdit::singleline(&mut current_search).hint_text("Search"));
-                    line.search_string.replace(current_search.clone());
-                    if ui.button("Search").clicked() {
-                        let port = if line.port != 70 {
-                            format!(":{}", line.port)
-                        } else {
-                            "".to_string()
-                        };
-                        let scheme = breeze.current_url.scheme();
-                        let url = format!(
-                            "{}://{}{}{}?{}",
-                            scheme, line.hostname, port, line.selector, &current_search
-                        );
-                        breeze.url.set(url.clone());
-                        breeze.navigation_hint.set(Some(NavigationHint {
-                            url,
-                            protocol: Protocol::Gopher(false),
-                            add_to_history: true,
-                        }));
-                    }
-                } else if line.is_link {
-                    let link_text = RichText::new(&line.user_display_string)
-                        .color(Color32::BLUE)
-                        .underline()
-                        .monospace()
-                        .size(14.0);
-                    let port = if line.port != 70 {
-                        format!(":{}", line.port)
-                    } else {
-                        "".to_string()
-                    };
-                    let scheme = breeze.current_url.scheme();
-                    let mut url =
-                        format!("{}://{}{}{}", scheme, line.hostname, port, line.selector);
-
-                    let link = ui.add(Label::new(link_text).sense(egui::Sense::hover()));
-                    if link.hovered() {
-                        ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
-                        *breeze.status_text.borrow_mut() = url.clone();
-                    }
-                    if link.clicked() {
-                        if line.user_display_string.contains("://") {
-                            url = line.user_display_string.clone();
-                        }
-                        breeze.url.set(url.clone());
-                        let hint = if line.line_type == LineType::Text {
-                            Protocol::Plaintext
-                        } else {
-                            Protocol::from_url(&Url::parse(&url).unwrap())
-                        };
-                        breeze.navigation_hint.set(Some(NavigationHint {
-                            url,
-                            protocol: hint,
-                            add_to_history: true,
-                        }));
-                    }
-                } else {
-                    let text = RichText::new(&line.user_display_string).size(14.0);
-                    ui.monospace(text);
-                }
-            });
-        }
+    Document {
+        blocks: lines.map(|line| parse_line(line, secure)).collect(),
+        monospace: true,
+        gutter: true,
     }
 }
@@ -3,24 +3,34 @@ pub mod gemtext;
 pub mod gopher;
 pub mod nex;
 pub mod plaintext;
+pub mod render;
 pub mod scorpion;
+pub mod text;
 
-use eframe::egui;
+use eframe::egui::{self, RichText};
 use url::Url;
 
+use crate::document::{Document, TextSegment};
+use crate::history::is_visited;
+use crate::Breeze;
+
 #[allow(clippy::enum_variant_names)]
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Protocol {
+    File,
     Finger,
     Gopher(bool),
     Gemini,
     Guppy,
+    Misfin,
     Nex,
+    Nps,
     Plaintext,
     Scorpion,
     Scroll,
     Spartan,
     TextProtocol,
+    Titan,
     Unknown,
 }
 
@@ -31,23 +41,126 @@ impl Protocol {
 
     pub fn from_str(s: &str) -> Protocol {
         match s.split(':').next().unwrap() {
+            "file" => Protocol::File,
             "finger" => Protocol::Finger,
             "gemini" => Protocol::Gemini,
             "gopher" => Protocol::Gopher(false),
             "gophers" => Protocol::Gopher(true),
             "guppy" => Protocol::Guppy,
+            "misfin" => Protocol::Misfin,
             "nex" => Protocol::Nex,
+            "nps" => Protocol::Nps,
             "scorpion" => Protocol::Scorpion,
             "scroll" => Protocol::Scroll,
             "spartan" => Protocol::Spartan,
             "text" => Protocol::TextProtocol,
+            "titan" => Protocol::Titan,
             _ => Protocol::Unknown,
         }
     }
 }
 
-pub trait ProtocolHandler {
-    // Parses server text response updates internal page representation
-    fn parse_content(&mut self, response: &[u8], plaintext: bool);
-    fn render_page(&self, ui: &mut egui::Ui, breeze: &super::Breeze);
+/// Splits a line of unstructured text into plain text and any URLs found within it
+pub fn segment_urls(line: &str) -> Vec<TextSegment> {
+    let mut segments = Vec::new();
+    let mut rest = line;
+    while let Some(separator) = rest.find("://") {
+        // The scheme starts after the last character that can't be part of one, however wide
+        let start = rest[..separator]
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !(c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.')))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let end = rest[separator..]
+            .find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '"' | '\''))
+            .map_or(rest.len(), |i| separator + i);
+        let candidate = rest[start..end].trim_end_matches(['.', ',', ';', ':', ')', '!', '?']);
+        match Url::parse(candidate) {
+            Ok(url) if start < separator && url.has_host() => {
+                if start > 0 {
+                    segments.push(TextSegment {
+                        text: rest[..start].to_string(),
+                        url: None,
+                    });
+                }
+                segments.push(TextSegment {
+                    text: candidate.to_string(),
+                    url: Some(url),
+                });
+                rest = &rest[start + candidate.len()..];
+            }
+            _ => {
+                segments.push(TextSegment {
+                    text: rest[..separator + 3].to_string(),
+                    url: None,
+                });
+                rest = &rest[separator + 3..];
+            }
+        }
+    }
+    if !rest.is_empty() || segments.is_empty() {
+        segments.push(TextSegment {
+            text: rest.to_string(),
+            url: None,
+        });
+    }
+    segments
+}
+
+/// Styles link text in the theme's link or visited colour depending on its destination
+pub fn link_text(breeze: &Breeze, text: RichText, url: &str) -> RichText {
+    breeze.theme.link(text, is_visited(url))
+}
+
+/// Labels links that lead away from the current page's protocol with their scheme
+pub fn protocol_badge(ui: &mut egui::Ui, breeze: &Breeze, url: &str) {
+    if !breeze.show_protocol_badges {
+        return;
+    }
+    let Some((scheme, _)) = url.split_once("://") else {
+        return;
+    };
+    if !scheme.eq_ignore_ascii_case(breeze.current_url.scheme()) {
+        let badge = RichText::new(format!(" {} ", scheme.to_lowercase()))
+            .small()
+            .monospace()
+            .weak();
+        ui.label(badge);
+    }
+}
+
+/// Parses a response with the parser for the protocol it was fetched over
+pub fn parse_content(response: &[u8], plaintext: bool, protocol: Protocol) -> Document {
+    match protocol {
+        Protocol::Finger => finger::parse(response, plaintext),
+        Protocol::Gemini | Protocol::Spartan | Protocol::Guppy | Protocol::Scroll => {
+            gemtext::parse(response, plaintext)
+        }
+        Protocol::Gopher(secure) => gopher::parse(response, plaintext, secure),
+        Protocol::Nex | Protocol::Nps => nex::parse(response, plaintext),
+        Protocol::Scorpion => scorpion::parse(response, plaintext),
+        Protocol::TextProtocol => text::parse(response, plaintext),
+        _ => plaintext::parse(response),
+    }
+}
+
+/// Parses a response with the parser for the content type the server reported, rather than
+/// the protocol's
+pub fn parse_content_type(
+    response: &[u8],
+    content_type: &str,
+    plaintext: bool,
+    protocol: Protocol,
+) -> Document {
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    match mime {
+        "text/gemini" => gemtext::parse(response, plaintext),
+        "application/gopher-menu" => gopher::parse(response, plaintext, false),
+        "text/x-nex" => nex::parse(response, plaintext),
+        // Text protocol documents may contain link lines
+        _ if protocol == Protocol::TextProtocol && mime.starts_with("text/") => {
+            text::parse(response, plaintext)
+        }
+        _ => plaintext::parse(response),
+    }
 }
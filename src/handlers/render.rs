@@ -0,0 +1,200 @@
+use eframe::egui::{
+    CursorIcon, Id, Label, RichText, ScrollArea, Sense, TextEdit, Ui, Vec2, WidgetInfo, WidgetType,
+};
+use url::Url;
+
+use crate::dns;
+use crate::document::{Block, Document, Link, TextSegment};
+use crate::{Breeze, NavigationHint};
+
+use super::{link_text, protocol_badge, Protocol};
+
+const GUTTER_WIDTH: f32 = 16.0;
+
+fn body(breeze: &Breeze, document: &Document, text: &str) -> RichText {
+    if document.monospace {
+        breeze.theme.monospace(text)
+    } else {
+        breeze.theme.text(text)
+    }
+}
+
+fn navigate_to(breeze: &Breeze, url: &Url, plaintext: bool) {
+    breeze.url.set(url.to_string());
+    let protocol = if plaintext {
+        Protocol::Plaintext
+    } else {
+        Protocol::from_url(url)
+    };
+    breeze.navigation_hint.set(Some(NavigationHint {
+        url: url.to_string(),
+        protocol,
+        add_to_history: true,
+    }));
+}
+
+// Gopher menus put link markers in a column of their own, elsewhere they lead the line
+fn render_marker(ui: &mut Ui, breeze: &Breeze, document: &Document, marker: Option<&str>) {
+    if document.gutter {
+        let marker = RichText::new(marker.unwrap_or(" ")).monospace();
+        ui.add_sized([GUTTER_WIDTH, GUTTER_WIDTH], Label::new(marker));
+        ui.add_space(4.0);
+    } else if let Some(marker) = marker {
+        ui.label(body(breeze, document, &format!("{} ", marker)));
+    }
+}
+
+fn render_link(
+    ui: &mut Ui,
+    breeze: &Breeze,
+    document: &Document,
+    text: &str,
+    target: &str,
+    plaintext: bool,
+) {
+    let Ok(url) = breeze.current_url.join(target) else {
+        breeze
+            .find
+            .add(ui, body(breeze, document, text), Label::new);
+        return;
+    };
+    let text = link_text(breeze, body(breeze, document, text), url.as_str());
+    let link = breeze
+        .find
+        .add(ui, text, |text| Label::new(text).sense(Sense::click()));
+    protocol_badge(ui, breeze, url.as_str());
+    // Proxied schemes are looked up by the proxy, which is the point of a proxy like Tor
+    let settings = &breeze.settings;
+    if settings.prefetch_dns
+        && settings.dns_cache_minutes > 0
+        && !settings.proxies.contains_key(url.scheme())
+        && ui.is_rect_visible(link.rect)
+    {
+        if let Some(host) = url.host_str() {
+            dns::prefetch(host);
+        }
+    }
+    if link.hovered() {
+        ui.ctx().set_cursor_icon(CursorIcon::PointingHand);
+        *breeze.status_text.borrow_mut() = url.to_string();
+    }
+    if breeze.link_hints.hinted(ui, &link) || link.clicked() {
+        navigate_to(breeze, &url, plaintext);
+    }
+}
+
+fn render_segments(ui: &mut Ui, breeze: &Breeze, document: &Document, segments: &[TextSegment]) {
+    if let [TextSegment { text, url: None }] = segments {
+        breeze.find.add(ui, body(breeze, document, text), |text| {
+            Label::new(text).wrap()
+        });
+        return;
+    }
+    ui.spacing_mut().item_spacing.x = 0.0;
+    for segment in segments {
+        match &segment.url {
+            Some(url) => render_link(ui, breeze, document, &segment.text, url.as_str(), false),
+            None => {
+                breeze
+                    .find
+                    .add(ui, body(breeze, document, &segment.text), Label::new);
+            }
+        }
+    }
+}
+
+fn render_input(ui: &mut Ui, breeze: &Breeze, document: &Document, link: &Link) {
+    render_marker(ui, breeze, document, link.marker.as_deref());
+    breeze
+        .find
+        .add(ui, body(breeze, document, link.label()), Label::new);
+    ui.add_space(4.0);
+    // Keep what has been typed per destination, across frames
+    let id = Id::new(("input", &link.url));
+    let mut query = ui.data_mut(|data| data.get_temp::<String>(id).unwrap_or_default());
+    ui.add(TextEdit::singleline(&mut query).hint_text("Input"));
+    ui.add_space(4.0);
+    let submitted = ui.button("Submit").clicked();
+    ui.data_mut(|data| data.insert_temp(id, query.clone()));
+    if submitted {
+        if let Ok(mut url) = breeze.current_url.join(&link.url) {
+            url.set_query(Some(&query));
+            navigate_to(breeze, &url, link.plaintext);
+        }
+    }
+}
+
+fn render_block(ui: &mut Ui, breeze: &Breeze, document: &Document, index: usize, block: &Block) {
+    let theme = &breeze.theme;
+    match block {
+        Block::Heading(level, text) => {
+            breeze.find.add(ui, theme.heading(*level, text), |text| {
+                Label::new(text).wrap()
+            });
+        }
+        Block::Paragraph(segments) if document.gutter => {
+            ui.horizontal(|ui| {
+                render_marker(ui, breeze, document, None);
+                render_segments(ui, breeze, document, segments);
+            });
+        }
+        Block::Paragraph(segments) => {
+            ui.horizontal_wrapped(|ui| render_segments(ui, breeze, document, segments));
+        }
+        Block::Link(link) | Block::Image(link) => {
+            ui.horizontal_wrapped(|ui| {
+                render_marker(ui, breeze, document, link.marker.as_deref());
+                render_link(
+                    ui,
+                    breeze,
+                    document,
+                    link.label(),
+                    &link.url,
+                    link.plaintext,
+                );
+            });
+        }
+        Block::ListItem(text) => {
+            ui.horizontal_wrapped(|ui| {
+                ui.label(body(breeze, document, "• "));
+                breeze.find.add(ui, body(breeze, document, text), |text| {
+                    Label::new(text).wrap()
+                });
+            });
+        }
+        Block::Quote(text) => {
+            ui.horizontal_wrapped(|ui| {
+                ui.label(body(breeze, document, "┃ ").color(theme.quote_color));
+                breeze
+                    .find
+                    .add(ui, theme.quote(text), |text| Label::new(text).wrap());
+            });
+        }
+        Block::Preformatted { text, alt_text } => {
+            // Scroll long lines sideways rather than wrapping or stretching the page
+            ScrollArea::horizontal()
+                .id_salt(("preformatted", index))
+                .auto_shrink([false, true])
+                .show(ui, |ui| {
+                    let text = theme.preformatted(text);
+                    let block = breeze.find.add(ui, text, |text| Label::new(text).extend());
+                    if let Some(alt_text) = alt_text {
+                        block
+                            .widget_info(|| WidgetInfo::labeled(WidgetType::Label, true, alt_text));
+                        block.on_hover_text(alt_text);
+                    }
+                });
+        }
+        Block::Input(link) => {
+            ui.horizontal(|ui| render_input(ui, breeze, document, link));
+        }
+    }
+}
+
+/// Renders a document, the one place pages are drawn whatever their protocol
+pub fn render_document(ui: &mut Ui, breeze: &Breeze, document: &Document) {
+    ui.style_mut().spacing.item_spacing = Vec2::new(0.0, 2.0);
+    for (index, block) in document.blocks.iter().enumerate() {
+        render_block(ui, breeze, document, index, block);
+    }
+}
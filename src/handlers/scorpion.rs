@@ -1,11 +1,6 @@
-use eframe::egui::{Color32, CursorIcon, Label, RichText, Sense, Ui};
-
-use crate::{Breeze, NavigationHint, Protocol};
-
-use super::ProtocolHandler;
-
 use codepage_437::{CP437_CONTROL, CP437_WINGDINGS};
-use url::Url;
+
+use crate::document::{Block, Document, Link};
 
 #[derive(Debug)]
 enum BlockType {
@@ -69,197 +64,140 @@ impl From<u8> for CharacterEncoding {
     }
 }
 
-#[derive(Debug)]
-struct Block {
-    block_type: BlockType,
-    attribute_data: String,
-    body_data: String,
-    plaintext: bool,
-}
-
-#[derive(Default)]
-pub struct Scorpion {
-    current_page_contents: Vec<Block>,
-}
-
-impl Scorpion {
-    fn parse_body_data(&mut self, encoding: CharacterEncoding, body_data: &[u8]) -> String {
-        let mut offset = 0;
-        let mut body_string = String::new();
-
-        while offset < body_data.len() {
-            match body_data[offset] {
-                // Whatever comes before it is some kind of section number or item number or a bullet indicating a list item.
-                0x02 => {}
-                // data+text sub-block start
-                0x05 => {}
-                // data+text sub-block separator
-                0x06 => {}
-                // data+text sub-block end
-                0x07 => {}
-                // Tab (preformatted only)
-                0x09 => {}
-                // Line break (preformatted only)
-                0x0A => {}
-                // Next byte - 0x40 is a graphics character from codepage 437
-                0x10 => {
-                    if encoding == CharacterEncoding::PC {
-                        body_string.push(CP437_WINGDINGS.decode(body_data[offset + 1] - 0x40));
-                        offset += 1;
+fn parse_body_data(encoding: CharacterEncoding, body_data: &[u8]) -> String {
+    let mut offset = 0;
+    let mut body_string = String::new();
+
+    while offset < body_data.len() {
+        match body_data[offset] {
+            // Whatever comes before it is some kind of section number or item number or a bullet indicating a list item.
+            0x02 => {}
+            // data+text sub-block start
+            0x05 => {}
+            // data+text sub-block separator
+            0x06 => {}
+            // data+text sub-block end
+            0x07 => {}
+            // Tab (preformatted only)
+            0x09 => {}
+            // Line break (preformatted only)
+            0x0A => {}
+            // Next byte - 0x40 is a graphics character from codepage 437
+            0x10 => {
+                if encoding == CharacterEncoding::PC {
+                    if let Some(c) = body_data.get(offset + 1) {
+                        body_string.push(CP437_WINGDINGS.decode(c.wrapping_sub(0x40)));
                     }
+                    offset += 1;
                 }
-                // Normal style
-                0x11 => {}
-                // Strong style
-                0x12 => {}
-                // Emphasis style
-                0x13 => {}
-                // Monospace style
-                0x14 => {}
-                // Forward text direction
-                0x15 => {}
-                // Reverse text direction
-                0x16 => {}
-                // Furigana block main text
-                0x17 => {}
-                // Furigana block furigana text
-                0x18 => {}
-                // Furigana block end
-                0x19 => {}
-                // Used for SGR codes
-                0x1B => {}
-                // Only with ISO 2022 character encoding; must be immediately
-                // followed by a GR character which is interpreted as G2 instead of G1
-                // (further GR characters are interpreted as G1). (In PC and TRON encodings,
-                // this code represents a graphic character or a part of one.)
-                0x8E => {}
-                // Like 0x8E but G3 instead of G2.
-                0x8F => {}
-                _ => {
-                    if encoding == CharacterEncoding::PC {
-                        body_string.push(CP437_CONTROL.decode(body_data[offset]));
-                        //body_string.push(body_data[offset] as char);
-                    } else {
-                        body_string.push(body_data[offset] as char);
-                    }
+            }
+            // Normal style
+            0x11 => {}
+            // Strong style
+            0x12 => {}
+            // Emphasis style
+            0x13 => {}
+            // Monospace style
+            0x14 => {}
+            // Forward text direction
+            0x15 => {}
+            // Reverse text direction
+            0x16 => {}
+            // Furigana block main text
+            0x17 => {}
+            // Furigana block furigana text
+            0x18 => {}
+            // Furigana block end
+            0x19 => {}
+            // Used for SGR codes
+            0x1B => {}
+            // Only with ISO 2022 character encoding; must be immediately
+            // followed by a GR character which is interpreted as G2 instead of G1
+            // (further GR characters are interpreted as G1). (In PC and TRON encodings,
+            // this code represents a graphic character or a part of one.)
+            0x8E => {}
+            // Like 0x8E but G3 instead of G2.
+            0x8F => {}
+            _ => {
+                if encoding == CharacterEncoding::PC {
+                    body_string.push(CP437_CONTROL.decode(body_data[offset]));
+                    //body_string.push(body_data[offset] as char);
+                } else {
+                    body_string.push(body_data[offset] as char);
                 }
             }
-            offset += 1;
         }
-        body_string
+        offset += 1;
     }
+    body_string
 }
 
-impl ProtocolHandler for Scorpion {
-    fn parse_content(&mut self, response: &[u8], plaintext: bool) {
-        if plaintext {
-            let block = Block {
-                block_type: BlockType::Paragraph,
-                attribute_data: String::new(),
-                body_data: String::from_utf8_lossy(response).to_string(),
-                plaintext: true,
-            };
-            self.current_page_contents = vec![block];
-            return;
-        }
-
-        let mut blocks = Vec::new();
-        let mut offset = 0;
-
-        // We use 6 since it is the minimum possible block size (1 byte type/encoding, 2 bytes attribute length, 3 byte body length)
-        while offset + 6 < response.len() {
-            let block_type = BlockType::from(response[offset] & 0x0F);
-            let character_encoding = CharacterEncoding::from(response[offset] & 0xF0);
-            offset += 1;
-
-            let attribute_length = (response[offset] as u16) << 8 | response[offset + 1] as u16;
-            offset += 2;
-            let attribute_data = response[offset..offset + attribute_length as usize].to_vec();
-            offset += attribute_length as usize;
-
-            let body_length = (response[offset] as u32) << 16
-                | (response[offset + 1] as u32) << 8
-                | (response[offset + 2] as u32);
-            offset += 3;
-            let body_data = response[offset..offset + body_length as usize].to_vec();
-            offset += body_length as usize;
-
-            blocks.push(Block {
-                block_type,
-                attribute_data: String::from_utf8_lossy(&attribute_data).to_string(),
-                body_data: self.parse_body_data(character_encoding, &body_data),
-                plaintext: false,
-            });
-        }
+fn to_block(block_type: BlockType, attribute_data: String, body_data: String) -> Option<Block> {
+    let link = || Link {
+        plaintext: attribute_data.ends_with(".txt"),
+        ..Link::new(&attribute_data, &body_data)
+    };
+    Some(match block_type {
+        BlockType::Paragraph => Block::text(&body_data),
+        BlockType::Heading1 => Block::Heading(1, body_data),
+        BlockType::Heading2 => Block::Heading(2, body_data),
+        BlockType::Heading3 => Block::Heading(3, body_data),
+        BlockType::Heading4 => Block::Heading(4, body_data),
+        BlockType::Heading5 => Block::Heading(5, body_data),
+        BlockType::Heading6 => Block::Heading(6, body_data),
+        BlockType::Hyperlink | BlockType::HyperlinkInteractive => Block::Link(link()),
+        BlockType::HyperlinkInput => Block::Input(link()),
+        BlockType::Blockquote => Block::Quote(body_data),
+        BlockType::Preformatted => Block::Preformatted {
+            text: body_data,
+            alt_text: None,
+        },
+        BlockType::AlternateService | BlockType::Metadata => return None,
+    })
+}
 
-        self.current_page_contents = blocks;
+pub fn parse(response: &[u8], plaintext: bool) -> Document {
+    if plaintext {
+        return Document::plain(&String::from_utf8_lossy(response));
     }
 
-    fn render_page(&self, ui: &mut Ui, breeze: &Breeze) {
-        self.current_page_contents
-            .iter()
-            .for_each(|block| match block.block_type {
-                _ if block.plaintext => {
-                    ui.monospace(&block.body_data);
-                }
-                BlockType::Paragraph => {
-                    let text = RichText::new(&block.body_data).size(14.0);
-                    ui.label(text);
-                }
-                BlockType::Heading1 => {
-                    ui.label(RichText::new(&block.body_data).size(24.0));
-                }
-                BlockType::Heading2 => {
-                    ui.label(RichText::new(&block.body_data).size(22.0));
-                }
-                BlockType::Heading3 => {
-                    ui.label(RichText::new(&block.body_data).size(20.0));
-                }
-                BlockType::Heading4 => {
-                    ui.label(RichText::new(&block.body_data).size(18.0));
-                }
-                BlockType::Heading5 => {
-                    ui.label(RichText::new(&block.body_data).size(16.0));
-                }
-                BlockType::Heading6 => {
-                    ui.label(RichText::new(&block.body_data).size(14.0));
-                }
-                BlockType::Hyperlink => {
-                    let link_text = RichText::new(&block.body_data)
-                        .color(Color32::BLUE)
-                        .underline()
-                        .monospace()
-                        .size(14.0);
-                    let current_url = breeze.current_url.clone();
-                    let mut url = current_url.join(&block.attribute_data).unwrap().to_string();
-                    if block.attribute_data.contains("://") {
-                        url = block.attribute_data.clone();
-                    }
+    let mut blocks = Vec::new();
+    let mut offset = 0;
+
+    // We use 6 since it is the minimum possible block size (1 byte type/encoding, 2 bytes attribute length, 3 byte body length)
+    while offset + 6 < response.len() {
+        let block_type = BlockType::from(response[offset] & 0x0F);
+        let character_encoding = CharacterEncoding::from(response[offset] & 0xF0);
+        offset += 1;
+
+        let attribute_length = (response[offset] as usize) << 8 | response[offset + 1] as usize;
+        offset += 2;
+        // Stop at a block that claims to run past the end of the response
+        let Some(attribute_data) = response.get(offset..offset + attribute_length) else {
+            break;
+        };
+        offset += attribute_length;
+
+        let Some(length) = response.get(offset..offset + 3) else {
+            break;
+        };
+        let body_length =
+            (length[0] as usize) << 16 | (length[1] as usize) << 8 | length[2] as usize;
+        offset += 3;
+        let Some(body_data) = response.get(offset..offset + body_length) else {
+            break;
+        };
+        offset += body_length;
+
+        blocks.extend(to_block(
+            block_type,
+            String::from_utf8_lossy(attribute_data).to_string(),
+            parse_body_data(character_encoding, body_data),
+        ));
+    }
 
-                    let link = ui.add(Label::new(link_text).sense(Sense::hover()));
-                    if link.hovered() {
-                        ui.ctx().set_cursor_icon(CursorIcon::PointingHand);
-                        *breeze.status_text.borrow_mut() = url.clone();
-                    }
-                    if link.clicked() {
-                        breeze.url.set(url.clone());
-                        let hint = if url.ends_with(".txt") {
-                            Protocol::Plaintext
-                        } else {
-                            Protocol::from_url(&Url::parse(&url).unwrap())
-                        };
-                        breeze.navigation_hint.set(Some(NavigationHint {
-                            url,
-                            protocol: hint,
-                            add_to_history: true,
-                        }));
-                    }
-                }
-                BlockType::Preformatted => {
-                    let text = RichText::new(&block.body_data).size(14.0);
-                    ui.code(text);
-                }
-                _ => {}
-            });
+    Document {
+        blocks,
+        ..Default::default()
     }
 }
@@ -0,0 +1,31 @@
+use crate::document::{Block, Document, Link};
+
+// Link lines take the form "=> URL [description]"
+fn parse_line(s: &str) -> Block {
+    let Some(link) = s.strip_prefix("=>").map(str::trim) else {
+        return Block::text(s);
+    };
+    if link.is_empty() {
+        return Block::text(s);
+    }
+    let (path, description) = link
+        .split_once(char::is_whitespace)
+        .map_or((link, ""), |(path, description)| (path, description.trim()));
+    Block::Link(Link {
+        marker: Some("=>".to_string()),
+        ..Link::new(path, description)
+    })
+}
+
+/// Parses documents served over the Text protocol, plain text with link lines
+pub fn parse(response: &[u8], plaintext: bool) -> Document {
+    let response = String::from_utf8_lossy(response);
+    if plaintext {
+        return Document::plain(&response);
+    }
+    Document {
+        blocks: response.lines().map(parse_line).collect(),
+        monospace: true,
+        gutter: false,
+    }
+}
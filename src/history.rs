@@ -1,23 +1,48 @@
+use std::collections::HashSet;
 use std::sync::{LazyLock, Mutex, MutexGuard};
 
+use eframe::egui::Vec2;
+use time::OffsetDateTime;
 use url::Url;
 
+use crate::db::{add_visited, get_visited};
+use crate::find::FindSelection;
 use crate::handlers::Protocol;
 
+/// How a page was being viewed when it was last left
+#[derive(Clone, Debug, Default)]
+pub struct PageState {
+    pub scroll_offset: Vec2,
+    pub find: Option<FindSelection>,
+}
+
 #[derive(Clone, Debug)]
 pub struct HistoryEntry {
     pub url: Url,
     pub protocol: Protocol,
+    pub state: PageState,
 }
 
 impl HistoryEntry {
     fn new(url: Url, protocol: Protocol) -> Self {
-        Self { url, protocol }
+        Self {
+            url,
+            protocol,
+            state: PageState::default(),
+        }
     }
 }
 
 static HISTORY: LazyLock<Mutex<Vec<HistoryEntry>>> = LazyLock::new(|| Mutex::new(Vec::new()));
 static HISTORY_INDEX: LazyLock<Mutex<usize>> = LazyLock::new(|| Mutex::new(0));
+// Every URL ever navigated to, loaded from the database so link styling survives restarts
+static VISITED: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(|| {
+    let visited = get_visited().unwrap_or_else(|e| {
+        println!("Failed to load visited links: {}", e);
+        Vec::new()
+    });
+    Mutex::new(visited.into_iter().collect())
+});
 
 pub fn add_entry(url: Url, protocol: Protocol) {
     let mut history = history();
@@ -47,6 +72,15 @@ pub fn remove_latest_entry() -> HistoryEntry {
     history.pop().unwrap()
 }
 
+/// Points the current entry somewhere else, e.g. when its page redirected
+pub fn replace_current_entry(url: Url, protocol: Protocol) {
+    let mut history = history();
+    let index = index();
+    if let Some(entry) = history.get_mut(*index) {
+        *entry = HistoryEntry::new(url, protocol);
+    }
+}
+
 pub fn back() -> Option<HistoryEntry> {
     let history = history();
     let mut index = index();
@@ -69,6 +103,36 @@ pub fn forward() -> Option<HistoryEntry> {
     }
 }
 
+pub fn mark_visited(url: &Url) {
+    let url = url.to_string();
+    if let Err(e) = add_visited(&url, OffsetDateTime::now_utc().unix_timestamp()) {
+        println!("Failed to record visit to {}: {}", url, e);
+    }
+    visited().insert(url);
+}
+
+pub fn is_visited(url: &str) -> bool {
+    // Normalise so that links written differently still match what was recorded
+    match Url::parse(url) {
+        Ok(url) => visited().contains(url.as_str()),
+        Err(_) => visited().contains(url),
+    }
+}
+
+pub fn save_page_state(state: PageState) {
+    let mut history = history();
+    let index = index();
+    if let Some(entry) = history.get_mut(*index) {
+        entry.state = state;
+    }
+}
+
+pub fn current_entry() -> Option<HistoryEntry> {
+    let history = history();
+    let index = index();
+    history.get(*index).cloned()
+}
+
 pub fn can_go_forward() -> bool {
     let index = index();
     *index + 1 < history().len()
@@ -83,6 +147,10 @@ fn history() -> MutexGuard<'static, Vec<HistoryEntry>> {
     HISTORY.lock().expect("Failed to lock history mutex")
 }
 
+fn visited() -> MutexGuard<'static, HashSet<String>> {
+    VISITED.lock().expect("Failed to lock visited links mutex")
+}
+
 fn index() -> MutexGuard<'static, usize> {
     HISTORY_INDEX
         .lock()
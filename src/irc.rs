@@ -0,0 +1,316 @@
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use native_tls::TlsConnector;
+
+use crate::networking::connect_through;
+use crate::settings;
+
+/// A single IRC protocol message, as described in RFC 1459 section 2.3.1
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    pub prefix: Option<String>,
+    pub command: String,
+    pub params: Vec<String>,
+}
+
+impl Message {
+    pub fn new(command: &str, params: &[&str]) -> Self {
+        Self {
+            prefix: None,
+            command: command.to_string(),
+            params: params.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut rest = line.trim_end_matches(['\r', '\n']);
+        // Message tags are not used, so skip over them
+        if rest.starts_with('@') {
+            rest = rest.split_once(' ')?.1;
+        }
+        let prefix = match rest.strip_prefix(':') {
+            Some(prefixed) => {
+                let (prefix, remainder) = prefixed.split_once(' ')?;
+                rest = remainder;
+                Some(prefix.to_string())
+            }
+            None => None,
+        };
+
+        let (middle, trailing) = match rest.split_once(" :") {
+            Some((middle, trailing)) => (middle, Some(trailing)),
+            None => (rest, None),
+        };
+        let mut words = middle.split(' ').filter(|w| !w.is_empty());
+        let command = words.next()?.to_uppercase();
+        let mut params = words.map(str::to_string).collect::<Vec<_>>();
+        if let Some(trailing) = trailing {
+            params.push(trailing.to_string());
+        }
+
+        Some(Self {
+            prefix,
+            command,
+            params,
+        })
+    }
+
+    pub fn to_line(&self) -> String {
+        let mut line = String::new();
+        if let Some(prefix) = &self.prefix {
+            line.push_str(&format!(":{} ", prefix));
+        }
+        line.push_str(&self.command);
+        for (i, param) in self.params.iter().enumerate() {
+            let last = i + 1 == self.params.len();
+            if last && (param.is_empty() || param.contains(' ') || param.starts_with(':')) {
+                line.push_str(&format!(" :{}", param));
+            } else {
+                line.push_str(&format!(" {}", param));
+            }
+        }
+        line
+    }
+
+    /// The nickname part of a `nick!user@host` prefix
+    pub fn source_nick(&self) -> Option<&str> {
+        let prefix = self.prefix.as_deref()?;
+        Some(prefix.split_once('!').map_or(prefix, |(nick, _)| nick))
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ServerConfig {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+    pub nick: String,
+    pub sasl_username: String,
+    /// Only kept for the session, it isn't saved with the rest
+    pub sasl_password: String,
+    /// Channels joined once registration completes, separated by spaces
+    pub autojoin: String,
+}
+
+impl ServerConfig {
+    fn uses_sasl(&self) -> bool {
+        !self.sasl_username.is_empty() && !self.sasl_password.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub enum Event {
+    Message(Message),
+    Status(String),
+    Disconnected(String),
+}
+
+/// A connection to an IRC server running on its own thread
+pub struct Connection {
+    commands: Sender<Message>,
+    events: Receiver<Event>,
+}
+
+impl Connection {
+    pub fn connect(config: ServerConfig) -> Self {
+        let (command_tx, command_rx) = channel();
+        let (event_tx, event_rx) = channel();
+        thread::spawn(move || {
+            let result = connect_stream(&config).and_then(|stream| match stream {
+                Stream::Plain(stream) => run_session(stream, &config, &event_tx, &command_rx),
+                Stream::Tls(stream) => run_session(stream, &config, &event_tx, &command_rx),
+            });
+            let reason = match result {
+                Ok(()) => "Connection closed".to_string(),
+                Err(e) => e,
+            };
+            let _ = event_tx.send(Event::Disconnected(reason));
+        });
+        Self {
+            commands: command_tx,
+            events: event_rx,
+        }
+    }
+
+    pub fn send(&self, message: Message) {
+        let _ = self.commands.send(message);
+    }
+
+    pub fn poll(&self) -> Vec<Event> {
+        self.events.try_iter().collect()
+    }
+}
+
+enum Stream {
+    Plain(TcpStream),
+    Tls(native_tls::TlsStream<TcpStream>),
+}
+
+fn connect_stream(config: &ServerConfig) -> Result<Stream, String> {
+    let proxy = settings::settings().proxy("irc");
+    let stream = connect_through(&config.host, config.port, proxy.as_ref())?;
+    // Reads time out periodically so queued commands can be written in between
+    stream
+        .set_read_timeout(Some(Duration::from_millis(100)))
+        .map_err(|e| e.to_string())?;
+    if !config.tls {
+        return Ok(Stream::Plain(stream));
+    }
+    let connector = TlsConnector::new().map_err(|e| e.to_string())?;
+    let stream = connector
+        .connect(&config.host, stream)
+        .map_err(|e| e.to_string())?;
+    Ok(Stream::Tls(stream))
+}
+
+/// Registers with the server and relays messages until the connection closes.
+///
+/// Works on any stream so sessions can be driven by a local stand-in server.
+pub fn run_session<S: Read + Write>(
+    stream: S,
+    config: &ServerConfig,
+    events: &Sender<Event>,
+    commands: &Receiver<Message>,
+) -> Result<(), String> {
+    let mut reader = BufReader::new(stream);
+    let mut nick = config.nick.clone();
+    let mut registered = false;
+
+    fn send<S: Write>(reader: &mut BufReader<S>, message: Message) -> Result<(), String> {
+        let line = format!("{}\r\n", message.to_line());
+        reader
+            .get_mut()
+            .write_all(line.as_bytes())
+            .map_err(|e| e.to_string())
+    }
+
+    if config.uses_sasl() {
+        send(&mut reader, Message::new("CAP", &["REQ", "sasl"]))?;
+    }
+    send(&mut reader, Message::new("NICK", &[&nick]))?;
+    send(&mut reader, Message::new("USER", &[&nick, "0", "*", &nick]))?;
+    let _ = events.send(Event::Status(format!("Registering as {}", nick)));
+
+    // Kept as bytes so a read timing out part way through a character loses nothing
+    let mut line = Vec::new();
+    loop {
+        match commands.try_recv() {
+            Ok(message) => {
+                let quitting = message.command == "QUIT";
+                send(&mut reader, message)?;
+                if quitting {
+                    return Ok(());
+                }
+                continue;
+            }
+            Err(TryRecvError::Disconnected) => return Ok(()),
+            Err(TryRecvError::Empty) => {}
+        }
+
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) => return Ok(()),
+            Ok(_) => {}
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => continue,
+            Err(e) => return Err(e.to_string()),
+        }
+        let Some(message) = Message::parse(&String::from_utf8_lossy(&line)) else {
+            line.clear();
+            continue;
+        };
+        line.clear();
+
+        match (message.command.as_str(), message.params.as_slice()) {
+            ("PING", params) => {
+                let token = params.first().map_or("", String::as_str);
+                send(&mut reader, Message::new("PONG", &[token]))?;
+            }
+            ("CAP", [_, subcommand, caps, ..]) if subcommand == "ACK" && caps.contains("sasl") => {
+                send(&mut reader, Message::new("AUTHENTICATE", &["PLAIN"]))?;
+            }
+            ("CAP", [_, subcommand, ..]) if subcommand == "NAK" => {
+                let _ = events.send(Event::Status("Server does not support SASL".to_string()));
+                send(&mut reader, Message::new("CAP", &["END"]))?;
+            }
+            ("AUTHENTICATE", [challenge, ..]) if challenge == "+" => {
+                let credentials = format!(
+                    "{}\0{}\0{}",
+                    config.sasl_username, config.sasl_username, config.sasl_password
+                );
+                let encoded = BASE64_STANDARD.encode(credentials);
+                send(&mut reader, Message::new("AUTHENTICATE", &[&encoded]))?;
+            }
+            // SASL success, failure, or aborted
+            ("903" | "904" | "905" | "906", _) => {
+                send(&mut reader, Message::new("CAP", &["END"]))?;
+            }
+            // Nickname already in use during registration
+            ("433", _) if !registered => {
+                nick.push('_');
+                send(&mut reader, Message::new("NICK", &[&nick]))?;
+            }
+            ("001", _) => {
+                registered = true;
+                for channel in config.autojoin.split_whitespace() {
+                    send(&mut reader, Message::new("JOIN", &[channel]))?;
+                }
+            }
+            _ => {}
+        }
+        let _ = events.send(Event::Message(message));
+    }
+}
+
+/// Turns a line typed into a buffer into the messages to send.
+///
+/// Plain text is sent to `target`, while lines starting with `/` are treated as commands.
+pub fn parse_input(input: &str, target: Option<&str>) -> Result<Vec<Message>, String> {
+    let input = input.trim_end();
+    let require_target = || target.ok_or("This buffer does not accept messages".to_string());
+    let Some(command_line) = input.strip_prefix('/') else {
+        return Ok(vec![Message::new("PRIVMSG", &[require_target()?, input])]);
+    };
+    let (command, args) = command_line.split_once(' ').unwrap_or((command_line, ""));
+    let args = args.trim();
+    let messages = match command.to_lowercase().as_str() {
+        "join" | "j" if !args.is_empty() => {
+            let params = args.split_whitespace().collect::<Vec<_>>();
+            vec![Message::new("JOIN", &params)]
+        }
+        "part" | "leave" => {
+            let (channel, reason) = match args.split_once(' ') {
+                Some((channel, reason)) if channel.starts_with(['#', '&']) => (channel, reason),
+                _ if args.starts_with(['#', '&']) => (args, ""),
+                _ => (require_target()?, args),
+            };
+            if reason.is_empty() {
+                vec![Message::new("PART", &[channel])]
+            } else {
+                vec![Message::new("PART", &[channel, reason])]
+            }
+        }
+        "msg" | "query" => match args.split_once(' ') {
+            Some((recipient, text)) => vec![Message::new("PRIVMSG", &[recipient, text])],
+            None => return Err("Usage: /msg <nick> <message>".to_string()),
+        },
+        "me" => {
+            let action = format!("\x01ACTION {}\x01", args);
+            vec![Message::new("PRIVMSG", &[require_target()?, &action])]
+        }
+        "nick" if !args.is_empty() => vec![Message::new("NICK", &[args])],
+        "quit" => vec![Message::new("QUIT", &[args])],
+        // Escape hatch for anything without a dedicated command
+        "quote" | "raw" => {
+            vec![Message::parse(args).ok_or("Usage: /quote <raw IRC line>".to_string())?]
+        }
+        "join" | "j" => return Err("Usage: /join <#channel> [key]".to_string()),
+        "nick" => return Err("Usage: /nick <new nickname>".to_string()),
+        _ => return Err(format!("Unknown command: /{}", command)),
+    };
+    Ok(messages)
+}
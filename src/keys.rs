@@ -0,0 +1,295 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fs;
+
+use eframe::egui::{
+    Align2, Color32, Context, Event, FontId, Id, Key, KeyboardShortcut, LayerId, Modifiers, Order,
+    Rect, Response, Ui,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Back,
+    Forward,
+    Reload,
+    HardReload,
+    FocusUrlBar,
+    ScrollDown,
+    ScrollUp,
+    PageDown,
+    PageUp,
+    ScrollTop,
+    ScrollBottom,
+    LinkHints,
+}
+
+impl Action {
+    fn from_name(name: &str) -> Option<Action> {
+        Some(match name {
+            "back" => Action::Back,
+            "forward" => Action::Forward,
+            "reload" => Action::Reload,
+            "hard_reload" => Action::HardReload,
+            "focus_url_bar" => Action::FocusUrlBar,
+            "scroll_down" => Action::ScrollDown,
+            "scroll_up" => Action::ScrollUp,
+            "page_down" => Action::PageDown,
+            "page_up" => Action::PageUp,
+            "scroll_top" => Action::ScrollTop,
+            "scroll_bottom" => Action::ScrollBottom,
+            "link_hints" => Action::LinkHints,
+            _ => return None,
+        })
+    }
+}
+
+/// Parses shortcuts written like "Ctrl+L", "Alt+ArrowLeft" or "j"
+fn parse_shortcut(s: &str) -> Option<KeyboardShortcut> {
+    let mut modifiers = Modifiers::NONE;
+    let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+    let key = Key::from_name(parts.pop()?)?;
+    for part in parts {
+        match part.to_lowercase().as_str() {
+            "ctrl" | "control" => modifiers.ctrl = true,
+            "alt" | "option" => modifiers.alt = true,
+            "shift" => modifiers.shift = true,
+            "cmd" | "command" => modifiers.command = true,
+            _ => return None,
+        }
+    }
+    Some(KeyboardShortcut::new(modifiers, key))
+}
+
+pub struct Bindings {
+    bindings: Vec<(KeyboardShortcut, Action)>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let defaults = [
+            ("Alt+ArrowLeft", Action::Back),
+            ("Shift+H", Action::Back),
+            ("Alt+ArrowRight", Action::Forward),
+            ("Shift+L", Action::Forward),
+            ("F5", Action::Reload),
+            ("Ctrl+R", Action::Reload),
+            ("r", Action::Reload),
+            ("Shift+F5", Action::HardReload),
+            ("Ctrl+Shift+R", Action::HardReload),
+            ("Ctrl+L", Action::FocusUrlBar),
+            ("o", Action::FocusUrlBar),
+            ("j", Action::ScrollDown),
+            ("ArrowDown", Action::ScrollDown),
+            ("k", Action::ScrollUp),
+            ("ArrowUp", Action::ScrollUp),
+            ("Space", Action::PageDown),
+            ("PageDown", Action::PageDown),
+            ("Shift+Space", Action::PageUp),
+            ("PageUp", Action::PageUp),
+            ("g", Action::ScrollTop),
+            ("Home", Action::ScrollTop),
+            ("Shift+G", Action::ScrollBottom),
+            ("End", Action::ScrollBottom),
+            ("f", Action::LinkHints),
+        ];
+        let mut bindings = Self {
+            bindings: defaults
+                .into_iter()
+                .filter_map(|(shortcut, action)| Some((parse_shortcut(shortcut)?, action)))
+                .collect(),
+        };
+        bindings.sort();
+        bindings
+    }
+}
+
+impl Bindings {
+    /// The default bindings with any overrides from the user's keys.toml applied. Each entry maps
+    /// an action name to a list of shortcuts, e.g. `back = ["Alt+ArrowLeft", "Backspace"]`
+    pub fn load() -> Self {
+        let mut bindings = Self::default();
+        let Some(path) = dirs::config_dir().map(|dir| dir.join("breeze").join("keys.toml")) else {
+            return bindings;
+        };
+        let Ok(text) = fs::read_to_string(&path) else {
+            return bindings;
+        };
+        let overrides: HashMap<String, Vec<String>> = match toml::from_str(&text) {
+            Ok(overrides) => overrides,
+            Err(e) => {
+                println!("Failed to parse {}: {}", path.display(), e);
+                return bindings;
+            }
+        };
+        for (name, shortcuts) in overrides {
+            let Some(action) = Action::from_name(&name) else {
+                println!("Unknown action in key bindings: {}", name);
+                continue;
+            };
+            bindings.bindings.retain(|(_, a)| *a != action);
+            for shortcut in shortcuts {
+                match parse_shortcut(&shortcut) {
+                    Some(shortcut) => bindings.bindings.push((shortcut, action)),
+                    None => println!("Invalid shortcut for {}: {}", name, shortcut),
+                }
+            }
+        }
+        bindings.sort();
+        bindings
+    }
+
+    // egui matches shortcuts ignoring extra modifiers, so try the most specific ones first
+    fn sort(&mut self) {
+        let count = |m: Modifiers| {
+            [m.ctrl, m.alt, m.shift, m.command]
+                .iter()
+                .filter(|m| **m)
+                .count()
+        };
+        self.bindings
+            .sort_by_key(|(shortcut, _)| std::cmp::Reverse(count(shortcut.modifiers)));
+    }
+
+    /// Consumes the shortcuts pressed this frame, returning their actions. Bindings without
+    /// Ctrl, Alt or Command are skipped while a text field has focus so typing still works.
+    pub fn pressed(&self, ctx: &Context) -> Vec<Action> {
+        let typing = ctx.wants_keyboard_input();
+        ctx.input_mut(|input| {
+            self.bindings
+                .iter()
+                .filter(|(shortcut, _)| {
+                    let m = shortcut.modifiers;
+                    !typing || m.ctrl || m.alt || m.command
+                })
+                .filter(|(shortcut, _)| input.consume_shortcut(shortcut))
+                .map(|(_, action)| *action)
+                .collect()
+        })
+    }
+}
+
+const HINT_CHARS: &[char] = &['a', 's', 'd', 'f', 'g', 'h', 'j', 'k', 'l'];
+
+/// Overlays short labels on visible links so they can be followed by typing the label
+#[derive(Default)]
+pub struct LinkHints {
+    pub active: bool,
+    typed: String,
+    /// Link rects registered so far this frame, in render order
+    links: RefCell<Vec<Rect>>,
+    /// Number of links registered over the whole of the last frame
+    count: usize,
+    chosen: Cell<Option<usize>>,
+}
+
+impl LinkHints {
+    pub fn start(&mut self) {
+        self.active = true;
+        self.typed.clear();
+        self.chosen.set(None);
+    }
+
+    pub fn cancel(&mut self) {
+        self.active = false;
+        self.typed.clear();
+    }
+
+    fn label(&self, index: usize) -> String {
+        // Every label has the same length so none is a prefix of another
+        let mut length = 1;
+        while HINT_CHARS.len().pow(length) < self.count {
+            length += 1;
+        }
+        let mut label = String::new();
+        let mut rest = index;
+        for _ in 0..length {
+            label.insert(0, HINT_CHARS[rest % HINT_CHARS.len()]);
+            rest /= HINT_CHARS.len();
+        }
+        label
+    }
+
+    /// Handles typed hint characters, call before rendering the page
+    pub fn handle_input(&mut self, ctx: &Context) {
+        if !self.active {
+            return;
+        }
+        // Swallow keyboard input so typed hints don't trigger anything else
+        let events = ctx.input_mut(|input| {
+            let (keys, other) = std::mem::take(&mut input.events)
+                .into_iter()
+                .partition(|event| matches!(event, Event::Key { .. } | Event::Text(_)));
+            input.events = other;
+            keys
+        });
+        for event in events {
+            match event {
+                Event::Key {
+                    key: Key::Escape,
+                    pressed: true,
+                    ..
+                } => self.cancel(),
+                Event::Key {
+                    key: Key::Backspace,
+                    pressed: true,
+                    ..
+                } => {
+                    self.typed.pop();
+                }
+                Event::Text(text) => self.typed.push_str(&text.to_lowercase()),
+                _ => {}
+            }
+        }
+        if !self.active {
+            return;
+        }
+        let chosen = (0..self.count).find(|&i| self.label(i) == self.typed);
+        let any_prefix = (0..self.count).any(|i| self.label(i).starts_with(&self.typed));
+        if chosen.is_some() {
+            self.chosen.set(chosen);
+        } else if !any_prefix {
+            self.cancel();
+        }
+    }
+
+    /// Registers a link that is being rendered and returns whether its hint was just typed
+    pub fn hinted(&self, ui: &Ui, link: &Response) -> bool {
+        if !self.active || !ui.clip_rect().intersects(link.rect) {
+            return false;
+        }
+        let mut links = self.links.borrow_mut();
+        let index = links.len();
+        links.push(link.rect);
+        self.chosen.get() == Some(index)
+    }
+
+    /// Paints the hint labels over the links registered this frame, call after rendering the page
+    pub fn end(&mut self, ctx: &Context) {
+        let links = self.links.take();
+        self.count = links.len();
+        if !self.active {
+            return;
+        }
+        if self.chosen.take().is_some() || links.is_empty() {
+            self.cancel();
+            return;
+        }
+        let painter = ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("link_hints")));
+        for (index, rect) in links.iter().enumerate() {
+            let label = self.label(index);
+            if !label.starts_with(&self.typed) {
+                continue;
+            }
+            let galley = painter.layout_no_wrap(
+                label.to_uppercase(),
+                FontId::monospace(12.0),
+                Color32::BLACK,
+            );
+            let background = Align2::LEFT_TOP
+                .anchor_size(rect.left_top(), galley.size())
+                .expand(2.0);
+            painter.rect_filled(background, 2.0, Color32::from_rgb(0xff, 0xd6, 0x00));
+            painter.galley(rect.left_top(), galley, Color32::BLACK);
+        }
+    }
+}
@@ -0,0 +1,571 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use eframe::egui::{
+    Align, Button, CollapsingHeader, Context, Grid, Layout, RichText, ScrollArea, SidePanel,
+    TextEdit, Ui,
+};
+use poll_promise::Promise;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use url::Url;
+
+use crate::db::{
+    delete_message, get_default_profile, get_messages, mark_message_read, new_message,
+};
+use crate::document::Document;
+use crate::handlers::gemtext;
+use crate::handlers::render::render_document;
+use crate::handlers::Protocol;
+use crate::misfin::{MailServer, MAX_REQUEST_LENGTH};
+use crate::networking::{fetch, GeminiStatus, ServerResponse, ServerStatus};
+use crate::profile::fingerprint;
+use crate::settings::Settings;
+use crate::Breeze;
+
+/// A message as stored in the local mailbox
+pub struct MailMessage {
+    pub id: i64,
+    /// The sender for received messages, the recipient for sent ones
+    pub address: String,
+    pub subject: String,
+    /// The full message in gemmail format
+    pub body: String,
+    pub timestamp: i64,
+    pub read: bool,
+}
+
+/// A gemmail message split into its metadata lines and Gemtext body
+pub struct Gemmail {
+    pub senders: Vec<String>,
+    pub recipients: Vec<String>,
+    pub timestamps: Vec<String>,
+    pub subject: String,
+    pub body: String,
+}
+
+impl Gemmail {
+    pub fn parse(text: &str) -> Self {
+        let mut senders = Vec::new();
+        let mut recipients = Vec::new();
+        let mut timestamps = Vec::new();
+        let mut lines = text.lines().peekable();
+
+        // Metadata lines come before the message itself
+        while let Some(line) = lines.peek() {
+            if let Some(sender) = line.strip_prefix('<') {
+                senders.push(sender.trim().to_string());
+            } else if let Some(receivers) = line.strip_prefix(':') {
+                recipients.extend(receivers.split_whitespace().map(str::to_string));
+            } else if let Some(timestamp) = line.strip_prefix('@') {
+                timestamps.push(timestamp.trim().to_string());
+            } else {
+                break;
+            }
+            lines.next();
+        }
+
+        let body = lines.collect::<Vec<_>>().join("\n");
+        let subject = body
+            .lines()
+            .find_map(|line| line.strip_prefix('#'))
+            .map(|heading| heading.trim_start_matches('#').trim().to_string())
+            .filter(|subject| !subject.is_empty())
+            .unwrap_or_else(|| "(no subject)".to_string());
+
+        Self {
+            senders,
+            recipients,
+            timestamps,
+            subject,
+            body,
+        }
+    }
+
+    // The address part of the first sender line, which is followed by an optional name
+    fn sender_address(&self) -> Option<&str> {
+        self.senders
+            .first()
+            .and_then(|sender| sender.split_whitespace().next())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Folder {
+    Inbox,
+    Sent,
+}
+
+impl Folder {
+    fn as_str(&self) -> &str {
+        match self {
+            Folder::Inbox => "inbox",
+            Folder::Sent => "sent",
+        }
+    }
+
+    fn label(&self) -> &str {
+        match self {
+            Folder::Inbox => "📥 Inbox",
+            Folder::Sent => "📤 Sent",
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Draft {
+    pub recipient: String,
+    pub subject: String,
+    pub body: String,
+}
+
+impl Draft {
+    fn to_gemmail(&self) -> String {
+        let subject = self.subject.trim();
+        if subject.is_empty() {
+            self.body.trim_end().to_string()
+        } else {
+            format!("# {}\n\n{}", subject, self.body.trim_end())
+        }
+    }
+}
+
+struct SendJob {
+    draft: Draft,
+    promise: Promise<Result<ServerResponse, String>>,
+}
+
+pub struct Mail {
+    folder: Folder,
+    messages: Vec<MailMessage>,
+    should_refresh: bool,
+    selected: Option<i64>,
+    reader: Document,
+    draft: Option<Draft>,
+    send_job: Option<SendJob>,
+    status: String,
+    server: Option<MailServer>,
+    /// Whether mail is being received, or why it can't be
+    receiving: String,
+}
+
+impl Default for Mail {
+    fn default() -> Self {
+        Self {
+            folder: Folder::Inbox,
+            messages: Vec::new(),
+            should_refresh: true,
+            selected: None,
+            reader: Document::default(),
+            draft: None,
+            send_job: None,
+            status: String::new(),
+            server: None,
+            receiving: String::new(),
+        }
+    }
+}
+
+// Stores a received message in the inbox. Its sender is whoever the message says it's from,
+// since they can't be asked for their certificate.
+fn deliver(address: &str, message: &str) -> Result<(), String> {
+    let gemmail = Gemmail::parse(message);
+    let sender = gemmail.sender_address().unwrap_or("unknown sender");
+    println!("Received mail for {} from {}", address, sender);
+    new_message(
+        Folder::Inbox.as_str(),
+        sender,
+        &gemmail.subject,
+        message,
+        OffsetDateTime::now_utc().unix_timestamp(),
+    )
+    .map_err(|e| format!("Failed to store the message: {}", e))
+}
+
+impl Mail {
+    /// Starts or stops receiving mail to match the settings, or starts again anyway when the
+    /// profile it is received as may have changed
+    pub fn listen(&mut self, settings: &Settings, restart: bool) {
+        let port = settings.receive_mail.then_some(settings.mail_port);
+        if !restart && self.server.as_ref().map(|server| server.port) == port {
+            return;
+        }
+        self.server = None;
+        self.receiving.clear();
+        let Some(port) = port else { return };
+        let server = get_default_profile()
+            .map_err(|e| format!("no usable profile to receive as ({})", e))
+            .and_then(|profile| {
+                let fingerprint = fingerprint(&profile.cert)?;
+                MailServer::start(port, profile.identity, fingerprint, Arc::new(deliver))
+            });
+        match server {
+            Ok(server) => {
+                self.receiving = format!("Receiving mail on port {}", server.port);
+                self.server = Some(server);
+            }
+            Err(e) => self.receiving = format!("Can't receive mail: {}", e),
+        }
+    }
+
+    /// Opens the composer, used when navigating to a misfin:// address
+    pub fn compose_to(&mut self, recipient: String) {
+        self.draft = Some(Draft {
+            recipient,
+            ..Default::default()
+        });
+    }
+
+    fn select(&mut self, id: i64) {
+        let Some(message) = self.messages.iter_mut().find(|m| m.id == id) else {
+            return;
+        };
+        if !message.read {
+            let _ = mark_message_read(id);
+            message.read = true;
+        }
+        let gemmail = Gemmail::parse(&message.body);
+        self.reader = gemtext::parse(gemmail.body.as_bytes(), false);
+        self.selected = Some(id);
+    }
+
+    fn send(&mut self, draft: Draft) {
+        let address = draft.recipient.trim().trim_start_matches("misfin://");
+        let url = match Url::parse(&format!("misfin://{}", address)) {
+            Ok(url) if !url.username().is_empty() && url.host_str().is_some() => url,
+            _ => {
+                self.status = "Recipients must be addressed as user@host".to_string();
+                self.draft = Some(draft);
+                return;
+            }
+        };
+        if get_default_profile().is_err() {
+            self.status =
+                "Misfin sends as one of your profiles, create one under Profile > Manage certificates"
+                    .to_string();
+            self.draft = Some(draft);
+            return;
+        }
+        let request_body = format!("{} {}", url, draft.to_gemmail());
+        if request_body.len() + 2 > MAX_REQUEST_LENGTH {
+            self.status = format!(
+                "Message is too long, Misfin requests are limited to {} bytes",
+                MAX_REQUEST_LENGTH
+            );
+            self.draft = Some(draft);
+            return;
+        }
+
+        self.status = format!("Sending to {}...", address);
+        let promise = Promise::spawn_thread("misfin", move || {
+            fetch(&url, &request_body, true, Protocol::Misfin)
+        });
+        self.send_job = Some(SendJob { draft, promise });
+    }
+
+    fn poll_send_job(&mut self) {
+        let Some(job) = &self.send_job else { return };
+        let Some(result) = job.promise.ready() else {
+            return;
+        };
+        let recipient = job.draft.recipient.trim().trim_start_matches("misfin://");
+        self.status = match result {
+            Ok(ServerResponse {
+                status: ServerStatus::Gemini(status),
+                ..
+            }) => match status {
+                GeminiStatus::Success(_) => {
+                    let now = OffsetDateTime::now_utc();
+                    let body = format!(
+                        ": {}\n@ {}\n{}",
+                        recipient,
+                        now.format(&Rfc3339).unwrap_or_default(),
+                        job.draft.to_gemmail()
+                    );
+                    let subject = Gemmail::parse(&body).subject;
+                    let _ = new_message(
+                        Folder::Sent.as_str(),
+                        recipient,
+                        &subject,
+                        &body,
+                        now.unix_timestamp(),
+                    );
+                    self.should_refresh = true;
+                    format!("Message delivered to {}", recipient)
+                }
+                GeminiStatus::TemporaryRedirect(address)
+                | GeminiStatus::PermanentRedirect(address) => {
+                    self.draft = Some(Draft {
+                        recipient: address.trim().to_string(),
+                        ..job.draft.clone()
+                    });
+                    format!("{} has moved to {}", recipient, address.trim())
+                }
+                GeminiStatus::RequiresClientCertificate
+                | GeminiStatus::CertificateNotAuthorized
+                | GeminiStatus::CertificateNotValid => {
+                    self.draft = Some(job.draft.clone());
+                    "The server rejected your profile's certificate".to_string()
+                }
+                GeminiStatus::TemporaryFailure(data)
+                | GeminiStatus::ServerUnavailable(data)
+                | GeminiStatus::CGIError(data)
+                | GeminiStatus::ProxyError(data)
+                | GeminiStatus::SlowDown(data)
+                | GeminiStatus::PermanentFailure(data)
+                | GeminiStatus::NotFound(data)
+                | GeminiStatus::Gone(data)
+                | GeminiStatus::ProxyRequestRefused(data)
+                | GeminiStatus::BadRequest(data)
+                | GeminiStatus::InputExpected(data, _) => {
+                    self.draft = Some(job.draft.clone());
+                    format!("Delivery failed: {}", data)
+                }
+            },
+            Ok(response) => format!("Unexpected response: {:?}", response.status),
+            Err(error) => {
+                self.draft = Some(job.draft.clone());
+                format!("Delivery failed: {}", error)
+            }
+        };
+        self.send_job = None;
+    }
+
+    fn reply(&mut self, id: i64) {
+        let Some(message) = self.messages.iter().find(|m| m.id == id) else {
+            return;
+        };
+        let gemmail = Gemmail::parse(&message.body);
+        let recipient = match self.folder {
+            Folder::Inbox => gemmail.sender_address().unwrap_or(&message.address),
+            Folder::Sent => &message.address,
+        };
+        let subject = if message.subject.to_lowercase().starts_with("re:") {
+            message.subject.clone()
+        } else {
+            format!("Re: {}", message.subject)
+        };
+        let quoted = gemmail
+            .body
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| format!("> {}", line))
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.draft = Some(Draft {
+            recipient: recipient.to_string(),
+            subject,
+            body: format!("\n\n{}", quoted),
+        });
+    }
+
+    // Groups messages into threads by subject, ignoring reply prefixes
+    fn threads(&self) -> Vec<(&str, Vec<&MailMessage>)> {
+        let mut threads: BTreeMap<String, Vec<&MailMessage>> = BTreeMap::new();
+        for message in &self.messages {
+            threads
+                .entry(thread_key(&message.subject))
+                .or_default()
+                .push(message);
+        }
+        let mut threads = threads
+            .into_values()
+            .map(|messages| (messages[0].subject.as_str(), messages))
+            .collect::<Vec<_>>();
+        // Messages are already newest first, so order threads by their latest message
+        threads.sort_by_key(|(_, messages)| std::cmp::Reverse(messages[0].timestamp));
+        threads
+    }
+}
+
+fn thread_key(subject: &str) -> String {
+    let mut subject = subject.trim().to_lowercase();
+    while let Some(stripped) = subject.strip_prefix("re:") {
+        subject = stripped.trim_start().to_string();
+    }
+    subject
+}
+
+fn format_timestamp(timestamp: i64) -> String {
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .ok()
+        .and_then(|t| t.format(&Rfc3339).ok())
+        .unwrap_or_default()
+}
+
+pub fn render_mail(ui: &mut Ui, _ctx: &Context, breeze: &mut Breeze) {
+    let mail = &mut breeze.mail;
+    mail.poll_send_job();
+    if mail
+        .server
+        .as_ref()
+        .is_some_and(|server| !server.poll().is_empty())
+        && mail.folder == Folder::Inbox
+    {
+        mail.should_refresh = true;
+    }
+    if mail.should_refresh {
+        mail.should_refresh = false;
+        mail.messages = get_messages(mail.folder.as_str()).unwrap_or_default();
+    }
+
+    ui.horizontal(|ui| {
+        if ui.button("✉ Compose").clicked() {
+            mail.draft = Some(Draft::default());
+        }
+        ui.label(&mail.status);
+        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+            ui.label(RichText::new(&mail.receiving).weak());
+        });
+    });
+    ui.separator();
+
+    SidePanel::left("mail_folders")
+        .resizable(false)
+        .show_inside(ui, |ui| {
+            for folder in [Folder::Inbox, Folder::Sent] {
+                if ui
+                    .selectable_label(mail.folder == folder, folder.label())
+                    .clicked()
+                {
+                    mail.folder = folder;
+                    mail.selected = None;
+                    mail.should_refresh = true;
+                }
+            }
+        });
+
+    let mut selection = None;
+    SidePanel::left("mail_threads")
+        .default_width(240.0)
+        .show_inside(ui, |ui| {
+            ScrollArea::vertical().show(ui, |ui| {
+                if mail.messages.is_empty() {
+                    ui.label("No messages");
+                }
+                for (subject, messages) in mail.threads() {
+                    let mut message_row = |ui: &mut Ui, message: &MailMessage| {
+                        let mut text = RichText::new(format!("{} — {}", message.address, subject));
+                        if !message.read {
+                            text = text.strong();
+                        }
+                        if ui
+                            .selectable_label(mail.selected == Some(message.id), text)
+                            .clicked()
+                        {
+                            selection = Some(message.id);
+                        }
+                    };
+                    if messages.len() == 1 {
+                        message_row(ui, messages[0]);
+                    } else {
+                        CollapsingHeader::new(format!("{} ({})", subject, messages.len()))
+                            .id_salt(thread_key(subject))
+                            .show(ui, |ui| {
+                                for message in messages.iter().rev() {
+                                    message_row(ui, message);
+                                }
+                            });
+                    }
+                }
+            });
+        });
+    if let Some(id) = selection {
+        mail.select(id);
+    }
+
+    let mut reply = None;
+    let mut delete = None;
+    if let Some(message) = mail
+        .selected
+        .and_then(|id| mail.messages.iter().find(|m| m.id == id))
+    {
+        let gemmail = Gemmail::parse(&message.body);
+        Grid::new("mail_headers").num_columns(2).show(ui, |ui| {
+            if !gemmail.senders.is_empty() {
+                ui.label("From:");
+                ui.label(gemmail.senders.join(", "));
+                ui.end_row();
+            }
+            if !gemmail.recipients.is_empty() {
+                ui.label("To:");
+                ui.label(gemmail.recipients.join(", "));
+                ui.end_row();
+            }
+            ui.label("Date:");
+            ui.label(
+                gemmail
+                    .timestamps
+                    .first()
+                    .cloned()
+                    .unwrap_or_else(|| format_timestamp(message.timestamp)),
+            );
+            ui.end_row();
+            ui.label("Subject:");
+            ui.label(RichText::new(&message.subject).strong());
+            ui.end_row();
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Reply").clicked() {
+                reply = Some(message.id);
+            }
+            if ui.button("Delete").clicked() {
+                delete = Some(message.id);
+            }
+        });
+        ui.separator();
+    }
+    if mail.selected.is_some() {
+        ScrollArea::vertical().auto_shrink(false).show(ui, |ui| {
+            render_document(ui, breeze, &breeze.mail.reader);
+        });
+    }
+
+    let mail = &mut breeze.mail;
+    if let Some(id) = reply {
+        mail.reply(id);
+    }
+    if let Some(id) = delete {
+        let _ = delete_message(id);
+        mail.selected = None;
+        mail.should_refresh = true;
+    }
+
+    let mut send = None;
+    if let Some(draft) = &mut mail.draft {
+        let mut close = false;
+        eframe::egui::Modal::new("misfin".into()).show(ui.ctx(), |ui| {
+            ui.heading("New message");
+            Grid::new("draft_headers").num_columns(2).show(ui, |ui| {
+                ui.label("To:");
+                ui.add(TextEdit::singleline(&mut draft.recipient).hint_text("user@host"));
+                ui.end_row();
+                ui.label("Subject:");
+                ui.add(TextEdit::singleline(&mut draft.subject));
+                ui.end_row();
+            });
+            ui.add(
+                TextEdit::multiline(&mut draft.body)
+                    .desired_rows(12)
+                    .desired_width(480.0)
+                    .hint_text("Message body, written in Gemtext"),
+            );
+            ui.horizontal(|ui| {
+                let sending = mail.send_job.is_some();
+                if ui.add_enabled(!sending, Button::new("Send")).clicked() {
+                    send = Some(draft.clone());
+                    close = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    close = true;
+                }
+            });
+        });
+        if close {
+            mail.draft = None;
+        }
+    }
+    if let Some(draft) = send {
+        mail.send(draft);
+    }
+}
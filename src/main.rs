@@ -1,56 +1,108 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
+mod cache;
+mod certificates;
+mod chat;
+mod cli;
+mod composer;
 mod db;
+mod dns;
+mod document;
+mod find;
 mod handlers;
 mod history;
+mod irc;
+mod keys;
+mod mail;
+mod misfin;
 mod networking;
 mod profile;
+mod proxy;
+mod ratelimit;
+mod redirect;
+mod settings;
+#[cfg(test)]
+mod tests;
+mod theme;
 
 use std::cell::{Cell, RefCell};
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::str::FromStr;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use db::{get_all_profiles, set_active_profile};
 use eframe::egui::{
-    include_image, menu, vec2, Align, Button, CentralPanel, Context, CursorIcon, FontData,
-    FontDefinitions, FontFamily, Frame, IconData, Image, Key, Label, Layout, Modal, PointerButton,
-    RichText, ScrollArea, Separator, TextEdit, TopBottomPanel, Ui, ViewportBuilder, ViewportId,
+    include_image, menu, vec2, Align, Button, CentralPanel, Context, CursorIcon, DragValue,
+    FontData, FontDefinitions, FontFamily, Frame, IconData, Image, Key, KeyboardShortcut, Label,
+    Layout, Modal, Modifiers, PointerButton, RichText, ScrollArea, Separator, TextEdit,
+    TopBottomPanel, Vec2, ViewportBuilder, ViewportCommand, ViewportId,
 };
+use percent_encoding::percent_decode_str;
 use poll_promise::Promise;
 use url::Url;
 
-use crate::handlers::finger::Finger;
-use crate::handlers::gemtext::Gemtext;
-use crate::handlers::gopher::Gopher;
-use crate::handlers::nex::Nex;
-use crate::handlers::plaintext::Plaintext;
-use crate::handlers::scorpion::Scorpion;
-use crate::handlers::{Protocol, ProtocolHandler};
-use crate::history::{add_entry, can_go_back, can_go_forward};
+use crate::certificates::{
+    render_certificate_request, render_certificates, render_unlock, CertificateProblem,
+    CertificateRequest, Certificates,
+};
+use crate::chat::{render_chat, Chat};
+use crate::cli::{run_fetch, FetchArgs};
+use crate::composer::{render_composer, Composer};
+use crate::document::{Block, Document, Link};
+use crate::find::Find;
+use crate::handlers::render::render_document;
+use crate::handlers::{parse_content, parse_content_type, Protocol};
+use crate::history::{
+    add_entry, can_go_back, can_go_forward, current_entry, mark_visited, HistoryEntry, PageState,
+};
+use crate::keys::{Action, Bindings, LinkHints};
+use crate::mail::{render_mail, Mail};
 use crate::networking::{
-    fetch, GeminiStatus, ScorpionStatus, ServerResponse, ServerStatus, SpartanStatus,
-    TextProtocolStatus,
+    fetch, fetch_file, is_plaintext_url, request_for, upload, GeminiStatus, GuppyStatus,
+    ScorpionStatus, ServerResponse, ServerStatus, SpartanStatus, TextProtocolStatus,
 };
 use crate::profile::Profile;
+use crate::settings::{render_settings, Settings, SETTINGS_URL};
+use crate::theme::Theme;
 
 #[derive(Parser)]
 struct Args {
-    #[arg(short, long, default_value = "gemini://geminiprotocol.net/")]
-    url: String,
+    /// Page to open, instead of the home page from the settings
+    #[arg(short, long)]
+    url: Option<String>,
+    /// Keep profiles, mail and history in this directory rather than the platform's data directory
+    #[arg(long, global = true)]
+    profile_dir: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    Fetch(FetchArgs),
 }
 
 fn main() -> eframe::Result {
     let args = Args::parse();
+    if let Some(dir) = args.profile_dir {
+        db::set_data_dir(dir);
+    }
+    if let Some(Command::Fetch(fetch_args)) = args.command {
+        exit(run_fetch(fetch_args));
+    }
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
     let icon = include_bytes!("../res/breeze32.png");
     let image = image::load_from_memory(icon)
         .expect("Failed to open icon path")
         .to_rgba8();
+    let settings = settings::settings();
     let viewport = ViewportBuilder::default()
-        .with_inner_size([800.0, 600.0])
+        .with_inner_size([settings.window_width, settings.window_height])
         .with_icon(IconData {
             rgba: image.into_raw(),
             width: 32,
@@ -61,32 +113,16 @@ fn main() -> eframe::Result {
         ..Default::default()
     };
 
-    // Set up custom fonts needed for rendering
-    let mut fonts = FontDefinitions::default();
-    // UnifontEX for uniform monospace pan-unicode font
-    load_font!(
-        fonts,
-        FontFamily::Monospace,
-        "UnifontEx".to_string(),
-        "../res/UnifontExMono.ttf"
-    );
-    // Code2000 for proportional pan-unicode font
-    load_font!(
-        fonts,
-        FontFamily::Proportional,
-        "CODE2000".to_string(),
-        "../res/CODE2000.ttf"
-    );
-
     eframe::run_native(
         "Breeze",
         options,
         Box::new(|cc| {
             // This gives us image support:
             egui_extras::install_image_loaders(&cc.egui_ctx);
-            cc.egui_ctx.set_fonts(fonts);
+            cc.egui_ctx.set_fonts(load_fonts(&settings));
 
-            Ok(Box::<Breeze>::new(Breeze::new(args.url)))
+            let url = args.url.unwrap_or(settings.home_page);
+            Ok(Box::<Breeze>::new(Breeze::new(url)))
         }),
     )
 }
@@ -107,29 +143,62 @@ macro_rules! load_font {
     };
 }
 
-#[derive(Default)]
-struct ContentHandlers {
-    finger: Finger,
-    gemtext: Gemtext,
-    gopher: Gopher,
-    nex: Nex,
-    scorpion: Scorpion,
-    plaintext: Plaintext,
-}
+// Fonts needed for rendering, with any configured ones tried before the built-in ones
+fn load_fonts(settings: &Settings) -> FontDefinitions {
+    let mut fonts = FontDefinitions::default();
+    // UnifontEX for uniform monospace pan-unicode font
+    load_font!(
+        fonts,
+        FontFamily::Monospace,
+        "UnifontEx".to_string(),
+        "../res/UnifontExMono.ttf"
+    );
+    // Code2000 for proportional pan-unicode font
+    load_font!(
+        fonts,
+        FontFamily::Proportional,
+        "CODE2000".to_string(),
+        "../res/CODE2000.ttf"
+    );
 
-impl ContentHandlers {
-    pub fn parse_content(&mut self, response: &[u8], plaintext: bool, protocol: Protocol) {
-        match protocol {
-            Protocol::Finger => self.finger.parse_content(response, plaintext),
-            Protocol::Gemini | Protocol::Spartan | Protocol::Guppy | Protocol::Scroll => {
-                self.gemtext.parse_content(response, plaintext)
+    for (family, path) in [
+        (FontFamily::Proportional, &settings.proportional_font),
+        (FontFamily::Monospace, &settings.monospace_font),
+    ] {
+        let path = Path::new(path.trim());
+        // Half-typed paths are ignored rather than reported
+        if !path.is_file() {
+            continue;
+        }
+        // Anything that isn't a font would bring egui down when it builds the atlas
+        match fs::read(path) {
+            Ok(data) if ab_glyph::FontRef::try_from_slice(&data).is_err() => {
+                println!("Not a TrueType or OpenType font: {}", path.display());
+            }
+            Ok(data) => {
+                let name = path.display().to_string();
+                fonts
+                    .font_data
+                    .insert(name.clone(), Arc::new(FontData::from_owned(data)));
+                fonts.families.get_mut(&family).unwrap().insert(0, name);
             }
-            Protocol::Gopher(_) => self.gopher.parse_content(response, plaintext),
-            Protocol::Nex => self.nex.parse_content(response, plaintext),
-            Protocol::Scorpion => self.scorpion.parse_content(response, plaintext),
-            _ => self.plaintext.parse_content(response, plaintext),
+            Err(e) => println!("Failed to load font {}: {}", path.display(), e),
         }
     }
+    fonts
+}
+
+// Name to save a page under, taken from the end of its path
+fn download_name(url: &Url) -> String {
+    url.path_segments()
+        .and_then(|mut segments| segments.rfind(|s| !s.is_empty()))
+        .map(|name| {
+            percent_decode_str(name)
+                .decode_utf8_lossy()
+                .replace(['/', '\\'], "_")
+        })
+        .or_else(|| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| "index".to_string())
 }
 
 struct NavigationHint {
@@ -142,6 +211,12 @@ struct NavigationJob {
     nav_promise: Promise<Result<ServerResponse, String>>,
     plaintext: bool,
     protocol: Protocol,
+    /// Whether the response came from the cache rather than the network
+    cached: bool,
+    /// How to show the page once it has loaded, rather than from the top
+    restore: Option<PageState>,
+    /// Pages that redirected on the way here, oldest first
+    redirects: Vec<Url>,
 }
 
 impl NavigationJob {
@@ -154,6 +229,9 @@ impl NavigationJob {
             nav_promise,
             plaintext,
             protocol,
+            cached: false,
+            restore: None,
+            redirects: Vec::new(),
         }
     }
 }
@@ -166,11 +244,33 @@ struct InputRequest {
     pub completed: bool,
 }
 
+/// A redirect to another protocol or host, waiting for the user to allow it
+struct PendingRedirect {
+    target: Url,
+    chain: Vec<Url>,
+    permanent: bool,
+    reason: String,
+}
+
+/// A page whose server asked to slow down, fetched again once the wait is over
+struct SlowDown {
+    url: Url,
+    retry_at: Instant,
+}
+
+/// A message being written for submission to an NPS endpoint
+struct NpsComposer {
+    pub destination: Url,
+    pub message: String,
+}
+
+#[derive(PartialEq)]
 enum ActiveView {
     Browser,
     Mail,
     Chat,
     Composer,
+    Certificates,
 }
 
 struct Breeze {
@@ -180,119 +280,455 @@ struct Breeze {
     current_url: Url,
     /// The plaintext response from the server for this page
     page_content: String,
-    content_handlers: ContentHandlers,
+    /// The response exactly as received, for saving the page
+    page_data: Vec<u8>,
+    document: Document,
     navigation_hint: Cell<Option<NavigationHint>>,
     reset_scroll_pos: bool,
+    /// Offset to move the page to on the next frame, instead of the top
+    restore_scroll: Option<Vec2>,
+    /// How far the current page is scrolled
+    scroll_offset: Vec2,
     nav_job: Option<NavigationJob>,
     input_request: Option<InputRequest>,
+    pending_redirect: Option<PendingRedirect>,
+    certificate_request: Option<CertificateRequest>,
+    /// Where a saved page went, or why it couldn't be saved
+    save_notice: Option<String>,
+    slow_down: Option<SlowDown>,
+    nps_composer: Option<NpsComposer>,
     show_about_window: Arc<AtomicBool>,
     status_text: RefCell<String>,
     active_view: ActiveView,
+    mail: Mail,
+    chat: Chat,
+    composer: Composer,
+    certificates: Certificates,
     profiles: Vec<Profile>,
     should_update_profiles: bool,
+    theme: Theme,
+    themes: Vec<Theme>,
+    theme_changed: bool,
+    show_protocol_badges: bool,
+    /// Settings as being edited, applied whenever they differ from the stored ones
+    settings: Settings,
+    /// When the settings were last changed, while waiting for editing to pause to save them
+    settings_changed: Option<Instant>,
+    find: Find,
+    bindings: Bindings,
+    link_hints: LinkHints,
+    focus_url_bar: bool,
+    pending_scroll: Option<Action>,
 }
 
 impl Breeze {
     fn new(starting_url: String) -> Self {
-        let starting_url = Url::from_str(&starting_url).unwrap();
+        let starting_url = Url::from_str(&starting_url).unwrap_or_else(|e| {
+            println!("Invalid starting URL {}: {}", starting_url, e);
+            Url::from_str(settings::DEFAULT_HOME_PAGE).unwrap()
+        });
         let profiles = get_all_profiles().unwrap();
+        let settings = settings::settings();
+        let themes = Theme::all();
+        let theme = themes
+            .iter()
+            .find(|theme| theme.name == settings.theme)
+            .cloned()
+            .unwrap_or_default();
+        let mut mail = Mail::default();
+        mail.listen(&settings, false);
         Self {
             url: Cell::new(starting_url.to_string()),
             current_url: starting_url.clone(),
             page_content: "".to_string(),
-            content_handlers: Default::default(),
+            page_data: Vec::new(),
+            document: Document::default(),
             navigation_hint: Cell::new(Some(NavigationHint {
                 url: starting_url.to_string(),
                 protocol: Protocol::from_url(&starting_url),
                 add_to_history: true,
             })),
             reset_scroll_pos: false,
+            restore_scroll: None,
+            scroll_offset: Vec2::ZERO,
             nav_job: None,
             input_request: None,
+            pending_redirect: None,
+            certificate_request: None,
+            save_notice: None,
+            slow_down: None,
+            nps_composer: None,
             show_about_window: Arc::new(AtomicBool::new(false)),
             status_text: RefCell::new("".to_string()),
             active_view: ActiveView::Browser,
+            mail,
+            chat: Chat::new(),
+            composer: Composer::default(),
+            certificates: Certificates::default(),
             profiles,
             should_update_profiles: false,
+            theme,
+            themes,
+            theme_changed: true,
+            show_protocol_badges: false,
+            settings,
+            settings_changed: None,
+            find: Find::default(),
+            bindings: Bindings::load(),
+            link_hints: LinkHints::default(),
+            focus_url_bar: false,
+            pending_scroll: None,
+        }
+    }
+
+    fn go_back(&mut self) {
+        history::save_page_state(self.page_state());
+        if let Some(entry) = history::back() {
+            self.navigate_history(entry);
+        }
+    }
+
+    fn go_forward(&mut self) {
+        history::save_page_state(self.page_state());
+        if let Some(entry) = history::forward() {
+            self.navigate_history(entry);
+        }
+    }
+
+    fn page_state(&self) -> PageState {
+        PageState {
+            scroll_offset: self.scroll_offset,
+            find: self.find.selection(),
+        }
+    }
+
+    // Pages are shown as they were left, straight from the cache if visited recently
+    fn navigate_history(&mut self, entry: HistoryEntry) {
+        self.url.set(entry.url.to_string());
+        let Some(cached) = cache::get(&entry.url) else {
+            // Don't let a page still loading from before pick up this entry's state
+            self.nav_job = None;
+            self.navigate(Some(entry.protocol), false);
+            if let Some(job) = &mut self.nav_job {
+                job.restore = Some(entry.state);
+            }
+            return;
+        };
+        self.current_url = entry.url;
+        mark_visited(&self.current_url);
+        let protocol = Protocol::from_url(&self.current_url);
+        let plaintext =
+            entry.protocol == Protocol::Plaintext || is_plaintext_url(&self.current_url, protocol);
+        let response = ServerResponse {
+            content: cached.content,
+            status: ServerStatus::success(protocol, cached.content_type),
+            identified: false,
+        };
+        let mut job = NavigationJob::new(Promise::from_ready(Ok(response)), plaintext, protocol);
+        job.cached = true;
+        job.restore = Some(entry.state);
+        self.nav_job.replace(job);
+    }
+
+    fn reload(&mut self) {
+        // Keep whatever hint the page was first opened with, e.g. plaintext for .txt links
+        let hint = current_entry()
+            .filter(|entry| entry.url == self.current_url)
+            .map(|entry| entry.protocol);
+        let state = self.page_state();
+        self.url.set(self.current_url.to_string());
+        self.nav_job = None;
+        self.navigate(hint, false);
+        if let Some(job) = &mut self.nav_job {
+            job.restore = Some(state);
+        }
+    }
+
+    // Throws away the cached copy and any remembered move, and starts again from the top of
+    // the page
+    fn hard_reload(&mut self) {
+        cache::remove(&self.current_url);
+        redirect::forget(&self.current_url);
+        self.reload();
+        self.reset_scroll_pos = true;
+    }
+
+    fn perform(&mut self, action: Action) {
+        match action {
+            Action::Back => self.go_back(),
+            Action::Forward => self.go_forward(),
+            Action::Reload => self.reload(),
+            Action::HardReload => self.hard_reload(),
+            Action::FocusUrlBar => self.focus_url_bar = true,
+            Action::ScrollTop => self.reset_scroll_pos = true,
+            Action::ScrollDown
+            | Action::ScrollUp
+            | Action::PageDown
+            | Action::PageUp
+            | Action::ScrollBottom => self.pending_scroll = Some(action),
+            Action::LinkHints => self.link_hints.start(),
         }
     }
 
     // Validate URL before updating the currently active page content
     fn navigate(&mut self, protocol_hint: Option<Protocol>, should_add_entry: bool) {
+        // Going anywhere, even the same page again, replaces a pending retry
+        self.slow_down = None;
+        // Some schemes accept submissions rather than serve pages, so open the matching composer
+        let destination = Url::from_str(self.url.get_mut()).unwrap();
+        let is_submission = match Protocol::from_url(&destination) {
+            Protocol::Nps => {
+                self.nps_composer = Some(NpsComposer {
+                    destination,
+                    message: String::new(),
+                });
+                true
+            }
+            Protocol::Misfin => {
+                let address = destination.as_str().trim_start_matches("misfin://");
+                self.mail.compose_to(address.to_string());
+                self.active_view = ActiveView::Mail;
+                true
+            }
+            Protocol::Titan => {
+                self.composer.publish_to(&destination);
+                self.active_view = ActiveView::Composer;
+                true
+            }
+            _ => false,
+        };
+        if is_submission {
+            self.url.set(self.current_url.to_string());
+            return;
+        }
+
+        // Pages known to have moved for good are fetched from where they are now
+        if let Some(moved) = Url::from_str(self.url.get_mut())
+            .ok()
+            .and_then(|url| redirect::remembered(&url))
+        {
+            self.url.set(moved.to_string());
+        }
+
         if should_add_entry {
+            history::save_page_state(self.page_state());
+            // New pages start from the top, unlike ones returned to
+            self.reset_scroll_pos = true;
             println!("{}", self.url.get_mut());
             let protocol = protocol_hint.unwrap_or(Protocol::from_url(&self.current_url));
             add_entry(Url::from_str(self.url.get_mut()).unwrap(), protocol);
         }
         self.current_url = Url::from_str(self.url.get_mut()).unwrap();
+        // Built-in pages are drawn by the browser rather than fetched
+        if self.current_url.scheme() == "breeze" {
+            self.nav_job = None;
+            if self.current_url.as_str() != SETTINGS_URL {
+                let msg = format!("There is no built-in page at {}", self.current_url);
+                self.document = Document::plain(&msg);
+            }
+            return;
+        }
         let protocol = Protocol::from_url(&self.current_url);
         if protocol == Protocol::Unknown {
             self.page_content = "Invalid URL".to_string();
             return;
         }
-
-        let current_url = self.current_url.to_string();
-        let hostname = self.current_url.host_str().expect("Hostname is empty!");
-        let mut path = self.current_url.path().to_string();
-        if path.is_empty() {
-            path = "/".to_string();
+        mark_visited(&self.current_url);
+        let plaintext = protocol_hint.is_some_and(|p| p == Protocol::Plaintext);
+        if protocol == Protocol::File {
+            let url = self.current_url.clone();
+            let promise = Promise::spawn_thread("file", move || fetch_file(&url));
+            self.nav_job
+                .replace(NavigationJob::new(promise, plaintext, protocol));
+            return;
         }
-        let query = if let Some(q) = self.current_url.query() {
-            &format!("\t{}", q)
-        } else {
-            ""
-        };
-        let plaintext = protocol_hint.is_some_and(|p| p == Protocol::Plaintext)
-            || current_url.ends_with(".txt");
-        let (request_body, ssl) = match protocol {
-            Protocol::Finger => (path.strip_prefix("/").unwrap_or(&path).to_string(), false),
-            Protocol::Gemini => (current_url, true),
-            Protocol::Gopher(ssl) => (format!("{}{}", path, query), ssl),
-            Protocol::Guppy => (current_url, false),
-            Protocol::Nex => (path, false),
-            Protocol::Scorpion => (format!("R {}", current_url), false),
-            Protocol::Scroll => (format!("{} {}", current_url, "en"), true),
-            Protocol::Spartan => {
-                let query = if let Some(q) = self.current_url.query() {
-                    &format!("{}\n{}", q.len(), q)
-                } else {
-                    "0"
-                };
-                (format!("{} {} {}", hostname, path, query), false)
-            }
-            Protocol::TextProtocol => (current_url, false),
-            _ => unreachable!(),
-        };
+
+        let plaintext = plaintext || is_plaintext_url(&self.current_url, protocol);
+        let (request_body, ssl) = request_for(&self.current_url, protocol);
         let url = self.current_url.clone();
         let promise =
             Promise::spawn_thread("net", move || fetch(&url, &request_body, ssl, protocol));
         self.nav_job
             .replace(NavigationJob::new(promise, plaintext, protocol));
     }
+
+    // Follows a redirect from the current page, unless it loops, runs too long or needs confirming
+    fn redirect(&mut self, target: &str, permanent: bool, mut chain: Vec<Url>) {
+        println!("Redirecting to: {}", target);
+        let target = redirect::resolve(&self.current_url, target).and_then(|target| {
+            chain.push(self.current_url.clone());
+            redirect::check_chain(&chain, &target, self.settings.max_redirects).map(|_| target)
+        });
+        match target {
+            Ok(target) => match redirect::confirmation_reason(&self.current_url, &target) {
+                Some(reason) => {
+                    self.pending_redirect = Some(PendingRedirect {
+                        target,
+                        chain,
+                        permanent,
+                        reason,
+                    })
+                }
+                None => self.follow_redirect(target, chain, permanent),
+            },
+            Err(msg) => self.document = Document::plain(&msg),
+        }
+    }
+
+    fn follow_redirect(&mut self, target: Url, chain: Vec<Url>, permanent: bool) {
+        if permanent {
+            redirect::remember(&self.current_url, &target);
+        }
+        // The page that redirected is replaced rather than kept, so going back skips over it
+        let protocol = Protocol::from_url(&target);
+        history::replace_current_entry(target.clone(), protocol);
+        self.url.set(target.to_string());
+        self.navigate(Some(protocol), false);
+        if let Some(job) = &mut self.nav_job {
+            job.redirects = chain;
+        }
+    }
+
+    // Shows where a declined redirect leads, so it can still be followed by hand
+    fn decline_redirect(&mut self, target: &Url) {
+        self.document = Document {
+            blocks: vec![
+                Block::text(&format!("The redirect to {} was not followed.", target)),
+                Block::Link(Link::new(target.as_str(), "Follow it anyway")),
+            ],
+            ..Document::default()
+        };
+    }
+
+    fn go_home(&mut self) {
+        self.url.set(self.settings.home_page.clone());
+        self.navigate(None, true);
+    }
+
+    // Saves the page as it was received, next to anything already saved under the same name
+    fn save_page(&self) -> Result<PathBuf, String> {
+        let dir = self
+            .settings
+            .download_dir()
+            .ok_or("There is no downloads directory, choose one in the settings")?;
+        fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        let name = download_name(&self.current_url);
+        let mut path = dir.join(&name);
+        let mut copy = 1;
+        while path.exists() {
+            let stem = Path::new(&name).file_stem().unwrap_or_default();
+            let mut copy_name = format!("{} ({})", stem.to_string_lossy(), copy);
+            if let Some(extension) = Path::new(&name).extension() {
+                copy_name = format!("{}.{}", copy_name, extension.to_string_lossy());
+            }
+            path = dir.join(copy_name);
+            copy += 1;
+        }
+        fs::write(&path, &self.page_data).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(path)
+    }
+
+    // Puts into effect whatever changed in the edited settings
+    fn apply_settings(&mut self, ctx: &Context) {
+        let previous = settings::settings();
+        settings::set_settings(self.settings.clone());
+        if previous.theme != self.settings.theme {
+            if let Some(theme) = self.themes.iter().find(|t| t.name == self.settings.theme) {
+                self.theme = theme.clone();
+                self.theme_changed = true;
+            }
+        }
+        if previous.proportional_font != self.settings.proportional_font
+            || previous.monospace_font != self.settings.monospace_font
+        {
+            ctx.set_fonts(load_fonts(&self.settings));
+        }
+        if previous.window_width != self.settings.window_width
+            || previous.window_height != self.settings.window_height
+        {
+            ctx.send_viewport_cmd(ViewportCommand::InnerSize(vec2(
+                self.settings.window_width,
+                self.settings.window_height,
+            )));
+        }
+        self.mail.listen(&self.settings, false);
+    }
+
+    // Sends a message to an NPS endpoint, the reply is displayed like a Nex page
+    fn submit_nps(&mut self, destination: Url, message: &str) {
+        let mut path = destination.path().to_string();
+        if path.is_empty() {
+            path = "/".to_string();
+        }
+        // The message follows the path line and ends with a line holding a single dot
+        let message = format!("{}\r\n.\r\n", message.trim_end());
+        self.current_url = destination;
+        self.url.set(self.current_url.to_string());
+        let url = self.current_url.clone();
+        let promise = Promise::spawn_thread("net", move || {
+            upload(&url, &path, message.as_bytes(), false, Protocol::Nps)
+        });
+        self.nav_job
+            .replace(NavigationJob::new(promise, false, Protocol::Nps));
+        self.reset_scroll_pos = true;
+    }
 }
 
 impl eframe::App for Breeze {
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        if self.settings_changed.is_some() {
+            settings::save_settings();
+        }
+    }
+
     fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) {
         ctx.request_repaint();
+        if self.theme_changed {
+            ctx.set_visuals(self.theme.visuals());
+            self.theme_changed = false;
+        }
+        let find_shortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::F);
+        if self.active_view == ActiveView::Browser
+            && ctx.input_mut(|i| i.consume_shortcut(&find_shortcut))
+        {
+            self.find.open();
+        }
+        if self.active_view == ActiveView::Browser {
+            self.link_hints.handle_input(ctx);
+            for action in self.bindings.pressed(ctx) {
+                self.perform(action);
+            }
+        }
         TopBottomPanel::top("menubar").show(ctx, |ui| {
             menu::bar(ui, |ui| {
                 ui.menu_button("File", |ui| {
+                    if ui.button("Save page").clicked() {
+                        self.save_notice = Some(match self.save_page() {
+                            Ok(path) => format!("Saved to {}", path.display()),
+                            Err(e) => format!("Failed to save page: {}", e),
+                        });
+                        ui.close_menu();
+                    }
+                    if ui.button("Settings").clicked() {
+                        self.navigation_hint.set(Some(NavigationHint {
+                            url: SETTINGS_URL.to_string(),
+                            protocol: Protocol::Unknown,
+                            add_to_history: true,
+                        }));
+                        ui.close_menu();
+                    }
+                    ui.separator();
                     if ui.button("Quit").clicked() {
                         exit(0);
                     }
                 });
                 ui.menu_button("Profile", |ui| {
-                    if ui.button("New").clicked() {
-                        self.input_request = Some(InputRequest {
-                            prompt: "Enter the name you would like to use for this profile"
-                                .to_string(),
-                            sensitive: false,
-                            destination: "breeze://profile/new".to_string(),
-                            user_input: String::new(),
-                            completed: false,
-                        })
+                    if ui.button("Manage certificates").clicked() {
+                        self.active_view = ActiveView::Certificates;
+                        ui.close_menu();
                     }
                     ui.separator();
+                    ui.label("Send mail as");
                     for profile in &self.profiles {
                         ui.horizontal(|ui| {
                             if profile.active {
@@ -307,6 +743,52 @@ impl eframe::App for Breeze {
                         });
                     }
                 });
+                ui.menu_button("View", |ui| {
+                    let find = Button::new("Find in Page")
+                        .shortcut_text(ctx.format_shortcut(&find_shortcut));
+                    if ui.add(find).clicked() {
+                        self.active_view = ActiveView::Browser;
+                        self.find.open();
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    ui.label("Theme");
+                    for theme in &self.themes {
+                        if ui
+                            .radio(self.theme.name == theme.name, &theme.name)
+                            .clicked()
+                        {
+                            self.settings.theme = theme.name.clone();
+                        }
+                    }
+                    ui.separator();
+                    ui.checkbox(&mut self.show_protocol_badges, "Show protocol badges");
+                    ui.separator();
+                    ui.add(
+                        DragValue::new(&mut self.settings.max_redirects)
+                            .range(0..=20)
+                            .prefix("Redirect limit: "),
+                    );
+                    ui.separator();
+                    ui.menu_button("Cache", |ui| {
+                        ui.add(
+                            DragValue::new(&mut self.settings.cache.size_mb)
+                                .range(0..=4096)
+                                .prefix("Size: ")
+                                .suffix(" MB"),
+                        );
+                        ui.add(
+                            DragValue::new(&mut self.settings.cache.expiry_minutes)
+                                .range(0..=10080)
+                                .prefix("Expiry: ")
+                                .suffix(" min"),
+                        );
+                        if ui.button("Clear cache").clicked() {
+                            cache::clear();
+                            ui.close_menu();
+                        }
+                    });
+                });
                 ui.menu_button("Help", |ui| {
                     if ui.button("About Breeze").clicked() {
                         self.show_about_window.store(true, Ordering::Relaxed);
@@ -336,12 +818,26 @@ impl eframe::App for Breeze {
             });
         });
         self.status_text.borrow_mut().clear();
+        self.chat.poll();
         CentralPanel::default().show(ctx, |ui| match self.active_view {
             ActiveView::Browser => render_browser(ui, ctx, self),
             ActiveView::Mail => render_mail(ui, ctx, self),
             ActiveView::Chat => render_chat(ui, ctx, self),
             ActiveView::Composer => render_composer(ui, ctx, self),
+            ActiveView::Certificates => render_certificates(ui, ctx, self),
         });
+        render_unlock(ctx, self);
+        if self.settings != settings::settings() {
+            self.apply_settings(ctx);
+            self.settings_changed = Some(Instant::now());
+        }
+        if self
+            .settings_changed
+            .is_some_and(|changed| changed.elapsed() >= settings::SAVE_DELAY)
+        {
+            settings::save_settings();
+            self.settings_changed = None;
+        }
 
         if self.show_about_window.load(Ordering::Relaxed) {
             let show_about_window = self.show_about_window.clone();
@@ -370,9 +866,10 @@ impl eframe::App for Breeze {
         }
 
         if let Some(hint) = self.navigation_hint.take() {
+            // Links can be followed from other views, e.g. in mail, so bring the browser forward
+            self.active_view = ActiveView::Browser;
             self.url.set(hint.url);
             self.navigate(Some(hint.protocol), hint.add_to_history);
-            self.reset_scroll_pos = true;
         }
 
         if self.input_request.as_ref().is_some_and(|r| r.completed) {
@@ -382,14 +879,46 @@ impl eframe::App for Breeze {
         if self.should_update_profiles {
             self.should_update_profiles = false;
             self.profiles = get_all_profiles().unwrap();
+            // Mail is received as the active profile, which may have been unlocked or changed
+            self.mail.listen(&self.settings, true);
+        }
+
+        if self
+            .slow_down
+            .as_ref()
+            .is_some_and(|slow_down| Instant::now() >= slow_down.retry_at)
+        {
+            let slow_down = self.slow_down.take().unwrap();
+            if slow_down.url == self.current_url && self.nav_job.is_none() {
+                self.reload();
+            }
         }
 
         let Some(job) = &self.nav_job else { return };
+        // Followed once the finished job has been let go of
+        let mut redirect = None;
+        let mut certificate_problem = None;
         match job.nav_promise.ready() {
             Some(Ok(response)) => {
                 // TODO: This feels like it's getting very verbose,
                 // see if there's a way to better work with these statuses
                 self.page_content = String::from_utf8_lossy(&response.content).to_string();
+                self.page_data = response.content.clone();
+                if let Some(content_type) = response.status.success_type() {
+                    // Local files are always read fresh
+                    if !job.cached && job.protocol != Protocol::File {
+                        cache::insert(
+                            &self.current_url,
+                            content_type,
+                            &response.content,
+                            response.identified,
+                        );
+                    }
+                    if let Some(state) = job.restore.clone() {
+                        self.restore_scroll = Some(state.scroll_offset);
+                        self.find.restore(state.find);
+                    }
+                }
                 match &response.status {
                     // Input
                     ServerStatus::Gemini(GeminiStatus::InputExpected(prompt, sensitive)) => {
@@ -402,93 +931,151 @@ impl eframe::App for Breeze {
                             completed: false,
                         });
                     }
+                    ServerStatus::Guppy(GuppyStatus::Input(prompt)) => {
+                        history::remove_latest_entry();
+                        self.input_request = Some(InputRequest {
+                            prompt: prompt.clone(),
+                            sensitive: false,
+                            destination: self.current_url.to_string(),
+                            user_input: "".to_string(),
+                            completed: false,
+                        });
+                    }
                     // Success
-                    ServerStatus::Gemini(GeminiStatus::Success(_content_type))
-                    | ServerStatus::Spartan(SpartanStatus::Success(_content_type))
-                    | ServerStatus::TextProtocol(TextProtocolStatus::OK(_content_type))
-                    | ServerStatus::_Success(_content_type) => {
-                        self.content_handlers.parse_content(
+                    // Local files are typed by their extension
+                    ServerStatus::_Success(content_type) if job.protocol == Protocol::File => {
+                        self.document = parse_content_type(
                             &response.content,
+                            content_type,
                             job.plaintext,
                             job.protocol,
                         );
                     }
-                    ServerStatus::Scorpion(ScorpionStatus::OK) => {
-                        self.content_handlers.parse_content(
+                    // Pages from other protocols fetched through a Gemini proxy
+                    ServerStatus::Gemini(GeminiStatus::Success(content_type))
+                        if !matches!(job.protocol, Protocol::Gemini | Protocol::Scroll) =>
+                    {
+                        self.document = parse_content_type(
+                            &response.content,
+                            content_type,
+                            job.plaintext,
+                            job.protocol,
+                        );
+                    }
+                    ServerStatus::Gemini(GeminiStatus::Success(_content_type))
+                    | ServerStatus::Guppy(GuppyStatus::Success(_content_type))
+                    | ServerStatus::Spartan(SpartanStatus::Success(_content_type))
+                    | ServerStatus::_Success(_content_type) => {
+                        self.document =
+                            parse_content(&response.content, job.plaintext, job.protocol);
+                    }
+                    ServerStatus::TextProtocol(TextProtocolStatus::OK(content_type)) => {
+                        self.document = parse_content_type(
                             &response.content,
+                            content_type,
                             job.plaintext,
                             job.protocol,
                         );
                     }
+                    ServerStatus::Scorpion(ScorpionStatus::OK) => {
+                        self.document =
+                            parse_content(&response.content, job.plaintext, job.protocol);
+                    }
                     // Redirect
                     ServerStatus::Gemini(GeminiStatus::TemporaryRedirect(url))
-                    | ServerStatus::Gemini(GeminiStatus::PermanentRedirect(url))
+                    | ServerStatus::Guppy(GuppyStatus::Redirect(url))
                     | ServerStatus::Spartan(SpartanStatus::Redirect(url))
-                    | ServerStatus::TextProtocol(TextProtocolStatus::Redirect(url))
                     | ServerStatus::Scorpion(ScorpionStatus::TemporaryRedirect(url))
+                    | ServerStatus::TextProtocol(TextProtocolStatus::Redirect(url)) => {
+                        redirect = Some((url.clone(), false, job.redirects.clone()));
+                    }
+                    ServerStatus::Gemini(GeminiStatus::PermanentRedirect(url))
                     | ServerStatus::Scorpion(ScorpionStatus::PermanentRedirect(url)) => {
-                        println!("Redirecting to: {}", url);
-                        let mut current_url = self.current_url.clone();
-                        if url.starts_with("/") {
-                            current_url.set_path(&url);
-                        } else {
-                            current_url.join(&url).unwrap();
-                        }
-                        self.url.set(current_url.to_string());
-                        self.navigation_hint.set(Some(NavigationHint {
-                            url: current_url.to_string(),
-                            protocol: job.protocol,
-                            add_to_history: true,
-                        }));
+                        redirect = Some((url.clone(), true, job.redirects.clone()));
                     }
                     // Failure
                     ServerStatus::Gemini(GeminiStatus::TemporaryFailure(data))
                     | ServerStatus::Gemini(GeminiStatus::ServerUnavailable(data))
                     | ServerStatus::Gemini(GeminiStatus::CGIError(data))
-                    | ServerStatus::Gemini(GeminiStatus::ProxyError(data))
-                    | ServerStatus::Gemini(GeminiStatus::SlowDown(data))
                     | ServerStatus::Gemini(GeminiStatus::PermanentFailure(data))
                     | ServerStatus::Gemini(GeminiStatus::NotFound(data))
                     | ServerStatus::Gemini(GeminiStatus::Gone(data))
-                    | ServerStatus::Gemini(GeminiStatus::ProxyRequestRefused(data))
                     | ServerStatus::Gemini(GeminiStatus::BadRequest(data))
                     | ServerStatus::Scorpion(ScorpionStatus::PermanentError(data))
                     | ServerStatus::Scorpion(ScorpionStatus::FileNotFound(data))
                     | ServerStatus::Scorpion(ScorpionStatus::FileRemoved(data))
+                    | ServerStatus::Guppy(GuppyStatus::Error(data))
                     | ServerStatus::Spartan(SpartanStatus::ClientError(data))
                     | ServerStatus::Spartan(SpartanStatus::ServerError(data))
                     | ServerStatus::TextProtocol(TextProtocolStatus::NOK(data)) => {
                         let msg = format!("The requested resource could not be found.\n\nAdditional information:\n\n{}", data);
-                        self.content_handlers
-                            .parse_content(msg.as_bytes(), true, job.protocol);
+                        self.document = parse_content(msg.as_bytes(), true, job.protocol);
+                        // A page that moved here may have moved again since
+                        redirect::forget(&self.current_url);
+                    }
+                    // Slow down, the page is fetched again once the server is ready for it
+                    ServerStatus::Gemini(GeminiStatus::SlowDown(meta))
+                    | ServerStatus::Scorpion(ScorpionStatus::SlowDown(meta)) => {
+                        let msg = "The server asked for requests to slow down. The page will load once it is ready.";
+                        self.document = Document::plain(msg);
+                        self.slow_down = Some(SlowDown {
+                            url: self.current_url.clone(),
+                            retry_at: Instant::now() + ratelimit::slow_down_duration(meta),
+                        });
+                    }
+                    // Proxies
+                    ServerStatus::Gemini(GeminiStatus::ProxyError(data)) => {
+                        let msg = format!("The proxy could not fetch {} from its server.\n\nAdditional information:\n\n{}", self.current_url, data);
+                        self.document = parse_content(msg.as_bytes(), true, job.protocol);
+                    }
+                    ServerStatus::Scorpion(ScorpionStatus::ProxyError) => {
+                        let msg = format!(
+                            "The proxy could not fetch {} from its server.",
+                            self.current_url
+                        );
+                        self.document = parse_content(msg.as_bytes(), true, job.protocol);
+                    }
+                    ServerStatus::Gemini(GeminiStatus::ProxyRequestRefused(data)) => {
+                        let msg = format!("The server refused to fetch {}, it does not serve that host or act as a proxy for it. If you meant to use a Gemini proxy, check the proxy settings under File > Settings.\n\nAdditional information:\n\n{}", self.current_url, data);
+                        self.document = parse_content(msg.as_bytes(), true, job.protocol);
+                    }
+                    ServerStatus::Scorpion(ScorpionStatus::ProxyRequestRefused) => {
+                        let msg = format!("The server refused to fetch {}, it does not serve that host or act as a proxy for it.", self.current_url);
+                        self.document = parse_content(msg.as_bytes(), true, job.protocol);
                     }
                     // Certificates
                     ServerStatus::Gemini(GeminiStatus::RequiresClientCertificate) => {
-                        let msg = format!("The requested resource requires a client certificate. You can create one by clicking \"New\" in the Profiles tab.");
-                        self.content_handlers
-                            .parse_content(msg.as_bytes(), true, job.protocol);
+                        let msg = "The requested resource requires a client certificate. You can create one and choose where it is used under Profile > Manage certificates.";
+                        self.document = parse_content(msg.as_bytes(), true, job.protocol);
+                        certificate_problem = Some(CertificateProblem::Required);
                     }
                     ServerStatus::Gemini(GeminiStatus::CertificateNotAuthorized) => {
-                        let msg = format!(
-                            "Your client certificate is not authorized to access this resource"
-                        );
-                        self.content_handlers
-                            .parse_content(msg.as_bytes(), true, job.protocol);
+                        let msg =
+                            "Your client certificate is not authorized to access this resource";
+                        self.document = parse_content(msg.as_bytes(), true, job.protocol);
+                        certificate_problem = Some(CertificateProblem::NotAuthorized);
                     }
                     ServerStatus::Gemini(GeminiStatus::CertificateNotValid) => {
-                        let msg = format!("The requested resource is unavailable as your client certificate is invalid. Check to see if your certificate has expired.");
-                        self.content_handlers
-                            .parse_content(msg.as_bytes(), true, job.protocol);
+                        let msg = "The requested resource is unavailable as your client certificate is invalid. Check to see if your certificate has expired.";
+                        self.document = parse_content(msg.as_bytes(), true, job.protocol);
+                        certificate_problem = Some(CertificateProblem::NotValid);
                     }
                     _ => {
                         println!("Unhandled status: {:?}", response.status);
                     }
                 }
                 self.nav_job = None;
+                if let Some((target, permanent, chain)) = redirect {
+                    self.redirect(&target, permanent, chain);
+                }
+                if let Some(problem) = certificate_problem {
+                    let url = self.current_url.clone();
+                    self.certificate_request = Some(CertificateRequest::new(url, problem));
+                }
             }
             Some(Err(error)) => {
-                self.content_handlers
-                    .parse_content(error.as_bytes(), true, job.protocol);
+                self.document = parse_content(error.as_bytes(), true, job.protocol);
+                redirect::forget(&self.current_url);
                 self.nav_job = None;
             }
             None => ctx.set_cursor_icon(CursorIcon::Wait),
@@ -502,17 +1089,24 @@ fn render_browser(ui: &mut eframe::egui::Ui, ctx: &Context, breeze: &mut Breeze)
         if ui.add_enabled(can_go_back(), Button::new("←")).clicked()
             || ui.input(|input| input.pointer.button_clicked(PointerButton::Extra1))
         {
-            if let Some(entry) = history::back() {
-                breeze.url.set(entry.url.to_string());
-                breeze.navigate(Some(entry.protocol), false);
-            }
+            breeze.go_back();
         }
         if ui.add_enabled(can_go_forward(), Button::new("→")).clicked()
             || ui.input(|input| input.pointer.button_clicked(PointerButton::Extra2))
         {
-            if let Some(entry) = history::forward() {
-                breeze.url.set(entry.url.to_string());
-                breeze.navigate(Some(entry.protocol), false);
+            breeze.go_forward();
+        }
+        if ui.button("⌂").on_hover_text("Home").clicked() {
+            breeze.go_home();
+        }
+        let reload = ui
+            .button("⟳")
+            .on_hover_text("Reload (Shift+click to also clear the cached copy)");
+        if reload.clicked() {
+            if ui.input(|input| input.modifiers.shift) {
+                breeze.hard_reload();
+            } else {
+                breeze.reload();
             }
         }
         // Layout trick to have address bar render last and fill available remaining space
@@ -524,6 +1118,10 @@ fn render_browser(ui: &mut eframe::egui::Ui, ctx: &Context, breeze: &mut Breeze)
                 ui.available_size(),
                 TextEdit::singleline(breeze.url.get_mut()),
             );
+            if breeze.focus_url_bar {
+                url.request_focus();
+                breeze.focus_url_bar = false;
+            }
             if url.lost_focus() && ui.input(|input| input.key_pressed(Key::Enter)) {
                 breeze.navigate(None, true);
             }
@@ -532,28 +1130,60 @@ fn render_browser(ui: &mut eframe::egui::Ui, ctx: &Context, breeze: &mut Breeze)
     // Extend separator out a bit to match menubar separator
     ui.add(Separator::default().grow(8.0));
 
+    if breeze.find.open {
+        breeze.find.render_bar(ui);
+        ui.add(Separator::default().grow(8.0));
+    }
+
+    if let Some(slow_down) = &breeze.slow_down {
+        let remaining = slow_down.retry_at.saturating_duration_since(Instant::now());
+        let mut cancel = false;
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "The server asked to slow down, trying again in {}s",
+                remaining.as_secs_f32().ceil()
+            ));
+            cancel = ui.button("Cancel").clicked();
+        });
+        ui.add(Separator::default().grow(8.0));
+        if cancel {
+            breeze.slow_down = None;
+        }
+    }
+
     // Page content
     let mut scroll_area = ScrollArea::both().auto_shrink(false);
-    if breeze.reset_scroll_pos {
+    if let Some(offset) = breeze.restore_scroll.take() {
+        scroll_area = scroll_area.scroll_offset(offset);
+    } else if breeze.reset_scroll_pos {
         scroll_area = scroll_area.scroll_offset([0.0, 0.0].into());
         breeze.reset_scroll_pos = false;
     }
-    scroll_area.show(ui, |ui| {
+    let output = scroll_area.show(ui, |ui| {
         Frame::new().inner_margin(vec2(64.0, 16.0)).show(ui, |ui| {
-            // TODO: This should eventually check content type instead of protocol
-            let protocol = Protocol::from_url(&breeze.current_url);
-            match protocol {
-                Protocol::Finger => breeze.content_handlers.finger.render_page(ui, breeze),
-                Protocol::Gemini | Protocol::Spartan | Protocol::Guppy | Protocol::Scroll => {
-                    breeze.content_handlers.gemtext.render_page(ui, breeze);
-                }
-                Protocol::Gopher(_) => breeze.content_handlers.gopher.render_page(ui, breeze),
-                Protocol::Nex => breeze.content_handlers.nex.render_page(ui, breeze),
-                Protocol::Scorpion => breeze.content_handlers.scorpion.render_page(ui, breeze),
-                _ => breeze.content_handlers.plaintext.render_page(ui, breeze),
+            if breeze.theme.content_width > 0.0 {
+                ui.set_max_width(breeze.theme.content_width);
             }
-        })
+            if breeze.current_url.as_str() == SETTINGS_URL {
+                render_settings(ui, ctx, breeze);
+            } else {
+                breeze.find.begin();
+                render_document(ui, breeze, &breeze.document);
+                breeze.find.end();
+            }
+        });
+        let page = ui.clip_rect().height() * 0.9;
+        match breeze.pending_scroll.take() {
+            Some(Action::ScrollDown) => ui.scroll_with_delta(vec2(0.0, -48.0)),
+            Some(Action::ScrollUp) => ui.scroll_with_delta(vec2(0.0, 48.0)),
+            Some(Action::PageDown) => ui.scroll_with_delta(vec2(0.0, -page)),
+            Some(Action::PageUp) => ui.scroll_with_delta(vec2(0.0, page)),
+            Some(Action::ScrollBottom) => ui.scroll_to_cursor(Some(Align::BOTTOM)),
+            _ => {}
+        }
     });
+    breeze.scroll_offset = output.state.offset;
+    breeze.link_hints.end(ctx);
 
     if let Some(input_request) = &mut breeze.input_request {
         Modal::new("input".into()).show(ctx, |ui| {
@@ -562,35 +1192,80 @@ fn render_browser(ui: &mut eframe::egui::Ui, ctx: &Context, breeze: &mut Breeze)
                 .password(input_request.sensitive);
             ui.add(text_edit);
             if ui.button("Submit").clicked() {
-                if input_request.destination == "breeze://profile/new" {
-                    let profile = Profile::new(input_request.user_input.clone());
-                    let currently_active = breeze.profiles.iter_mut().find(|p| p.active);
-                    if let Some(currently_active) = currently_active {
-                        currently_active.active = false;
-                    }
-                    breeze.profiles.push(profile);
-                } else {
-                    let url = format!("{}?{}", input_request.destination, input_request.user_input);
-                    breeze.navigation_hint.set(Some(NavigationHint {
-                        url,
-                        protocol: Protocol::from_str(&input_request.destination),
-                        add_to_history: true,
-                    }));
-                }
+                let url = format!("{}?{}", input_request.destination, input_request.user_input);
+                breeze.navigation_hint.set(Some(NavigationHint {
+                    url,
+                    protocol: Protocol::from_str(&input_request.destination),
+                    add_to_history: true,
+                }));
                 input_request.completed = true;
             }
         });
     }
-}
 
-fn render_mail(ui: &mut Ui, _ctx: &Context, _breeze: &mut Breeze) {
-    ui.label("This is a placeholder for the mail tab, which will act as a client for Misfin and the NPS.");
-}
+    if let Some(pending) = &breeze.pending_redirect {
+        let mut follow = None;
+        Modal::new("redirect".into()).show(ctx, |ui| {
+            ui.label(format!("This page redirects to {}", pending.target));
+            ui.label(format!("The redirect {}. Follow it?", pending.reason));
+            ui.horizontal(|ui| {
+                if ui.button("Follow").clicked() {
+                    follow = Some(true);
+                }
+                if ui.button("Cancel").clicked() {
+                    follow = Some(false);
+                }
+            });
+        });
+        if let Some(follow) = follow {
+            let pending = breeze.pending_redirect.take().unwrap();
+            if follow {
+                breeze.follow_redirect(pending.target, pending.chain, pending.permanent);
+            } else {
+                breeze.decline_redirect(&pending.target);
+            }
+        }
+    }
 
-fn render_chat(ui: &mut Ui, _ctx: &Context, _breeze: &mut Breeze) {
-    ui.label("This is a placeholder for the chat tab, which will feature a built-in IRC client.");
-}
+    render_certificate_request(ctx, breeze);
+
+    if let Some(notice) = &breeze.save_notice {
+        let mut close = false;
+        Modal::new("save_notice".into()).show(ctx, |ui| {
+            ui.label(notice);
+            close = ui.button("OK").clicked();
+        });
+        if close {
+            breeze.save_notice = None;
+        }
+    }
 
-fn render_composer(ui: &mut Ui, _ctx: &Context, _breeze: &mut Breeze) {
-    ui.label("This is a placeholder for the composer tab, which will allow users to compose Gemtext, Gophermaps, and so on.");
+    let mut nps_submission = None;
+    if let Some(composer) = &mut breeze.nps_composer {
+        let mut close = false;
+        Modal::new("nps".into()).show(ctx, |ui| {
+            ui.label(format!("Post to {}", composer.destination));
+            ui.add(
+                TextEdit::multiline(&mut composer.message)
+                    .code_editor()
+                    .desired_rows(12)
+                    .desired_width(480.0),
+            );
+            ui.horizontal(|ui| {
+                if ui.button("Submit").clicked() {
+                    nps_submission = Some((composer.destination.clone(), composer.message.clone()));
+                    close = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    close = true;
+                }
+            });
+        });
+        if close {
+            breeze.nps_composer = None;
+        }
+    }
+    if let Some((destination, message)) = nps_submission {
+        breeze.submit_nps(destination, &message);
+    }
 }
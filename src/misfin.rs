@@ -0,0 +1,162 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use native_tls::{Identity, TlsAcceptor};
+
+// Misfin(B) requests, including the address and trailing CRLF, are capped at 2048 bytes
+pub const MAX_REQUEST_LENGTH: usize = 2048;
+// How often the listener checks whether it has been stopped while nobody is connecting
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(200);
+// Senders get this long to finish their request before they are hung up on
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Stores a delivered message, given the mailbox it was addressed to and the gemmail text
+pub type Deliver = dyn Fn(&str, &str) -> Result<(), String> + Send + Sync;
+
+/// Splits a request into the `mailbox@host` it is addressed to and the gemmail message
+pub fn parse_request(request: &str) -> Result<(String, String), String> {
+    let request = request
+        .strip_suffix("\r\n")
+        .ok_or("The request does not end with a line break")?;
+    let (address, message) = request
+        .strip_prefix("misfin://")
+        .and_then(|rest| rest.split_once(' '))
+        .ok_or("Requests must be a misfin:// address followed by the message")?;
+    match address.split_once('@') {
+        Some((mailbox, host)) if !mailbox.is_empty() && !host.is_empty() => {
+            Ok((address.to_string(), message.to_string()))
+        }
+        _ => Err(format!("{} is not a mailbox@host address", address)),
+    }
+}
+
+/// Listens for Misfin mail on a port of this machine, until dropped
+pub struct MailServer {
+    pub port: u16,
+    stop: Arc<AtomicBool>,
+    listener: Option<JoinHandle<()>>,
+    delivered: Receiver<String>,
+}
+
+impl MailServer {
+    /// Starts accepting mail, presenting `identity` to senders and answering with the
+    /// fingerprint of its certificate as Misfin expects
+    pub fn start(
+        port: u16,
+        identity: Identity,
+        fingerprint: String,
+        deliver: Arc<Deliver>,
+    ) -> Result<Self, String> {
+        let acceptor = TlsAcceptor::new(identity).map_err(|e| e.to_string())?;
+        let listener = TcpListener::bind(("0.0.0.0", port))
+            .map_err(|e| format!("Can't listen on port {}: {}", port, e))?;
+        let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+        // Polled rather than blocking, so the listener notices when it is stopped
+        listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let (sender, delivered) = channel();
+
+        let stopped = stop.clone();
+        let listener = thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let acceptor = acceptor.clone();
+                        let fingerprint = fingerprint.clone();
+                        let deliver = deliver.clone();
+                        let sender = sender.clone();
+                        thread::spawn(move || {
+                            if let Err(e) =
+                                receive(stream, &acceptor, &fingerprint, &*deliver, &sender)
+                            {
+                                println!("Failed to receive mail: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(ACCEPT_INTERVAL),
+                    Err(e) => {
+                        println!("Failed to accept a mail connection: {}", e);
+                        thread::sleep(ACCEPT_INTERVAL);
+                    }
+                }
+            }
+        });
+        Ok(Self {
+            port,
+            stop,
+            listener: Some(listener),
+            delivered,
+        })
+    }
+
+    /// The addresses of messages delivered since last asked
+    pub fn poll(&self) -> Vec<String> {
+        self.delivered.try_iter().collect()
+    }
+}
+
+impl Drop for MailServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // Waits for the port to be let go of, so that a new listener can take it straight away
+        if let Some(listener) = self.listener.take() {
+            let _ = listener.join();
+        }
+    }
+}
+
+// Reads one request and answers it. Senders can't be asked for their certificate here, so
+// who a message is from is only what the message itself says.
+fn receive(
+    stream: TcpStream,
+    acceptor: &TlsAcceptor,
+    fingerprint: &str,
+    deliver: &Deliver,
+    delivered: &Sender<String>,
+) -> Result<(), String> {
+    stream
+        .set_nonblocking(false)
+        .and_then(|_| stream.set_read_timeout(Some(REQUEST_TIMEOUT)))
+        .and_then(|_| stream.set_write_timeout(Some(REQUEST_TIMEOUT)))
+        .map_err(|e| e.to_string())?;
+    let mut stream = acceptor.accept(stream).map_err(|e| e.to_string())?;
+
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.ends_with(b"\r\n") && request.len() <= MAX_REQUEST_LENGTH {
+        match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => request.extend_from_slice(&buf[..len]),
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+
+    let response = if request.len() > MAX_REQUEST_LENGTH {
+        format!(
+            "59 Requests are limited to {} bytes\r\n",
+            MAX_REQUEST_LENGTH
+        )
+    } else {
+        match parse_request(&String::from_utf8_lossy(&request)) {
+            Ok((address, message)) => match deliver(&address, &message) {
+                Ok(()) => {
+                    let _ = delivered.send(address);
+                    format!("20 {}\r\n", fingerprint)
+                }
+                Err(e) => format!("40 {}\r\n", e),
+            },
+            Err(e) => format!("59 {}\r\n", e),
+        }
+    };
+    stream
+        .write_all(response.as_bytes())
+        .and_then(|_| stream.flush())
+        .map_err(|e| e.to_string())?;
+    let _ = stream.shutdown();
+    Ok(())
+}
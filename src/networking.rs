@@ -1,41 +1,26 @@
-use std::collections::HashMap;
-use std::io::{BufRead, Read, Write};
-use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
-use std::sync::{LazyLock, Mutex};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, UdpSocket};
+use std::sync::{mpsc, LazyLock, Mutex};
+use std::thread;
+use std::time::Duration;
 
-use eframe::egui::TextBuffer;
-use native_tls::TlsConnector;
+use native_tls::{Identity, TlsConnector};
 use url::Url;
 
-use crate::db::get_default_profile;
+use crate::db::{get_bindings, get_default_profile, get_profile};
+use crate::dns;
+use crate::handlers::finger::finger_query;
 use crate::handlers::Protocol;
-
-#[allow(dead_code)]
-static DNS_CACHE: LazyLock<Mutex<HashMap<String, Vec<SocketAddr>>>> =
-    LazyLock::new(|| Mutex::new(HashMap::new()));
-
-#[allow(dead_code)]
-pub fn is_hostname_valid(hostname: &str) -> bool {
-    let mut cache = DNS_CACHE.lock().expect("Failed to lock DNS cache");
-    if cache.contains_key(hostname) {
-        cache.get(hostname).is_some_and(|addrs| !addrs.is_empty())
-    } else {
-        // println!("First time seeing host: {}", hostname);
-        match hostname.to_socket_addrs() {
-            Ok(addrs) => {
-                cache.insert(hostname.to_string(), addrs.collect::<Vec<_>>());
-                true
-            }
-            Err(_) => {
-                cache.insert(hostname.to_string(), Vec::new());
-                false
-            }
-        }
-    }
-}
+use crate::profile::binding_for;
+use crate::proxy::{socks5_handshake, split_address, Proxy};
+use crate::ratelimit;
+use crate::settings;
 
 // TODO: Gopher+
 
+/// How long to wait on one address before also trying the next
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
 #[derive(Debug)]
 pub enum GeminiStatus {
     InputExpected(String, bool),
@@ -57,11 +42,14 @@ pub enum GeminiStatus {
     CertificateNotValid,
 }
 
-impl From<&str> for GeminiStatus {
-    fn from(status: &str) -> Self {
-        let (code, data) = status.split_once(' ').unwrap();
+impl TryFrom<&str> for GeminiStatus {
+    type Error = String;
+
+    fn try_from(status: &str) -> Result<Self, String> {
+        // Some servers leave out the space when there's no meta
+        let (code, data) = status.split_once(' ').unwrap_or((status, ""));
         let data = data.to_string();
-        match code {
+        Ok(match code {
             "10" => GeminiStatus::InputExpected(data, false),
             "11" => GeminiStatus::InputExpected(data, true),
             // Most of these extra ones are for scroll
@@ -83,8 +71,8 @@ impl From<&str> for GeminiStatus {
             "60" => GeminiStatus::RequiresClientCertificate,
             "61" => GeminiStatus::CertificateNotAuthorized,
             "62" => GeminiStatus::CertificateNotValid,
-            _ => unreachable!("Unknown Gemini status code: {}", code),
-        }
+            _ => return Err(format!("Unknown Gemini status code: {}", code)),
+        })
     }
 }
 
@@ -96,15 +84,40 @@ pub enum SpartanStatus {
     ServerError(String),
 }
 
-impl From<&str> for SpartanStatus {
-    fn from(status: &str) -> Self {
-        let (code, data) = status.split_once(' ').unwrap();
-        match code {
+impl TryFrom<&str> for SpartanStatus {
+    type Error = String;
+
+    fn try_from(status: &str) -> Result<Self, String> {
+        let (code, data) = status.split_once(' ').unwrap_or((status, ""));
+        Ok(match code {
             "2" => SpartanStatus::Success(data.to_string()),
             "3" => SpartanStatus::Redirect(data.to_string()),
             "4" => SpartanStatus::ClientError(data.to_string()),
             "5" => SpartanStatus::ServerError(data.to_string()),
-            _ => unreachable!("Unknown Spartan status code: {}", code),
+            _ => return Err(format!("Unknown Spartan status code: {}", code)),
+        })
+    }
+}
+
+/// Guppy answers with a sequence number of 6 or more when sending a page, the lower numbers
+/// are single packet responses
+#[derive(Debug)]
+pub enum GuppyStatus {
+    Success(String),
+    Input(String),
+    Redirect(String),
+    Error(String),
+}
+
+impl GuppyStatus {
+    // Reads a single packet response from the sequence number and the rest of its header
+    fn from_header(seq: u32, meta: &str) -> Option<Self> {
+        let meta = meta.to_string();
+        match seq {
+            1 => Some(GuppyStatus::Input(meta)),
+            3 => Some(GuppyStatus::Redirect(meta)),
+            4 => Some(GuppyStatus::Error(meta)),
+            _ => None,
         }
     }
 }
@@ -121,7 +134,7 @@ pub enum ScorpionStatus {
     DownForMaintenance,
     DynamicFileError,
     ProxyError,
-    SlowDown,
+    SlowDown(String),
     TemporarilyLockedFile,
     PermanentError(String),
     FileNotFound(String),
@@ -142,12 +155,14 @@ pub enum ScorpionStatus {
     AcceptedOther,
 }
 
-impl From<&str> for ScorpionStatus {
-    fn from(status: &str) -> Self {
+impl TryFrom<&str> for ScorpionStatus {
+    type Error = String;
+
+    fn try_from(status: &str) -> Result<Self, String> {
         // TODO: Handle additional data with codes
-        let (code, data) = status.split_once(' ').unwrap();
+        let (code, data) = status.split_once(' ').unwrap_or((status, ""));
         let data = data.to_string();
-        match code {
+        Ok(match code {
             "00" => ScorpionStatus::Interactive,
             "10" => ScorpionStatus::InputRequired,
             "20" => ScorpionStatus::OK,
@@ -158,7 +173,7 @@ impl From<&str> for ScorpionStatus {
             "41" => ScorpionStatus::DownForMaintenance,
             "42" => ScorpionStatus::DynamicFileError,
             "43" => ScorpionStatus::ProxyError,
-            "44" => ScorpionStatus::SlowDown,
+            "44" => ScorpionStatus::SlowDown(data),
             "45" => ScorpionStatus::TemporarilyLockedFile,
             "50" => ScorpionStatus::PermanentError(data),
             "51" => ScorpionStatus::FileNotFound(data),
@@ -177,8 +192,8 @@ impl From<&str> for ScorpionStatus {
             "80" => ScorpionStatus::AcceptedNewFile,
             "81" => ScorpionStatus::AcceptedFileModified,
             "82" => ScorpionStatus::AcceptedOther,
-            _ => unreachable!("Unknown Scorpion status code: {}", status),
-        }
+            _ => return Err(format!("Unknown Scorpion status code: {}", code)),
+        })
     }
 }
 
@@ -190,31 +205,144 @@ pub enum TextProtocolStatus {
     NOK(String),
 }
 
-impl From<&str> for TextProtocolStatus {
-    fn from(status: &str) -> Self {
-        let (code, data) = status.split_once(' ').unwrap();
-        match code {
+impl TryFrom<&str> for TextProtocolStatus {
+    type Error = String;
+
+    fn try_from(status: &str) -> Result<Self, String> {
+        let (code, data) = status.split_once(' ').unwrap_or((status, ""));
+        Ok(match code {
             "20" => TextProtocolStatus::OK(data.to_string()),
             "30" => TextProtocolStatus::Redirect(data.to_string()),
             "40" => TextProtocolStatus::NOK(data.to_string()),
-            _ => unreachable!("Unknown Text Protocol status code: {}", code),
-        }
+            _ => return Err(format!("Unknown Text Protocol status code: {}", code)),
+        })
     }
 }
 
 #[derive(Debug)]
 pub enum ServerStatus {
     Gemini(GeminiStatus),
+    Guppy(GuppyStatus),
     Scorpion(ScorpionStatus),
     Spartan(SpartanStatus),
     TextProtocol(TextProtocolStatus),
     _Success(String),
 }
 
+impl ServerStatus {
+    /// The content type of a successful response, or None for any other status
+    pub fn success_type(&self) -> Option<&str> {
+        match self {
+            ServerStatus::Gemini(GeminiStatus::Success(content_type))
+            | ServerStatus::Guppy(GuppyStatus::Success(content_type))
+            | ServerStatus::Spartan(SpartanStatus::Success(content_type))
+            | ServerStatus::TextProtocol(TextProtocolStatus::OK(content_type))
+            | ServerStatus::_Success(content_type) => Some(content_type),
+            ServerStatus::Scorpion(ScorpionStatus::OK) => Some(""),
+            _ => None,
+        }
+    }
+
+    /// How long the server asked to be left alone for, if it asked to slow down
+    pub fn slow_down(&self) -> Option<&str> {
+        match self {
+            ServerStatus::Gemini(GeminiStatus::SlowDown(meta))
+            | ServerStatus::Scorpion(ScorpionStatus::SlowDown(meta)) => Some(meta),
+            _ => None,
+        }
+    }
+
+    /// The status a protocol's server would have sent along with a successful response
+    pub fn success(protocol: Protocol, content_type: String) -> ServerStatus {
+        match protocol {
+            Protocol::Gemini | Protocol::Scroll => {
+                ServerStatus::Gemini(GeminiStatus::Success(content_type))
+            }
+            Protocol::Guppy => ServerStatus::Guppy(GuppyStatus::Success(content_type)),
+            Protocol::Scorpion => ServerStatus::Scorpion(ScorpionStatus::OK),
+            Protocol::Spartan => ServerStatus::Spartan(SpartanStatus::Success(content_type)),
+            Protocol::TextProtocol => {
+                ServerStatus::TextProtocol(TextProtocolStatus::OK(content_type))
+            }
+            _ => ServerStatus::_Success(content_type),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ServerResponse {
     pub content: Vec<u8>,
     pub status: ServerStatus,
+    /// Whether a client certificate was presented for it
+    pub identified: bool,
+}
+
+// Profile to present everywhere instead of the bound ones, set when fetching from the command line
+static CLIENT_PROFILE: LazyLock<Mutex<Option<String>>> = LazyLock::new(|| Mutex::new(None));
+
+pub fn set_client_profile(name: Option<String>) {
+    *CLIENT_PROFILE
+        .lock()
+        .expect("Failed to lock client profile") = name;
+}
+
+// Only the profile bound to a URL is presented there, except that Misfin always sends as the
+// active profile since that is who the mail is from
+fn identity_for(url: &Url, protocol: Protocol) -> Option<Identity> {
+    let name = match CLIENT_PROFILE.lock().unwrap().clone() {
+        Some(name) => name,
+        None if protocol == Protocol::Misfin => {
+            return get_default_profile().ok().map(|p| p.identity)
+        }
+        None => {
+            let bindings = get_bindings().unwrap_or_default();
+            binding_for(&bindings, url)?.profile.clone()
+        }
+    };
+    get_profile(&name).ok().map(|p| p.identity)
+}
+
+/// Builds the request line for a URL and whether it should be sent over TLS
+pub fn request_for(url: &Url, protocol: Protocol) -> (String, bool) {
+    let current_url = url.to_string();
+    // URLs without a host are turned away once sent
+    let hostname = url.host_str().unwrap_or_default();
+    let mut path = url.path().to_string();
+    if path.is_empty() {
+        path = "/".to_string();
+    }
+    let query = if let Some(q) = url.query() {
+        &format!("\t{}", q)
+    } else {
+        ""
+    };
+    match protocol {
+        Protocol::Finger => (finger_query(url), false),
+        Protocol::Gemini => (current_url, true),
+        Protocol::Gopher(ssl) => (format!("{}{}", path, query), ssl),
+        Protocol::Guppy => (current_url, false),
+        Protocol::Nex => (path, false),
+        Protocol::Scorpion => (format!("R {}", current_url), false),
+        Protocol::Scroll => {
+            let languages = settings::settings().languages.join(",");
+            (format!("{} {}", current_url, languages), true)
+        }
+        Protocol::Spartan => {
+            let query = if let Some(q) = url.query() {
+                &format!("{}\r\n{}", q.len(), q)
+            } else {
+                "0"
+            };
+            (format!("{} {} {}", hostname, path, query), false)
+        }
+        Protocol::TextProtocol => (current_url, false),
+        _ => unreachable!(),
+    }
+}
+
+// Text files linked from menus and pages are shown as-is rather than parsed
+pub fn is_plaintext_url(url: &Url, protocol: Protocol) -> bool {
+    url.as_str().ends_with(".txt") && protocol != Protocol::TextProtocol
 }
 
 pub fn fetch(
@@ -223,164 +351,417 @@ pub fn fetch(
     ssl: bool,
     protocol: Protocol,
 ) -> Result<ServerResponse, String> {
-    let hostname = url.host_str().expect("Hostname is empty!");
-    let port = url.port().unwrap_or(match protocol {
+    let proxy = settings::settings().proxy(url.scheme());
+    fetch_via(url, request_body, ssl, protocol, proxy.as_ref())
+}
+
+/// Fetches through the given proxy, or directly without one, rather than as configured
+pub fn fetch_via(
+    url: &Url,
+    request_body: &str,
+    ssl: bool,
+    protocol: Protocol,
+    proxy: Option<&Proxy>,
+) -> Result<ServerResponse, String> {
+    send_request(url, request_body, &[], ssl, protocol, proxy)
+}
+
+/// Sends a request line followed by raw data, as Titan and Spartan uploads and NPS posts do
+pub fn upload(
+    url: &Url,
+    request_line: &str,
+    data: &[u8],
+    ssl: bool,
+    protocol: Protocol,
+) -> Result<ServerResponse, String> {
+    let proxy = settings::settings().proxy(url.scheme());
+    send_request(url, request_line, data, ssl, protocol, proxy.as_ref())
+}
+
+pub fn default_port(protocol: Protocol) -> u16 {
+    match protocol {
         Protocol::Finger => 79,
         Protocol::Gemini => 1965,
         Protocol::Gopher(_) => 70,
         Protocol::Guppy => 6775,
+        Protocol::Misfin => 1958,
         Protocol::Nex => 1900,
+        Protocol::Nps => 1915,
         Protocol::Scorpion => 1517,
         Protocol::Scroll => 5699,
         Protocol::Spartan => 300,
         Protocol::TextProtocol => 1961,
+        Protocol::Titan => 1965,
         _ => 0,
-    });
-    let url = format!("{}:{}", hostname, port);
-    let request = format!("{}\r\n", request_body);
-    let mut buf = Vec::new();
-
-    if protocol == Protocol::Guppy {
-        return fetch_udp(hostname, port, request_body, ssl);
     }
+}
 
-    if ssl {
-        let identity = match get_default_profile() {
-            Ok(p) => Some(p.identity),
-            Err(_) => None,
+// Connects to whichever of the host's addresses answers first. Attempts are started a moment
+// apart, alternating IPv6 and IPv4, so one unreachable address doesn't hold up the rest.
+fn connect(host: &str, port: u16) -> Result<TcpStream, String> {
+    let settings = settings::settings();
+    let addrs: Vec<SocketAddr> = dns::connection_order(&dns::resolve(host)?)
+        .into_iter()
+        .map(|ip| SocketAddr::new(ip, port))
+        .collect();
+    let (sender, receiver) = mpsc::channel();
+    let mut errors = Vec::new();
+    let mut started = 0;
+    let mut pending = 0;
+    loop {
+        if let Some(&addr) = addrs.get(started) {
+            let sender = sender.clone();
+            let timeout = settings.connect_timeout();
+            thread::spawn(move || {
+                let _ = sender.send((addr, TcpStream::connect_timeout(&addr, timeout)));
+            });
+            started += 1;
+            pending += 1;
+        }
+        let attempt = if started < addrs.len() {
+            receiver.recv_timeout(ATTEMPT_DELAY).ok()
+        } else if pending > 0 {
+            receiver.recv().ok()
+        } else {
+            break;
         };
+        match attempt {
+            Some((_, Ok(stream))) => {
+                stream
+                    .set_read_timeout(Some(settings.read_timeout()))
+                    .and_then(|_| stream.set_write_timeout(Some(settings.read_timeout())))
+                    .map_err(|e| e.to_string())?;
+                return Ok(stream);
+            }
+            Some((addr, Err(e))) => {
+                pending -= 1;
+                errors.push(format!("{}: {}", addr, e));
+            }
+            // Still waiting, so give the next address a go alongside
+            None => {}
+        }
+    }
+    // The host may have moved, so look it up again next time
+    dns::forget(host);
+    Err(format!(
+        "Failed to connect to {}:{}\n\n{}",
+        host,
+        port,
+        errors.join("\n")
+    ))
+}
+
+/// Connects to a server, through a SOCKS5 proxy if one is given. Gemini proxies only fetch
+/// whole pages, so there's no connecting through them.
+pub fn connect_through(host: &str, port: u16, proxy: Option<&Proxy>) -> Result<TcpStream, String> {
+    match proxy {
+        Some(Proxy::Socks5 { address }) => {
+            let (proxy_host, proxy_port) = split_address(address, 1080)?;
+            let mut stream = connect(&proxy_host, proxy_port)?;
+            socks5_handshake(&mut stream, host, port)?;
+            Ok(stream)
+        }
+        Some(Proxy::Gemini { .. }) => {
+            Err(format!("A Gemini proxy can't connect to {}:{}", host, port))
+        }
+        None => connect(host, port),
+    }
+}
+
+fn send_request(
+    url: &Url,
+    request_body: &str,
+    data: &[u8],
+    ssl: bool,
+    protocol: Protocol,
+    proxy: Option<&Proxy>,
+) -> Result<ServerResponse, String> {
+    let hostname = url
+        .host_str()
+        .filter(|host| !host.is_empty())
+        .ok_or(format!("{} has no host to fetch from", url))?;
+    let port = url
+        .port()
+        .or_else(|| settings::settings().port(url.scheme()))
+        .unwrap_or(default_port(protocol));
+    let mut request = format!("{}\r\n", request_body).into_bytes();
+    request.extend_from_slice(data);
+
+    match proxy {
+        Some(Proxy::Socks5 { .. }) if protocol == Protocol::Guppy => {
+            return Err("Guppy runs over UDP, which can't be sent through a SOCKS5 proxy".into())
+        }
+        None if hostname.ends_with(".onion") => {
+            return Err(format!(
+                "{} is a Tor onion service, which can only be reached through Tor. Set a SOCKS5 proxy for {} under File > Settings.",
+                hostname,
+                url.scheme()
+            ))
+        }
+        _ => {}
+    }
+
+    // Every request to a server waits its turn, however it gets there
+    let server = format!("{}:{}", hostname, port);
+    ratelimit::wait_turn(&server);
+    let response = match proxy {
+        Some(Proxy::Gemini { address }) => fetch_through_gemini(url, address, data),
+        _ if protocol == Protocol::Guppy => fetch_udp(hostname, port, request_body, ssl),
+        _ => fetch_tcp(url, port, &request, ssl, protocol, proxy),
+    }?;
+    if let Some(meta) = response.status.slow_down() {
+        ratelimit::back_off(&server, ratelimit::slow_down_duration(meta));
+    }
+    Ok(response)
+}
+
+fn fetch_tcp(
+    url: &Url,
+    port: u16,
+    request: &[u8],
+    ssl: bool,
+    protocol: Protocol,
+    proxy: Option<&Proxy>,
+) -> Result<ServerResponse, String> {
+    let hostname = url
+        .host_str()
+        .filter(|host| !host.is_empty())
+        .ok_or(format!("{} has no host to fetch from", url))?;
+    let stream = connect_through(hostname, port, proxy)?;
+    let identity = if ssl {
+        identity_for(url, protocol)
+    } else {
+        None
+    };
+    let identified = identity.is_some();
+    let buf = exchange(stream, ssl.then_some(hostname), identity, request)?;
+    let mut response = parse_server_response(&buf, protocol)?;
+    response.identified = identified;
+    Ok(response)
+}
+
+// Sends the request and reads the whole response, over TLS when given the server's name
+fn exchange(
+    stream: TcpStream,
+    tls_host: Option<&str>,
+    identity: Option<Identity>,
+    request: &[u8],
+) -> Result<Vec<u8>, String> {
+    let mut buf = Vec::new();
+    if let Some(host) = tls_host {
         let mut connector_builder = TlsConnector::builder();
         connector_builder.danger_accept_invalid_certs(true);
         if let Some(identity) = identity {
             connector_builder.identity(identity);
         }
         let connector = connector_builder.build().unwrap();
-
-        let stream =
-            TcpStream::connect(format!("{}:{}", hostname, port)).map_err(|e| e.to_string())?;
-        let mut stream = connector
-            .connect(hostname, stream)
-            .map_err(|e| e.to_string())?;
-
-        stream
-            .write_all(request.as_bytes())
-            .map_err(|e| e.to_string())?;
+        let mut stream = connector.connect(host, stream).map_err(|e| e.to_string())?;
+        stream.write_all(request).map_err(|e| e.to_string())?;
         stream.read_to_end(&mut buf).map_err(|e| e.to_string())?;
-        Ok(parse_server_response(&buf, protocol))
-    } else if let Ok(mut stream) = TcpStream::connect(url) {
-        stream
-            .write_all(request.as_bytes())
-            .map_err(|e| e.to_string())?;
-        stream.read_to_end(&mut buf).map_err(|e| e.to_string())?;
-        Ok(parse_server_response(&buf, protocol))
     } else {
-        let msg = format!("Failed to connect to hostname: {}", hostname);
-        Err(msg)
+        let mut stream = stream;
+        stream.write_all(request).map_err(|e| e.to_string())?;
+        stream.read_to_end(&mut buf).map_err(|e| e.to_string())?;
     }
+    Ok(buf)
 }
 
+// The proxy is sent the page's full URL and answers like any Gemini server. Certificates bound
+// to the page aren't presented, the proxy isn't the site they were made for.
+fn fetch_through_gemini(url: &Url, proxy: &str, data: &[u8]) -> Result<ServerResponse, String> {
+    let (host, port) = split_address(proxy, 1965)?;
+    let stream = connect(&host, port)?;
+    let mut request = format!("{}\r\n", url).into_bytes();
+    request.extend_from_slice(data);
+    let tls_host = host.trim_start_matches('[').trim_end_matches(']');
+    let buf = exchange(stream, Some(tls_host), None, &request)?;
+    parse_server_response(&buf, Protocol::Gemini)
+}
+
+/// Reads a local file, or lists a directory as a Gemtext menu, for previewing capsule content
+pub fn fetch_file(url: &Url) -> Result<ServerResponse, String> {
+    let path = url
+        .to_file_path()
+        .map_err(|_| format!("Invalid file URL: {}", url))?;
+    if path.is_dir() {
+        let mut entries = std::fs::read_dir(&path)
+            .map_err(|e| e.to_string())?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| (entry.path().is_dir(), entry.path()))
+            .collect::<Vec<_>>();
+        // Directories first, then files, each alphabetically
+        entries.sort_by(|(a_dir, a), (b_dir, b)| b_dir.cmp(a_dir).then(a.cmp(b)));
+
+        let mut listing = format!("# Index of {}\n\n", path.display());
+        if let Some(parent) = path.parent() {
+            if let Ok(parent_url) = Url::from_directory_path(parent) {
+                listing.push_str(&format!("=> {} ..\n", parent_url));
+            }
+        }
+        for (is_dir, entry) in entries {
+            let name = entry.file_name().unwrap_or_default().to_string_lossy();
+            let link = if is_dir {
+                Url::from_directory_path(&entry).map(|url| (url, format!("{}/", name)))
+            } else {
+                Url::from_file_path(&entry).map(|url| (url, name.to_string()))
+            };
+            if let Ok((url, label)) = link {
+                listing.push_str(&format!("=> {} {}\n", url, label));
+            }
+        }
+        return Ok(ServerResponse {
+            content: listing.into_bytes(),
+            status: ServerStatus::_Success("text/gemini".to_string()),
+            identified: false,
+        });
+    }
+
+    let content = std::fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase();
+    let content_type = if name.ends_with(".gmi") || name.ends_with(".gemini") {
+        "text/gemini"
+    } else if name == "gophermap" || name.ends_with(".gophermap") || name.ends_with(".gph") {
+        "application/gopher-menu"
+    } else if name.ends_with(".nex") {
+        "text/x-nex"
+    } else {
+        "text/plain"
+    };
+    Ok(ServerResponse {
+        content,
+        status: ServerStatus::_Success(content_type.to_string()),
+        identified: false,
+    })
+}
+
+// Guppy sends a page as numbered packets, each acknowledged by the client. The first carries
+// the content type and an empty one marks the end.
 fn fetch_udp(
     hostname: &str,
     port: u16,
     selector: &str,
     _ssl: bool,
 ) -> Result<ServerResponse, String> {
-    let url = format!("{}:{}", hostname, port);
-    let request = format!("{}\r\n", selector);
-    let mut data = Vec::new();
-    let mut completed = false;
-
-    if let Ok(socket) = UdpSocket::bind("0.0.0.0:0") {
-        let addrs = url.to_socket_addrs().unwrap().collect::<Vec<_>>();
-        socket.connect(addrs.first().unwrap()).unwrap();
-        socket.send(request.as_bytes()).map_err(|e| e.to_string())?;
-        while !completed {
-            // This code is all Guppy-specific, as it's the only protocol using UDP instead of TCP
-            let mut buf = [0; 16384];
-            socket.recv(buf.as_mut()).map_err(|e| e.to_string())?;
-            let first_line = buf.lines().next().unwrap().unwrap();
-            let server_info = first_line.split(' ').collect::<Vec<_>>();
-            if let Some(content_type) = server_info.get(1) {
-                data.extend_from_slice(format!("{}\n", content_type).as_bytes());
-            }
-            if let Some(seq) = server_info.first() {
-                let data_lines = buf.lines().skip(1).collect::<Vec<_>>();
-                if data_lines.len() == 1 {
-                    completed = true;
-                } else {
-                    let newbuf = buf
-                        .iter()
-                        .filter_map(|b| if *b != 0 { Some(*b) } else { None })
-                        .collect::<Vec<_>>();
-                    let string = String::from_utf8_lossy(&newbuf).to_string();
-                    let newstring = string.split_once("\n").unwrap().1;
-                    data.extend(newstring.as_bytes());
-                    socket
-                        .send(format!("{}\r\n", seq).as_bytes())
-                        .map_err(|e| e.to_string())?;
-                }
-            } else {
-                completed = true;
-            }
-        }
-        Ok(parse_server_response(&data, Protocol::Guppy))
+    let addr = SocketAddr::new(dns::resolve(hostname)?[0], port);
+    let local = if addr.is_ipv6() {
+        "[::]:0"
     } else {
-        Err(format!("Failed to connect to hostname: {}", hostname))
-    }
-}
+        "0.0.0.0:0"
+    };
+    let socket = UdpSocket::bind(local)
+        .map_err(|_| format!("Failed to connect to hostname: {}", hostname))?;
+    socket.connect(addr).map_err(|e| e.to_string())?;
+    // Give up rather than wait forever on a server that has gone quiet
+    socket
+        .set_read_timeout(Some(settings::settings().read_timeout()))
+        .map_err(|e| e.to_string())?;
+    socket
+        .send(format!("{}\r\n", selector).as_bytes())
+        .map_err(|e| e.to_string())?;
 
-fn parse_server_response(response: &[u8], protocol: Protocol) -> ServerResponse {
-    match protocol {
-        Protocol::Gemini | Protocol::Scroll => {
-            let response = String::from_utf8_lossy(response);
-            let (server_status, content) = response.split_once('\n').unwrap();
-            ServerResponse {
-                content: Vec::from(content),
-                status: ServerStatus::Gemini(GeminiStatus::from(server_status)),
+    let mut content_type = None;
+    let mut next_seq = 0;
+    let mut data = Vec::new();
+    let mut buf = [0; 16384];
+    loop {
+        let len = socket.recv(&mut buf).map_err(|e| e.to_string())?;
+        let packet = &buf[..len];
+        let header_end = packet
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or("Malformed Guppy packet: missing header")?;
+        let header = String::from_utf8_lossy(&packet[..header_end]);
+        let body = &packet[header_end + 2..];
+        let (seq, meta) = header.split_once(' ').unwrap_or((&header, ""));
+        let seq: u32 = seq
+            .parse()
+            .map_err(|_| format!("Malformed Guppy packet: bad sequence number {}", seq))?;
+
+        if content_type.is_none() {
+            // Input, redirects and errors come in a packet of their own that isn't acknowledged
+            if let Some(status) = GuppyStatus::from_header(seq, meta) {
+                return Ok(ServerResponse {
+                    content: Vec::new(),
+                    status: ServerStatus::Guppy(status),
+                    identified: false,
+                });
             }
-        }
-        Protocol::Guppy => {
-            let response = String::from_utf8_lossy(response);
-            let (content_type, content) = response.split_once('\n').unwrap();
-            ServerResponse {
-                content: Vec::from(content),
-                status: ServerStatus::_Success(content_type.to_string()),
+            if seq < 6 {
+                return Err(format!("Unknown Guppy status: {}", seq));
             }
-        }
-        Protocol::TextProtocol => {
-            let response = String::from_utf8_lossy(response);
-            let (server_status, content) = response.split_once('\n').unwrap();
-            ServerResponse {
-                content: Vec::from(content),
-                status: ServerStatus::TextProtocol(TextProtocolStatus::from(server_status)),
+            if meta.is_empty() {
+                return Err("Malformed Guppy response: missing content type".to_string());
             }
+            content_type = Some(meta.to_string());
+            next_seq = seq;
         }
-        Protocol::Scorpion => {
-            // Get status line from server response
-            let status_end = response.iter().position(|b| *b == b'\n').unwrap();
-            let status_line_bytes = &response[0..status_end];
-            let content_bytes = &response[status_end + 1..];
-            let status_line = String::from_utf8_lossy(status_line_bytes);
-
-            // Parse status line
-            let status = ScorpionStatus::from(status_line.as_str());
-
-            ServerResponse {
-                content: Vec::from(content_bytes),
-                status: ServerStatus::Scorpion(status),
-            }
+        // Packets from further ahead are left for the server to send again
+        if seq > next_seq {
+            continue;
         }
-        Protocol::Spartan => {
-            let response = String::from_utf8_lossy(response);
-            let (server_status, content) = response.split_once('\n').unwrap();
-            ServerResponse {
-                content: Vec::from(content),
-                status: ServerStatus::Spartan(SpartanStatus::from(server_status)),
-            }
+        socket
+            .send(format!("{}\r\n", seq).as_bytes())
+            .map_err(|e| e.to_string())?;
+        // Already have this one, the server missed our acknowledgement
+        if seq < next_seq {
+            continue;
         }
-        _ => ServerResponse {
-            content: response.to_owned(),
-            status: ServerStatus::_Success("text/plain".to_string()),
-        },
+        if body.is_empty() {
+            break;
+        }
+        data.extend_from_slice(body);
+        next_seq += 1;
     }
+    Ok(ServerResponse {
+        content: data,
+        status: ServerStatus::Guppy(GuppyStatus::Success(content_type.unwrap_or_default())),
+        identified: false,
+    })
+}
+
+// Splits off the status line, without its line ending
+fn split_status_line(response: &[u8]) -> Result<(String, &[u8]), String> {
+    let end = response
+        .iter()
+        .position(|b| *b == b'\n')
+        .ok_or("Malformed response: missing status line")?;
+    let status_line = String::from_utf8_lossy(&response[..end]);
+    Ok((status_line.trim_end().to_string(), &response[end + 1..]))
+}
+
+fn parse_server_response(response: &[u8], protocol: Protocol) -> Result<ServerResponse, String> {
+    let (status_line, content) = match protocol {
+        Protocol::Gemini
+        | Protocol::Misfin
+        | Protocol::Scroll
+        | Protocol::Titan
+        | Protocol::TextProtocol
+        | Protocol::Scorpion
+        | Protocol::Spartan => split_status_line(response)?,
+        // Everything else is just the content
+        _ => {
+            return Ok(ServerResponse {
+                content: response.to_owned(),
+                status: ServerStatus::_Success("text/plain".to_string()),
+                identified: false,
+            })
+        }
+    };
+    let status_line = status_line.as_str();
+    let status = match protocol {
+        Protocol::TextProtocol => ServerStatus::TextProtocol(status_line.try_into()?),
+        Protocol::Scorpion => ServerStatus::Scorpion(status_line.try_into()?),
+        Protocol::Spartan => ServerStatus::Spartan(status_line.try_into()?),
+        // Misfin and Titan replies use the same status codes as Gemini
+        _ => ServerStatus::Gemini(status_line.try_into()?),
+    };
+    Ok(ServerResponse {
+        content: content.to_vec(),
+        status,
+        identified: false,
+    })
 }
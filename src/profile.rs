@@ -1,49 +1,407 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+use base64::prelude::{Engine, BASE64_STANDARD};
 use native_tls::Identity;
-use rcgen::{CertificateParams, DistinguishedName, DnType};
+use p12_keystore::{Certificate, KeyStore, KeyStoreEntry, PrivateKeyChain};
+use pkcs8::{EncryptedPrivateKeyInfo, LineEnding, PrivateKeyInfo, SecretDocument};
+use rcgen::{CertificateParams, DistinguishedName, DnType, KeyPair, PKCS_ECDSA_P256_SHA256};
+use rsa::pkcs1::DecodeRsaPrivateKey;
 use rsa::pkcs8::EncodePrivateKey;
 use rsa::rand_core::OsRng;
 use rsa::RsaPrivateKey;
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+use url::Url;
+use x509_parser::pem::Pem;
+use x509_parser::public_key::PublicKey;
 
-use crate::db::new_profile;
+use crate::db::{get_stored_keys, new_profile, set_stored_keys, update_profile_certificate};
 
 pub struct Profile {
     pub name: String,
+    pub cert: String,
+    pub key: String,
     pub identity: Identity,
     pub active: bool,
 }
 
+#[derive(Clone, Copy, PartialEq)]
+pub enum KeyType {
+    Rsa2048,
+    Rsa4096,
+    EcdsaP256,
+}
+
+impl KeyType {
+    // Windows doesn't support P256 keys in their crypto APIs, so only RSA is offered there
+    // Relevant issue: https://github.com/sfackler/rust-native-tls/issues/233
+    pub fn available() -> &'static [KeyType] {
+        if cfg!(windows) {
+            &[KeyType::Rsa2048, KeyType::Rsa4096]
+        } else {
+            &[KeyType::EcdsaP256, KeyType::Rsa2048, KeyType::Rsa4096]
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        match self {
+            KeyType::Rsa2048 => "RSA 2048",
+            KeyType::Rsa4096 => "RSA 4096",
+            KeyType::EcdsaP256 => "ECDSA P-256",
+        }
+    }
+}
+
+/// What a certificate says about itself, for showing in the certificate manager
+pub struct CertificateDetails {
+    pub subject: String,
+    pub key_type: String,
+    pub expires: OffsetDateTime,
+}
+
+impl CertificateDetails {
+    pub fn expired(&self) -> bool {
+        self.expires < OffsetDateTime::now_utc()
+    }
+}
+
+/// A profile used on every URL under a host and path
+#[derive(Clone, Debug, PartialEq)]
+pub struct Binding {
+    pub profile: String,
+    pub host: String,
+    pub path: String,
+}
+
+impl Binding {
+    fn matches(&self, url: &Url) -> bool {
+        url.host_str() == Some(self.host.as_str()) && url.path().starts_with(&self.path)
+    }
+}
+
+/// The binding that applies to a URL, the most specific one if several do
+pub fn binding_for<'a>(bindings: &'a [Binding], url: &Url) -> Option<&'a Binding> {
+    bindings
+        .iter()
+        .filter(|binding| binding.matches(url))
+        .max_by_key(|binding| binding.path.len())
+}
+
 impl Profile {
-    pub fn new(name: String) -> Self {
-        let mut distinguished_name = DistinguishedName::new();
-        distinguished_name.push(DnType::CommonName, &name);
-
-        let mut params = CertificateParams::new(vec![]).unwrap();
-        params.distinguished_name = distinguished_name;
-        params.not_before = time::OffsetDateTime::now_utc();
-        params.not_after = time::OffsetDateTime::now_utc() + time::Duration::days(1825); // 5 years
-        params.is_ca = rcgen::IsCa::ExplicitNoCa;
-
-        // Normally we would use the default P256 keypair, but Windows doesn't support it
-        // in their crypto APIs so we use RSA instead.
-        // Relevant issue: https://github.com/sfackler/rust-native-tls/issues/233
-        let mut rng = OsRng;
-        let bits = 2048;
-        let private_key = RsaPrivateKey::new(&mut rng, bits).unwrap();
-        let private_key_der = private_key.to_pkcs8_der().unwrap();
-        let key_pair = rcgen::KeyPair::try_from(private_key_der.as_bytes()).unwrap();
-
-        let certificate = params.self_signed(&key_pair).unwrap();
-        let cert_pem = certificate.pem();
-        let key_pem = key_pair.serialize_pem();
-
-        let _ = new_profile(name.clone(), cert_pem.clone(), key_pem.clone());
-
-        let identity = Identity::from_pkcs8(cert_pem.as_bytes(), key_pem.as_bytes()).unwrap();
-
-        Self {
+    pub fn from_pem(name: String, cert: String, key: String, active: bool) -> Result<Self, String> {
+        let identity = Identity::from_pkcs8(cert.as_bytes(), key.as_bytes())
+            .map_err(|e| format!("Invalid certificate or key: {}", e))?;
+        Ok(Self {
             name,
+            cert,
+            key,
             identity,
-            active: true,
+            active,
+        })
+    }
+
+    /// Creates a self-signed certificate and saves it as a new profile
+    pub fn generate(name: String, key_type: KeyType, days: i64) -> Result<Self, String> {
+        let (cert, key) = self_signed(&name, key_type, days)?;
+        Self::save(name, cert, key)
+    }
+
+    /// Loads a profile from the database, decrypting its key with the passphrase if needed
+    pub fn from_stored(
+        name: String,
+        cert: String,
+        key: String,
+        encrypted: bool,
+        active: bool,
+    ) -> Result<Self, String> {
+        let key = if encrypted { unlocked_key(&key)? } else { key };
+        Self::from_pem(name, cert, key, active)
+    }
+
+    /// Replaces a profile's certificate and key with fresh ones, keeping its name and bindings
+    pub fn regenerate(name: String, key_type: KeyType, days: i64) -> Result<Self, String> {
+        let (cert, key) = self_signed(&name, key_type, days)?;
+        let (stored_key, encrypted) = key_to_store(&key)?;
+        update_profile_certificate(&name, &cert, &stored_key, encrypted)
+            .map_err(|e| format!("Failed to save profile: {}", e))?;
+        Self::from_pem(name, cert, key, false)
+    }
+
+    /// Saves an existing identity from a PEM file or PKCS#12 archive as a new profile
+    pub fn import(
+        name: String,
+        data: &[u8],
+        key_data: Option<&[u8]>,
+        password: &str,
+    ) -> Result<Self, String> {
+        let (cert, key) = if data.starts_with(b"-----") {
+            let mut pem = data.to_vec();
+            if let Some(key_data) = key_data {
+                pem.push(b'\n');
+                pem.extend_from_slice(key_data);
+            }
+            parse_pem(&pem)?
+        } else {
+            parse_pkcs12(data, password)?
+        };
+        Self::save(name, cert, key)
+    }
+
+    fn save(name: String, cert: String, key: String) -> Result<Self, String> {
+        let name = name.trim().to_string();
+        if name.is_empty() {
+            return Err("Profiles need a name".to_string());
         }
+        let profile = Self::from_pem(name, cert, key, false)?;
+        let (stored_key, encrypted) = key_to_store(&profile.key)?;
+        new_profile(&profile.name, &profile.cert, &stored_key, encrypted)
+            .map_err(|e| format!("Failed to save profile: {}", e))?;
+        Ok(profile)
     }
+
+    /// The certificate followed by its private key, as most servers and tools expect them
+    pub fn to_pem(&self) -> String {
+        format!("{}{}", self.cert, self.key)
+    }
+
+    pub fn to_pkcs12(&self, password: &str) -> Result<Vec<u8>, String> {
+        to_pkcs12(&self.name, &self.cert, &self.key, password)
+    }
+
+    pub fn details(&self) -> Result<CertificateDetails, String> {
+        certificate_details(&self.cert)
+    }
+}
+
+/// Encrypts a PEM private key as an encrypted PKCS#8 key
+pub fn encrypt_key(key: &str, passphrase: &str) -> Result<String, String> {
+    let (_, der) = SecretDocument::from_pem(key).map_err(|e| e.to_string())?;
+    let info = PrivateKeyInfo::try_from(der.as_bytes()).map_err(|e| e.to_string())?;
+    let encrypted = info
+        .encrypt(OsRng, passphrase)
+        .map_err(|e| format!("Failed to encrypt key: {}", e))?;
+    encrypted
+        .to_pem("ENCRYPTED PRIVATE KEY", LineEnding::LF)
+        .map(|pem| pem.to_string())
+        .map_err(|e| e.to_string())
+}
+
+pub fn decrypt_key(key: &str, passphrase: &str) -> Result<String, String> {
+    let (_, der) = SecretDocument::from_pem(key).map_err(|e| e.to_string())?;
+    let info = EncryptedPrivateKeyInfo::try_from(der.as_bytes()).map_err(|e| e.to_string())?;
+    let decrypted = info
+        .decrypt(passphrase)
+        .map_err(|_| "Wrong passphrase".to_string())?;
+    decrypted
+        .to_pem("PRIVATE KEY", LineEnding::LF)
+        .map(|pem| pem.to_string())
+        .map_err(|e| e.to_string())
+}
+
+// The passphrase keys are stored under, once the user has given it
+static PASSPHRASE: LazyLock<Mutex<Option<String>>> = LazyLock::new(|| Mutex::new(None));
+// Decrypting is slow on purpose, so keys are only decrypted once a session, by their stored form
+static UNLOCKED_KEYS: LazyLock<Mutex<HashMap<String, String>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn passphrase() -> Option<String> {
+    PASSPHRASE
+        .lock()
+        .expect("Failed to lock passphrase")
+        .clone()
+}
+
+fn unlocked_key(key: &str) -> Result<String, String> {
+    if let Some(unlocked) = UNLOCKED_KEYS.lock().unwrap().get(key) {
+        return Ok(unlocked.clone());
+    }
+    let passphrase = passphrase().ok_or("Keys are locked until the passphrase is entered")?;
+    let unlocked = decrypt_key(key, &passphrase)?;
+    UNLOCKED_KEYS
+        .lock()
+        .unwrap()
+        .insert(key.to_string(), unlocked.clone());
+    Ok(unlocked)
+}
+
+// New keys are encrypted if the others are
+fn key_to_store(key: &str) -> Result<(String, bool), String> {
+    match passphrase() {
+        Some(passphrase) => Ok((encrypt_key(key, &passphrase)?, true)),
+        None => Ok((key.to_string(), false)),
+    }
+}
+
+pub fn keys_encrypted() -> bool {
+    get_stored_keys()
+        .unwrap_or_default()
+        .iter()
+        .any(|(_, _, encrypted)| *encrypted)
+}
+
+/// Whether there are stored keys that can't be used until the passphrase is given
+pub fn keys_locked() -> bool {
+    passphrase().is_none() && keys_encrypted()
+}
+
+/// Checks the passphrase against a stored key and keeps it for the rest of the session
+pub fn unlock(passphrase: &str) -> Result<(), String> {
+    let keys = get_stored_keys().map_err(|e| e.to_string())?;
+    if let Some((_, key, _)) = keys.iter().find(|(_, _, encrypted)| *encrypted) {
+        decrypt_key(key, passphrase)?;
+    }
+    *PASSPHRASE.lock().unwrap() = Some(passphrase.to_string());
+    Ok(())
+}
+
+/// Re-encrypts every stored key under a new passphrase, or stores them in the clear if `None`
+pub fn change_passphrase(new_passphrase: Option<&str>) -> Result<(), String> {
+    if keys_locked() {
+        return Err("Unlock your keys before changing the passphrase".to_string());
+    }
+    let keys = get_stored_keys().map_err(|e| e.to_string())?;
+    let mut stored = Vec::new();
+    for (name, key, encrypted) in keys {
+        let key = if encrypted { unlocked_key(&key)? } else { key };
+        let key = match new_passphrase {
+            Some(passphrase) => (encrypt_key(&key, passphrase)?, true),
+            None => (key, false),
+        };
+        stored.push((name, key.0, key.1));
+    }
+    set_stored_keys(&stored).map_err(|e| format!("Failed to save keys: {}", e))?;
+    *PASSPHRASE.lock().unwrap() = new_passphrase.map(str::to_string);
+    Ok(())
+}
+
+/// Creates a self-signed certificate valid for `days`, returning it and its key as PEM
+pub fn self_signed(name: &str, key_type: KeyType, days: i64) -> Result<(String, String), String> {
+    let mut distinguished_name = DistinguishedName::new();
+    distinguished_name.push(DnType::CommonName, name);
+
+    let mut params = CertificateParams::new(vec![]).map_err(|e| e.to_string())?;
+    params.distinguished_name = distinguished_name;
+    params.not_before = OffsetDateTime::now_utc();
+    params.not_after = OffsetDateTime::now_utc() + time::Duration::days(days);
+    params.is_ca = rcgen::IsCa::ExplicitNoCa;
+
+    let key_pair = match key_type {
+        KeyType::EcdsaP256 => KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256),
+        KeyType::Rsa2048 | KeyType::Rsa4096 => {
+            let bits = if key_type == KeyType::Rsa2048 {
+                2048
+            } else {
+                4096
+            };
+            let private_key = RsaPrivateKey::new(&mut OsRng, bits).map_err(|e| e.to_string())?;
+            let private_key_der = private_key.to_pkcs8_der().map_err(|e| e.to_string())?;
+            KeyPair::try_from(private_key_der.as_bytes())
+        }
+    }
+    .map_err(|e| e.to_string())?;
+
+    let certificate = params.self_signed(&key_pair).map_err(|e| e.to_string())?;
+    Ok((certificate.pem(), key_pair.serialize_pem()))
+}
+
+fn to_pem(label: &str, der: &[u8]) -> String {
+    let encoded = BASE64_STANDARD.encode(der);
+    let mut pem = format!("-----BEGIN {}-----\n", label);
+    for line in encoded.as_bytes().chunks(64) {
+        pem.push_str(&String::from_utf8_lossy(line));
+        pem.push('\n');
+    }
+    pem.push_str(&format!("-----END {}-----\n", label));
+    pem
+}
+
+/// Finds the first certificate and private key in PEM data, converting RSA keys to PKCS#8
+pub fn parse_pem(data: &[u8]) -> Result<(String, String), String> {
+    let mut cert = None;
+    let mut key = None;
+    for pem in Pem::iter_from_buffer(data) {
+        let pem = pem.map_err(|e| format!("Invalid PEM data: {}", e))?;
+        match pem.label.as_str() {
+            "CERTIFICATE" if cert.is_none() => cert = Some(to_pem(&pem.label, &pem.contents)),
+            "PRIVATE KEY" if key.is_none() => key = Some(to_pem(&pem.label, &pem.contents)),
+            "RSA PRIVATE KEY" if key.is_none() => {
+                let private_key = RsaPrivateKey::from_pkcs1_der(&pem.contents)
+                    .map_err(|e| format!("Invalid RSA key: {}", e))?;
+                let der = private_key.to_pkcs8_der().map_err(|e| e.to_string())?;
+                key = Some(to_pem("PRIVATE KEY", der.as_bytes()));
+            }
+            "ENCRYPTED PRIVATE KEY" | "EC PRIVATE KEY" => {
+                return Err(format!(
+                    "{} blocks aren't supported, convert the key to unencrypted PKCS#8 first",
+                    pem.label
+                ))
+            }
+            _ => (),
+        }
+    }
+    match (cert, key) {
+        (Some(cert), Some(key)) => Ok((cert, key)),
+        (None, _) => Err("No certificate found".to_string()),
+        (_, None) => Err("No private key found, choose the key file as well".to_string()),
+    }
+}
+
+/// Reads the first certificate and private key out of a PKCS#12 archive
+pub fn parse_pkcs12(data: &[u8], password: &str) -> Result<(String, String), String> {
+    let keystore = KeyStore::from_pkcs12(data, password)
+        .map_err(|e| format!("Failed to read PKCS#12 archive: {}", e))?;
+    let (_, chain) = keystore
+        .private_key_chain()
+        .ok_or("The archive holds no private key")?;
+    let cert = chain
+        .chain()
+        .first()
+        .ok_or("The archive holds no certificate for its key")?;
+    Ok((
+        to_pem("CERTIFICATE", cert.as_der()),
+        to_pem("PRIVATE KEY", chain.key()),
+    ))
+}
+
+pub fn to_pkcs12(name: &str, cert: &str, key: &str, password: &str) -> Result<Vec<u8>, String> {
+    let der = |pem: &str| {
+        Pem::iter_from_buffer(pem.as_bytes())
+            .next()
+            .and_then(Result::ok)
+            .map(|pem| pem.contents)
+            .ok_or_else(|| format!("Profile {} is damaged", name))
+    };
+    let cert = Certificate::from_der(&der(cert)?).map_err(|e| e.to_string())?;
+    let chain = PrivateKeyChain::new(der(key)?, name.as_bytes(), [cert]);
+    let mut keystore = KeyStore::new();
+    keystore.add_entry(name, KeyStoreEntry::PrivateKeyChain(chain));
+    keystore
+        .writer(password)
+        .write()
+        .map_err(|e| format!("Failed to write PKCS#12 archive: {}", e))
+}
+
+/// The SHA-256 fingerprint of a PEM certificate, in hex
+pub fn fingerprint(cert: &str) -> Result<String, String> {
+    let (_, pem) = x509_parser::pem::parse_x509_pem(cert.as_bytes()).map_err(|e| e.to_string())?;
+    let digest = Sha256::digest(&pem.contents);
+    Ok(digest.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+pub fn certificate_details(cert: &str) -> Result<CertificateDetails, String> {
+    let (_, pem) = x509_parser::pem::parse_x509_pem(cert.as_bytes()).map_err(|e| e.to_string())?;
+    let cert = pem.parse_x509().map_err(|e| e.to_string())?;
+    let key_type = match cert.public_key().parsed() {
+        Ok(PublicKey::RSA(key)) => format!("RSA {}", key.key_size()),
+        Ok(PublicKey::EC(key)) => format!("ECDSA {}", key.key_size()),
+        _ => "Unknown".to_string(),
+    };
+    let expires = OffsetDateTime::from_unix_timestamp(cert.validity().not_after.timestamp())
+        .map_err(|e| e.to_string())?;
+    Ok(CertificateDetails {
+        subject: cert.subject().to_string(),
+        key_type,
+        expires,
+    })
 }
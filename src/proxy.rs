@@ -0,0 +1,130 @@
+use std::io::{Read, Write};
+use std::net::IpAddr;
+
+use serde::{Deserialize, Serialize};
+
+/// How requests for a scheme reach their server, when not directly
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Proxy {
+    /// A SOCKS5 proxy such as a local Tor daemon, which also looks up host names
+    Socks5 { address: String },
+    /// A Gemini server that fetches pages on the client's behalf when sent their full URL
+    Gemini { address: String },
+}
+
+pub const TOR_ADDRESS: &str = "127.0.0.1:9050";
+
+impl Proxy {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Proxy::Socks5 { .. } => "SOCKS5",
+            Proxy::Gemini { .. } => "Gemini proxy",
+        }
+    }
+
+    pub fn address_mut(&mut self) -> &mut String {
+        match self {
+            Proxy::Socks5 { address } | Proxy::Gemini { address } => address,
+        }
+    }
+}
+
+/// Splits a proxy's host and port, using the default port if it doesn't give one
+pub fn split_address(address: &str, default_port: u16) -> Result<(String, u16), String> {
+    let address = address.trim();
+    if address.is_empty() {
+        return Err("The proxy has no address, set one in the settings".to_string());
+    }
+    // Bracketed IPv6 addresses contain colons of their own
+    match address.rsplit_once(':') {
+        Some((host, port)) if !port.contains(']') => {
+            let port = port
+                .parse()
+                .map_err(|_| format!("Invalid proxy port in {}", address))?;
+            Ok((host.to_string(), port))
+        }
+        _ => Ok((address.to_string(), default_port)),
+    }
+}
+
+fn socks5_error(code: u8) -> &'static str {
+    match code {
+        1 => "general failure",
+        2 => "connection not allowed by ruleset",
+        3 => "network unreachable",
+        4 => "host unreachable",
+        5 => "connection refused",
+        6 => "TTL expired",
+        7 => "command not supported",
+        8 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+/// Asks a SOCKS5 proxy to connect the stream onwards to the host, after which it carries the
+/// request as if connected directly
+pub fn socks5_handshake(
+    stream: &mut (impl Read + Write),
+    host: &str,
+    port: u16,
+) -> Result<(), String> {
+    let io = |e: std::io::Error| format!("SOCKS5 proxy: {}", e);
+    // No authentication, which is all Tor needs
+    stream.write_all(&[5, 1, 0]).map_err(io)?;
+    let mut reply = [0; 2];
+    stream.read_exact(&mut reply).map_err(io)?;
+    if reply[0] != 5 {
+        return Err("The proxy does not speak SOCKS5".to_string());
+    }
+    if reply[1] != 0 {
+        return Err("The SOCKS5 proxy requires authentication, which isn't supported".to_string());
+    }
+
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let mut request = vec![5, 1, 0];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(1);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(4);
+            request.extend_from_slice(&ip.octets());
+        }
+        // Names are looked up by the proxy, which is how .onion addresses resolve over Tor
+        Err(_) => {
+            let len = u8::try_from(host.len())
+                .map_err(|_| format!("Host name too long for SOCKS5: {}", host))?;
+            request.push(3);
+            request.push(len);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).map_err(io)?;
+
+    let mut header = [0; 4];
+    stream.read_exact(&mut header).map_err(io)?;
+    if header[1] != 0 {
+        return Err(format!(
+            "The SOCKS5 proxy could not connect to {}:{}: {}",
+            host,
+            port,
+            socks5_error(header[1])
+        ));
+    }
+    // The address the proxy connected from follows, which isn't needed
+    let len = match header[3] {
+        1 => 4,
+        4 => 16,
+        3 => {
+            let mut len = [0];
+            stream.read_exact(&mut len).map_err(io)?;
+            len[0] as usize
+        }
+        _ => return Err("Malformed SOCKS5 reply".to_string()),
+    };
+    stream.read_exact(&mut vec![0; len + 2]).map_err(io)?;
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::settings;
+
+/// How long to hold off when a server asks us to slow down without saying for how long
+pub const DEFAULT_SLOW_DOWN: Duration = Duration::from_secs(10);
+/// Longest a server can ask us to hold off for, so a typo doesn't shut it out for days
+const MAX_SLOW_DOWN: Duration = Duration::from_secs(600);
+/// Waits longer than this are worth mentioning, as they mostly follow a slow down
+const LONG_WAIT: Duration = Duration::from_secs(2);
+
+/// When the next request to each server may be sent, by host and port
+static NEXT_REQUEST: LazyLock<Mutex<HashMap<String, Instant>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// How long a slow down response asks to wait, from the seconds given as its meta
+pub fn slow_down_duration(meta: &str) -> Duration {
+    let seconds = meta
+        .split_whitespace()
+        .next()
+        .and_then(|seconds| seconds.parse::<u64>().ok());
+    match seconds {
+        Some(seconds) => Duration::from_secs(seconds.max(1)).min(MAX_SLOW_DOWN),
+        None => DEFAULT_SLOW_DOWN,
+    }
+}
+
+/// Takes the next turn to send a request to a server, returning how long to wait for it
+pub fn reserve(server: &str) -> Duration {
+    let interval = Duration::from_millis(settings::settings().request_interval_ms);
+    let now = Instant::now();
+    let mut next_request = NEXT_REQUEST.lock().expect("Failed to lock rate limiter");
+    let turn = next_request
+        .get(server)
+        .copied()
+        .filter(|next| *next > now)
+        .unwrap_or(now);
+    next_request.insert(server.to_string(), turn + interval);
+    turn - now
+}
+
+/// Waits until a request may be sent to a server without crowding it
+pub fn wait_turn(server: &str) {
+    let wait = reserve(server);
+    // Kept off stdout, where fetched pages go when run from the command line
+    if wait > LONG_WAIT {
+        eprintln!(
+            "Waiting {:.1}s before contacting {}",
+            wait.as_secs_f32(),
+            server
+        );
+    }
+    if !wait.is_zero() {
+        thread::sleep(wait);
+    }
+}
+
+/// Holds off every request to a server for a while, as it asked
+pub fn back_off(server: &str, duration: Duration) {
+    let until = Instant::now() + duration;
+    let mut next_request = NEXT_REQUEST.lock().expect("Failed to lock rate limiter");
+    let next = next_request.entry(server.to_string()).or_insert(until);
+    *next = (*next).max(until);
+}
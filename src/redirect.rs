@@ -0,0 +1,91 @@
+use url::Url;
+
+use crate::db::{clear_redirects, delete_redirects, get_redirect, set_redirect};
+
+pub const DEFAULT_MAX_REDIRECTS: usize = 5;
+
+/// Resolves a redirect target, which may be relative, against the page that sent it
+pub fn resolve(current: &Url, target: &str) -> Result<Url, String> {
+    let target = target.trim();
+    if target.is_empty() {
+        return Err("The server redirected without giving a destination".to_string());
+    }
+    current
+        .join(target)
+        .map_err(|e| format!("Invalid redirect target {}: {}", target, e))
+}
+
+/// Checks that `target` can be followed from a chain of pages that have redirected so far,
+/// the last of them being the one redirecting to it
+pub fn check_chain(chain: &[Url], target: &Url, max_redirects: usize) -> Result<(), String> {
+    let describe = || {
+        chain
+            .iter()
+            .chain([target])
+            .map(Url::as_str)
+            .collect::<Vec<_>>()
+            .join("\n→ ")
+    };
+    if chain.contains(target) {
+        Err(format!("Redirect loop detected:\n\n{}", describe()))
+    } else if chain.len() > max_redirects {
+        Err(format!(
+            "Stopped after {} redirects:\n\n{}",
+            max_redirects,
+            describe()
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Why a redirect should be confirmed before following it, if it should
+pub fn confirmation_reason(from: &Url, to: &Url) -> Option<String> {
+    if from.scheme() != to.scheme() {
+        Some(format!(
+            "switches from {} to {}",
+            from.scheme(),
+            to.scheme()
+        ))
+    } else if from.host_str() != to.host_str() {
+        Some(format!(
+            "leads to a different host, {}",
+            to.host_str().unwrap_or_default()
+        ))
+    } else {
+        None
+    }
+}
+
+/// Records that a page has moved for good, so it can be skipped from then on
+pub fn remember(from: &Url, to: &Url) {
+    if let Err(e) = set_redirect(from.as_str(), to.as_str()) {
+        println!("Failed to remember redirect from {}: {}", from, e);
+    }
+}
+
+/// Where a page that has permanently moved now lives, following any chain of moves
+pub fn remembered(url: &Url) -> Option<Url> {
+    let mut chain = vec![url.clone()];
+    while let Some(target) = get_redirect(chain.last()?.as_str()).ok().flatten() {
+        let target = Url::parse(&target).ok()?;
+        if check_chain(&chain, &target, DEFAULT_MAX_REDIRECTS).is_err() {
+            break;
+        }
+        chain.push(target);
+    }
+    chain.pop().filter(|target| target != url)
+}
+
+/// Stops skipping to or from a page, e.g. when where it moved to stops working
+pub fn forget(url: &Url) {
+    if let Err(e) = delete_redirects(url.as_str()) {
+        println!("Failed to forget redirects for {}: {}", url, e);
+    }
+}
+
+pub fn forget_all() {
+    if let Err(e) = clear_redirects() {
+        println!("Failed to forget redirects: {}", e);
+    }
+}
@@ -0,0 +1,475 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{LazyLock, Mutex, MutexGuard};
+use std::time::Duration;
+
+use eframe::egui::{Checkbox, ComboBox, Context, DragValue, Grid, Id, RichText, TextEdit, Ui};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::cache::{self, CacheSettings};
+use crate::handlers::Protocol;
+use crate::networking::default_port;
+use crate::proxy::{Proxy, TOR_ADDRESS};
+use crate::redirect::{self, DEFAULT_MAX_REDIRECTS};
+use crate::Breeze;
+
+pub const DEFAULT_HOME_PAGE: &str = "gemini://geminiprotocol.net/";
+/// Where the settings editor is opened from the address bar
+pub const SETTINGS_URL: &str = "breeze://settings";
+
+/// Schemes whose connections can be configured, in the order they are listed for editing
+pub const SCHEMES: [&str; 13] = [
+    "finger", "gemini", "gopher", "gophers", "guppy", "misfin", "nex", "nps", "scorpion", "scroll",
+    "spartan", "text", "titan",
+];
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Opened at startup when no URL is given, and by the home button
+    pub home_page: String,
+    pub window_width: f32,
+    pub window_height: f32,
+    /// Ports to use for a scheme when a URL doesn't give one, instead of the protocol's default
+    pub ports: BTreeMap<String, u16>,
+    /// Proxies to send a scheme's requests through, by scheme
+    pub proxies: BTreeMap<String, Proxy>,
+    pub connect_timeout_secs: u64,
+    pub read_timeout_secs: u64,
+    /// Least time between requests to the same server, to stay polite to small ones
+    pub request_interval_ms: u64,
+    /// How long looked up addresses are reused, 0 to look up every time
+    pub dns_cache_minutes: u32,
+    /// Look up the hosts of links on screen ahead of them being followed
+    pub prefetch_dns: bool,
+    /// Name of the theme to use, falling back to the default if it no longer exists
+    pub theme: String,
+    /// Font files to prefer over the built-in ones, empty to use only those
+    pub proportional_font: String,
+    pub monospace_font: String,
+    pub cache: CacheSettings,
+    /// Where saved pages go, empty for the platform's downloads directory
+    pub download_dir: String,
+    /// Languages to ask for content in, most preferred first
+    pub languages: Vec<String>,
+    pub max_redirects: usize,
+    /// Accept Misfin mail sent to this machine, as the active profile
+    pub receive_mail: bool,
+    pub mail_port: u16,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            home_page: DEFAULT_HOME_PAGE.to_string(),
+            window_width: 800.0,
+            window_height: 600.0,
+            ports: BTreeMap::new(),
+            proxies: BTreeMap::new(),
+            connect_timeout_secs: 10,
+            read_timeout_secs: 30,
+            request_interval_ms: 250,
+            dns_cache_minutes: 5,
+            prefetch_dns: false,
+            theme: "Light".to_string(),
+            proportional_font: String::new(),
+            monospace_font: String::new(),
+            cache: CacheSettings::default(),
+            download_dir: String::new(),
+            languages: vec!["en".to_string()],
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            receive_mail: false,
+            mail_port: default_port(Protocol::Misfin),
+        }
+    }
+}
+
+impl Settings {
+    pub fn port(&self, scheme: &str) -> Option<u16> {
+        self.ports.get(scheme).copied()
+    }
+
+    pub fn proxy(&self, scheme: &str) -> Option<Proxy> {
+        self.proxies.get(scheme).cloned()
+    }
+
+    // A zero timeout would never be met, so anything below a second is rounded up
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_secs(self.connect_timeout_secs.max(1))
+    }
+
+    pub fn read_timeout(&self) -> Duration {
+        Duration::from_secs(self.read_timeout_secs.max(1))
+    }
+
+    pub fn download_dir(&self) -> Option<PathBuf> {
+        match self.download_dir.trim() {
+            "" => dirs::download_dir(),
+            dir => Some(PathBuf::from(dir)),
+        }
+    }
+}
+
+/// How long editing has to pause before changed settings are written out
+pub const SAVE_DELAY: Duration = Duration::from_secs(1);
+
+static SETTINGS: LazyLock<Mutex<Settings>> = LazyLock::new(|| Mutex::new(load()));
+
+fn lock() -> MutexGuard<'static, Settings> {
+    SETTINGS.lock().expect("Failed to lock settings mutex")
+}
+
+// Tests run with the defaults rather than whatever is configured on the machine
+fn settings_path() -> Option<PathBuf> {
+    if cfg!(test) {
+        return None;
+    }
+    dirs::config_dir().map(|dir| dir.join("breeze").join("settings.toml"))
+}
+
+fn load() -> Settings {
+    let Some(path) = settings_path() else {
+        return Settings::default();
+    };
+    let Ok(text) = fs::read_to_string(path) else {
+        return Settings::default();
+    };
+    parse(&text).unwrap_or_else(|e| {
+        println!("Failed to parse settings: {}", e);
+        Settings::default()
+    })
+}
+
+pub fn parse(text: &str) -> Result<Settings, String> {
+    toml::from_str(text).map_err(|e| e.to_string())
+}
+
+pub fn settings() -> Settings {
+    lock().clone()
+}
+
+/// Puts settings into effect, writing them to the settings file is left to `save_settings`
+pub fn set_settings(settings: Settings) {
+    let previous = std::mem::replace(&mut *lock(), settings.clone());
+    if previous.cache != settings.cache {
+        cache::set_settings(settings.cache);
+    }
+}
+
+pub fn save_settings() {
+    let Some(path) = settings_path() else { return };
+    let settings = settings();
+    let result = fs::create_dir_all(path.parent().unwrap()).and_then(|_| {
+        let text = toml::to_string(&settings).expect("Failed to serialize settings");
+        fs::write(&path, text)
+    });
+    if let Err(e) = result {
+        println!("Failed to save settings: {}", e);
+    }
+}
+
+// Edits the browser's working copy, which is applied once the frame is done and saved once
+// editing pauses
+pub fn render_settings(ui: &mut Ui, _ctx: &Context, breeze: &mut Breeze) {
+    let themes = &breeze.themes;
+    let settings = &mut breeze.settings;
+    ui.heading("Settings");
+    ui.add_space(8.0);
+
+    ui.label(RichText::new("General").strong());
+    Grid::new("settings_general")
+        .num_columns(2)
+        .spacing([16.0, 6.0])
+        .show(ui, |ui| {
+            ui.label("Home page");
+            ui.vertical(|ui| {
+                ui.add(TextEdit::singleline(&mut settings.home_page).desired_width(320.0));
+                if Url::parse(settings.home_page.trim()).is_err() {
+                    ui.label(RichText::new("Not a valid URL").small());
+                }
+            });
+            ui.end_row();
+            ui.label("Download directory");
+            let platform = dirs::download_dir().map(|dir| dir.display().to_string());
+            ui.add(
+                TextEdit::singleline(&mut settings.download_dir)
+                    .hint_text(platform.unwrap_or_default())
+                    .desired_width(320.0),
+            );
+            ui.end_row();
+            ui.label("Languages");
+            // Kept as typed, since a half-written list would otherwise lose its trailing comma
+            let id = Id::new("settings_languages");
+            let mut languages = ui
+                .data_mut(|data| data.get_temp::<String>(id))
+                .unwrap_or_else(|| settings.languages.join(", "));
+            let edit = ui.add(
+                TextEdit::singleline(&mut languages)
+                    .hint_text("e.g. en, fr")
+                    .desired_width(320.0),
+            );
+            if edit.changed() {
+                settings.languages = languages
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|language| !language.is_empty())
+                    .map(str::to_string)
+                    .collect();
+            }
+            ui.data_mut(|data| data.insert_temp(id, languages));
+            ui.end_row();
+            ui.label("Redirect limit");
+            ui.horizontal(|ui| {
+                ui.add(DragValue::new(&mut settings.max_redirects).range(0..=20));
+                if ui
+                    .button("Forget moved pages")
+                    .on_hover_text("Stop skipping pages that redirected permanently")
+                    .clicked()
+                {
+                    redirect::forget_all();
+                }
+            });
+            ui.end_row();
+        });
+    ui.separator();
+
+    ui.label(RichText::new("Mail").strong());
+    Grid::new("settings_mail")
+        .num_columns(2)
+        .spacing([16.0, 6.0])
+        .show(ui, |ui| {
+            ui.label("Receive mail");
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut settings.receive_mail, "Listen on port")
+                    .on_hover_text("Senders reach you at misfin://you@this machine's address");
+                ui.add_enabled(
+                    settings.receive_mail,
+                    DragValue::new(&mut settings.mail_port).range(1..=65535),
+                );
+            });
+            ui.end_row();
+        });
+    ui.separator();
+
+    ui.label(RichText::new("Appearance").strong());
+    Grid::new("settings_appearance")
+        .num_columns(2)
+        .spacing([16.0, 6.0])
+        .show(ui, |ui| {
+            ui.label("Theme");
+            ComboBox::from_id_salt("settings_theme")
+                .selected_text(&settings.theme)
+                .show_ui(ui, |ui| {
+                    for theme in themes {
+                        ui.selectable_value(&mut settings.theme, theme.name.clone(), &theme.name);
+                    }
+                });
+            ui.end_row();
+            ui.label("Proportional font");
+            ui.add(
+                TextEdit::singleline(&mut settings.proportional_font)
+                    .hint_text("Built-in")
+                    .desired_width(320.0),
+            );
+            ui.end_row();
+            ui.label("Monospace font");
+            ui.add(
+                TextEdit::singleline(&mut settings.monospace_font)
+                    .hint_text("Built-in")
+                    .desired_width(320.0),
+            );
+            ui.end_row();
+            ui.label("Window size");
+            // Only taken once let go of or typed in, rather than resizing the window every frame
+            let id = Id::new("settings_window_size");
+            let mut size = ui
+                .data_mut(|data| data.get_temp::<(f32, f32)>(id))
+                .unwrap_or((settings.window_width, settings.window_height));
+            let editing = ui
+                .horizontal(|ui| {
+                    let width = ui.add(DragValue::new(&mut size.0).range(320.0..=7680.0));
+                    ui.label("×");
+                    let height = ui.add(DragValue::new(&mut size.1).range(240.0..=4320.0));
+                    [width, height]
+                        .iter()
+                        .any(|edit| edit.dragged() || edit.has_focus())
+                })
+                .inner;
+            if editing {
+                ui.data_mut(|data| data.insert_temp(id, size));
+            } else {
+                (settings.window_width, settings.window_height) = size;
+                ui.data_mut(|data| data.remove_temp::<(f32, f32)>(id));
+            }
+            ui.end_row();
+        });
+    ui.separator();
+
+    ui.label(RichText::new("Network").strong());
+    Grid::new("settings_network")
+        .num_columns(2)
+        .spacing([16.0, 6.0])
+        .show(ui, |ui| {
+            ui.label("Connect timeout");
+            ui.add(
+                DragValue::new(&mut settings.connect_timeout_secs)
+                    .range(1..=300)
+                    .suffix(" s"),
+            );
+            ui.end_row();
+            ui.label("Read timeout");
+            ui.add(
+                DragValue::new(&mut settings.read_timeout_secs)
+                    .range(1..=300)
+                    .suffix(" s"),
+            );
+            ui.end_row();
+            ui.label("Request spacing");
+            ui.add(
+                DragValue::new(&mut settings.request_interval_ms)
+                    .range(0..=10000)
+                    .suffix(" ms"),
+            )
+            .on_hover_text("Least time between requests to the same server");
+            ui.end_row();
+            ui.label("DNS cache");
+            ui.horizontal(|ui| {
+                ui.add(
+                    DragValue::new(&mut settings.dns_cache_minutes)
+                        .range(0..=1440)
+                        .suffix(" min"),
+                );
+                ui.add_enabled(
+                    settings.dns_cache_minutes > 0,
+                    Checkbox::new(&mut settings.prefetch_dns, "Look up hosts of visible links"),
+                );
+            });
+            ui.end_row();
+            ui.label("Cache size");
+            ui.horizontal(|ui| {
+                ui.add(
+                    DragValue::new(&mut settings.cache.size_mb)
+                        .range(0..=4096)
+                        .suffix(" MB"),
+                );
+                if ui.button("Clear cache").clicked() {
+                    cache::clear();
+                }
+            });
+            ui.end_row();
+            ui.label("Cache expiry");
+            ui.add(
+                DragValue::new(&mut settings.cache.expiry_minutes)
+                    .range(0..=10080)
+                    .suffix(" min"),
+            );
+            ui.end_row();
+        });
+    ui.add_space(8.0);
+
+    // Ports are used when a URL doesn't give one, left empty for the protocol's own
+    ui.label(RichText::new("Connections").strong());
+    Grid::new("settings_connections")
+        .num_columns(4)
+        .spacing([16.0, 6.0])
+        .show(ui, |ui| {
+            ui.label("Scheme");
+            ui.label("Port");
+            ui.label("Proxy");
+            ui.label("Proxy address");
+            ui.end_row();
+            for scheme in SCHEMES {
+                ui.label(scheme);
+                let mut port = settings
+                    .port(scheme)
+                    .map(|p| p.to_string())
+                    .unwrap_or_default();
+                let default = default_port(Protocol::from_str(scheme)).to_string();
+                let edit = ui.add(
+                    TextEdit::singleline(&mut port)
+                        .hint_text(default)
+                        .desired_width(64.0),
+                );
+                if edit.changed() {
+                    match port.trim().parse::<u16>() {
+                        Ok(port) if port != 0 => {
+                            settings.ports.insert(scheme.to_string(), port);
+                        }
+                        _ if port.trim().is_empty() => {
+                            settings.ports.remove(scheme);
+                        }
+                        _ => {}
+                    }
+                }
+                render_proxy(ui, &mut settings.proxies, scheme, true);
+                ui.end_row();
+            }
+            // Each IRC network has a port of its own, and only SOCKS5 can carry a chat
+            ui.label("irc");
+            ui.label(RichText::new("per network").weak());
+            render_proxy(ui, &mut settings.proxies, "irc", false);
+            ui.end_row();
+        });
+    ui.separator();
+
+    if ui.button("Restore defaults").clicked() {
+        *settings = Settings::default();
+        ui.data_mut(|data| data.remove_temp::<String>(Id::new("settings_languages")));
+    }
+}
+
+fn render_proxy(
+    ui: &mut Ui,
+    proxies: &mut BTreeMap<String, Proxy>,
+    scheme: &str,
+    allow_gemini: bool,
+) {
+    let mut proxy = proxies.get(scheme).cloned();
+    let selected = proxy.as_ref().map_or("Direct", Proxy::label);
+    ComboBox::from_id_salt(("settings_proxy", scheme))
+        .selected_text(selected)
+        .show_ui(ui, |ui| {
+            ui.selectable_value(&mut proxy, None, "Direct");
+            // Tor listens for SOCKS5 connections here by default
+            let socks5 = Proxy::Socks5 {
+                address: TOR_ADDRESS.to_string(),
+            };
+            let gemini = Proxy::Gemini {
+                address: String::new(),
+            };
+            let choices = if allow_gemini {
+                vec![socks5, gemini]
+            } else {
+                vec![socks5]
+            };
+            for choice in choices {
+                let label = choice.label();
+                let current = proxy.as_ref().is_some_and(|p| p.label() == label);
+                if ui.selectable_label(current, label).clicked() && !current {
+                    proxy = Some(choice);
+                }
+            }
+        });
+    match &mut proxy {
+        Some(proxy) => {
+            let hint = match proxy {
+                Proxy::Socks5 { .. } => TOR_ADDRESS,
+                Proxy::Gemini { .. } => "host:port",
+            };
+            ui.add(
+                TextEdit::singleline(proxy.address_mut())
+                    .hint_text(hint)
+                    .desired_width(160.0),
+            );
+        }
+        None => {
+            ui.label("");
+        }
+    }
+    match proxy {
+        Some(proxy) => proxies.insert(scheme.to_string(), proxy),
+        None => proxies.remove(scheme),
+    };
+}
@@ -0,0 +1,49 @@
+use rusqlite::Connection;
+
+use crate::db::migrate;
+
+fn version(db: &Connection) -> usize {
+    db.query_row("SELECT version FROM schema_version;", [], |row| row.get(0))
+        .unwrap()
+}
+
+#[test]
+fn fresh_database() {
+    let mut db = Connection::open_in_memory().unwrap();
+    let latest = migrate(&mut db).unwrap();
+    assert_eq!(version(&db), latest);
+    db.execute(
+        "INSERT INTO profiles (name, cert, key, active, encrypted) VALUES ('a', '', '', 1, 1);",
+        (),
+    )
+    .unwrap();
+    // Running again finds nothing to do
+    migrate(&mut db).unwrap();
+    assert_eq!(version(&db), latest);
+    let count: u32 = db
+        .query_row("SELECT COUNT(*) FROM schema_version;", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 1);
+}
+
+#[test]
+fn database_from_before_versioning() {
+    let mut db = Connection::open_in_memory().unwrap();
+    db.execute_batch(
+        "CREATE TABLE profiles (name TEXT PRIMARY KEY, cert TEXT, key TEXT, active BOOLEAN);
+         INSERT INTO profiles VALUES ('old', 'cert', 'key', 1);
+         CREATE TABLE visited (url TEXT PRIMARY KEY, last_visited INTEGER, visits INTEGER);",
+    )
+    .unwrap();
+    let latest = migrate(&mut db).unwrap();
+    assert_eq!(version(&db), latest);
+    let (key, encrypted): (String, bool) = db
+        .query_row(
+            "SELECT key, encrypted FROM profiles WHERE name = 'old';",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    assert_eq!(key, "key");
+    assert!(!encrypted);
+}
@@ -0,0 +1,69 @@
+use std::net::{IpAddr, TcpListener};
+
+use url::Url;
+
+use crate::dns::{connection_order, resolve};
+use crate::handlers::Protocol;
+
+use super::mock::serve_tcp;
+use super::protocols::fetch_from;
+
+fn ips(ips: &[&str]) -> Vec<IpAddr> {
+    ips.iter().map(|ip| ip.parse().unwrap()).collect()
+}
+
+#[test]
+fn families_alternate() {
+    let resolved = ips(&[
+        "192.0.2.1",
+        "192.0.2.2",
+        "2001:db8::1",
+        "2001:db8::2",
+        "192.0.2.3",
+    ]);
+    assert_eq!(
+        connection_order(&resolved),
+        ips(&[
+            "2001:db8::1",
+            "192.0.2.1",
+            "2001:db8::2",
+            "192.0.2.2",
+            "192.0.2.3"
+        ])
+    );
+    assert_eq!(connection_order(&ips(&["192.0.2.1"])), ips(&["192.0.2.1"]));
+}
+
+#[test]
+fn literals_resolve_to_themselves() {
+    assert_eq!(resolve("192.0.2.7").unwrap(), ips(&["192.0.2.7"]));
+    assert_eq!(resolve("[2001:db8::7]").unwrap(), ips(&["2001:db8::7"]));
+    assert!(resolve("localhost").is_ok_and(|ips| ips.iter().all(IpAddr::is_loopback)));
+}
+
+// localhost often resolves to ::1 too, which nothing is listening on here
+#[test]
+fn falls_back_between_addresses() {
+    let server = serve_tcp("2 text/gemini\r\nHello\n");
+    let url = Url::parse(&format!("spartan://localhost:{}/", server.port)).unwrap();
+    let response = fetch_from(&url, Protocol::Spartan).unwrap();
+    assert_eq!(response.content, b"Hello\n");
+    server.request();
+}
+
+#[test]
+fn reports_each_address_tried() {
+    // Bind and drop a listener to find a port with nothing on it
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let url = Url::parse(&format!("spartan://127.0.0.1:{}/", port)).unwrap();
+    let error = fetch_from(&url, Protocol::Spartan).err().unwrap();
+    assert!(
+        error.contains(&format!("127.0.0.1:{}: ", port)),
+        "{}",
+        error
+    );
+}
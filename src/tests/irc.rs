@@ -0,0 +1,251 @@
+use std::net::TcpStream;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use eframe::egui::{CentralPanel, Context, RawInput};
+
+use crate::chat::{render_line, ChatLine, LineKind};
+use crate::db::{
+    append_scrollback, get_irc_servers, get_scrollback, rename_irc_server, save_irc_server,
+};
+use crate::irc::{parse_input, run_session, Event, Message, ServerConfig};
+use crate::settings::DEFAULT_HOME_PAGE;
+use crate::Breeze;
+
+use super::mock::{serve_irc, MockServer};
+
+struct Session {
+    events: Receiver<Event>,
+    commands: Sender<Message>,
+    session: JoinHandle<Result<(), String>>,
+}
+
+fn connect(server: &MockServer, config: ServerConfig) -> Session {
+    let stream = TcpStream::connect(("127.0.0.1", server.port)).unwrap();
+    // The session checks for commands to send whenever a read times out
+    stream
+        .set_read_timeout(Some(Duration::from_millis(50)))
+        .unwrap();
+    let (event_sender, events) = channel();
+    let (commands, command_receiver) = channel();
+    let session =
+        thread::spawn(move || run_session(stream, &config, &event_sender, &command_receiver));
+    Session {
+        events,
+        commands,
+        session,
+    }
+}
+
+impl Session {
+    /// Waits for a message with the command to come through from the server
+    fn wait_for(&self, command: &str) -> Message {
+        loop {
+            match self.events.recv_timeout(Duration::from_secs(5)) {
+                Ok(Event::Message(message)) if message.command == command => return message,
+                Ok(_) => continue,
+                Err(e) => panic!("No {} from the server: {}", command, e),
+            }
+        }
+    }
+
+    fn quit(self) {
+        self.commands.send(Message::new("QUIT", &["bye"])).unwrap();
+        self.session.join().unwrap().unwrap();
+    }
+}
+
+fn config() -> ServerConfig {
+    ServerConfig {
+        name: "Test".to_string(),
+        host: "127.0.0.1".to_string(),
+        nick: "breeze".to_string(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn registers_and_joins_after_taken_nick() {
+    let server = serve_irc(|line| match line {
+        "NICK breeze" => vec![":irc.test 433 * breeze :Nickname is already in use".to_string()],
+        "NICK breeze_" => vec!["PING :ready".to_string()],
+        "PONG ready" => vec![":irc.test 001 breeze_ :Welcome".to_string()],
+        "JOIN #breeze" => vec![
+            ":breeze_!b@localhost JOIN #breeze".to_string(),
+            ":mallory!m@localhost PRIVMSG #breeze :hello".to_string(),
+        ],
+        _ => Vec::new(),
+    });
+    let session = connect(
+        &server,
+        ServerConfig {
+            autojoin: "#breeze #gemini".to_string(),
+            ..config()
+        },
+    );
+    let message = session.wait_for("PRIVMSG");
+    assert_eq!(message.source_nick(), Some("mallory"));
+    assert_eq!(message.params, ["#breeze", "hello"]);
+    session.quit();
+    assert_eq!(
+        server.request(),
+        [
+            "NICK breeze",
+            "USER breeze 0 * breeze",
+            "NICK breeze_",
+            "PONG ready",
+            "JOIN #breeze",
+            "JOIN #gemini",
+            "QUIT bye",
+        ]
+        .join("\n")
+    );
+}
+
+#[test]
+fn authenticates_with_sasl_plain() {
+    let server = serve_irc(|line| match line {
+        "CAP REQ sasl" => vec![":irc.test CAP * ACK :sasl".to_string()],
+        "AUTHENTICATE PLAIN" => vec!["AUTHENTICATE +".to_string()],
+        line if line.starts_with("AUTHENTICATE ") => {
+            vec![":irc.test 903 breeze :SASL authentication successful".to_string()]
+        }
+        "CAP END" => vec![":irc.test 001 breeze :Welcome".to_string()],
+        _ => Vec::new(),
+    });
+    let session = connect(
+        &server,
+        ServerConfig {
+            sasl_username: "breeze".to_string(),
+            sasl_password: "hunter2".to_string(),
+            ..config()
+        },
+    );
+    session.wait_for("001");
+    session.quit();
+    assert_eq!(
+        server.request(),
+        [
+            "CAP REQ sasl",
+            "NICK breeze",
+            "USER breeze 0 * breeze",
+            "AUTHENTICATE PLAIN",
+            // "breeze\0breeze\0hunter2"
+            "AUTHENTICATE YnJlZXplAGJyZWV6ZQBodW50ZXIy",
+            "CAP END",
+            "QUIT bye",
+        ]
+        .join("\n")
+    );
+}
+
+fn lines(input: &str, target: Option<&str>) -> Result<Vec<String>, String> {
+    parse_input(input, target).map(|messages| messages.iter().map(Message::to_line).collect())
+}
+
+#[test]
+fn input_commands() {
+    let channel = Some("#breeze");
+    assert_eq!(
+        lines("hello there", channel).unwrap(),
+        ["PRIVMSG #breeze :hello there"]
+    );
+    assert_eq!(lines("/join #a #b", channel).unwrap(), ["JOIN #a #b"]);
+    assert_eq!(lines("/j #a", None).unwrap(), ["JOIN #a"]);
+    assert_eq!(lines("/part", channel).unwrap(), ["PART #breeze"]);
+    assert_eq!(
+        lines("/part see you", channel).unwrap(),
+        ["PART #breeze :see you"]
+    );
+    assert_eq!(lines("/leave #a bye", None).unwrap(), ["PART #a bye"]);
+    assert_eq!(
+        lines("/msg alice hi there", None).unwrap(),
+        ["PRIVMSG alice :hi there"]
+    );
+    assert_eq!(
+        lines("/me waves", channel).unwrap(),
+        ["PRIVMSG #breeze :\x01ACTION waves\x01"]
+    );
+    assert_eq!(lines("/NICK other", None).unwrap(), ["NICK other"]);
+    assert_eq!(lines("/quit", None).unwrap(), ["QUIT :"]);
+    assert_eq!(lines("/quote MODE #a +m", None).unwrap(), ["MODE #a +m"]);
+}
+
+#[test]
+fn input_errors() {
+    assert!(lines("hello", None).is_err());
+    assert!(lines("/me waves", None).is_err());
+    assert!(lines("/part", None).is_err());
+    assert!(lines("/join", Some("#a")).is_err());
+    assert!(lines("/nick", Some("#a")).is_err());
+    assert!(lines("/msg alice", Some("#a")).is_err());
+    assert_eq!(
+        lines("/frobnicate", Some("#a")),
+        Err("Unknown command: /frobnicate".to_string())
+    );
+}
+
+#[test]
+fn renders_urls_after_multibyte_text() {
+    let message = Message::parse(":mallory!m@localhost PRIVMSG #breeze :→gemini://x").unwrap();
+    let line = ChatLine {
+        timestamp: 0,
+        kind: LineKind::Message,
+        nick: message.source_nick().unwrap().to_string(),
+        text: message.params[1].clone(),
+    };
+    let breeze = Breeze::new(DEFAULT_HOME_PAGE.to_string());
+    let ctx = Context::default();
+    let _ = ctx.run(RawInput::default(), |ctx| {
+        CentralPanel::default().show(ctx, |ui| render_line(ui, &line, "breeze", &breeze));
+    });
+}
+
+#[test]
+fn renaming_keeps_scrollback() {
+    let config = ServerConfig {
+        name: "Before rename".to_string(),
+        ..config()
+    };
+    save_irc_server(&config).unwrap();
+    let line = ChatLine {
+        timestamp: 0,
+        kind: LineKind::Message,
+        nick: "alice".to_string(),
+        text: "hello".to_string(),
+    };
+    append_scrollback(&config.name, "#breeze", &line).unwrap();
+    rename_irc_server(&config.name, "After rename").unwrap();
+
+    let names = get_irc_servers()
+        .unwrap()
+        .into_iter()
+        .map(|server| server.name)
+        .collect::<Vec<_>>();
+    assert!(names.contains(&"After rename".to_string()));
+    assert!(!names.contains(&"Before rename".to_string()));
+    assert!(get_scrollback("Before rename", 10).unwrap().is_empty());
+    let scrollback = get_scrollback("After rename", 10).unwrap();
+    assert_eq!(scrollback.len(), 1);
+    assert_eq!(scrollback[0].0, "#breeze");
+    assert_eq!(scrollback[0].1.text, "hello");
+}
+
+#[test]
+fn sasl_password_is_not_saved() {
+    let config = ServerConfig {
+        name: "With SASL".to_string(),
+        sasl_username: "breeze".to_string(),
+        sasl_password: "hunter2".to_string(),
+        ..config()
+    };
+    save_irc_server(&config).unwrap();
+    let saved = get_irc_servers()
+        .unwrap()
+        .into_iter()
+        .find(|server| server.name == config.name)
+        .unwrap();
+    assert_eq!(saved.sasl_username, "breeze");
+    assert!(saved.sasl_password.is_empty());
+}
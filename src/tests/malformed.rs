@@ -0,0 +1,203 @@
+use url::Url;
+
+use crate::document::Block;
+use crate::handlers::{parse_content, segment_urls, Protocol};
+use crate::networking::{GeminiStatus, ServerStatus};
+
+use super::mock::{serve_tcp, serve_tls, serve_udp};
+use super::protocols::{fetch_from, scorpion_block};
+
+#[test]
+fn urls_without_a_host() {
+    for (url, protocol) in [
+        ("gemini:foo", Protocol::Gemini),
+        ("file:///etc/hosts", Protocol::Gemini),
+        ("spartan:foo", Protocol::Spartan),
+    ] {
+        let url = Url::parse(url).unwrap();
+        assert!(fetch_from(&url, protocol).is_err(), "{}", url);
+    }
+}
+
+#[test]
+fn gemini_empty_response() {
+    let server = serve_tls("");
+    assert!(fetch_from(&server.url("gemini", "/"), Protocol::Gemini).is_err());
+    server.request();
+}
+
+#[test]
+fn gemini_missing_line_ending() {
+    // Without a newline there is no way to tell where the status ends and content starts
+    let server = serve_tls("20 text/gemini");
+    assert!(fetch_from(&server.url("gemini", "/"), Protocol::Gemini).is_err());
+    server.request();
+}
+
+#[test]
+fn gemini_unknown_status() {
+    for status_line in ["99 What", "hello world", "", "2"] {
+        let server = serve_tls(format!("{}\r\nbody", status_line));
+        let response = fetch_from(&server.url("gemini", "/"), Protocol::Gemini);
+        assert!(response.is_err(), "{:?} was accepted", status_line);
+        server.request();
+    }
+}
+
+#[test]
+fn gemini_binary_content_kept_intact() {
+    let mut response = b"20 image/png\r\n".to_vec();
+    let content = [0x89, b'P', b'N', b'G', 0x00, 0xff, 0xfe, b'\n', 0x80];
+    response.extend_from_slice(&content);
+    let server = serve_tls(response);
+    let response = fetch_from(&server.url("gemini", "/image.png"), Protocol::Gemini).unwrap();
+    assert!(
+        matches!(response.status, ServerStatus::Gemini(GeminiStatus::Success(ct)) if ct == "image/png")
+    );
+    assert_eq!(response.content, content);
+    server.request();
+}
+
+#[test]
+fn spartan_unknown_status() {
+    let server = serve_tcp("7 nonsense\r\n");
+    assert!(fetch_from(&server.url("spartan", "/"), Protocol::Spartan).is_err());
+    server.request();
+}
+
+#[test]
+fn text_protocol_unknown_status() {
+    let server = serve_tcp("hello\n");
+    assert!(fetch_from(&server.url("text", "/"), Protocol::TextProtocol).is_err());
+    server.request();
+}
+
+#[test]
+fn scorpion_unknown_status() {
+    let server = serve_tcp("99 \r\n");
+    assert!(fetch_from(&server.url("scorpion", "/"), Protocol::Scorpion).is_err());
+    server.request();
+}
+
+#[test]
+fn scorpion_truncated_block() {
+    let mut response = scorpion_block(0x00, "", "Kept");
+    let mut truncated = scorpion_block(0x00, "", "This block claims to be longer");
+    truncated.truncate(12);
+    response.extend(truncated);
+    // An attribute length pointing past the end
+    response.extend([0x08, 0xff, 0xff, b'/', b'x', 0x00, 0x00]);
+    let document = parse_content(&response, false, Protocol::Scorpion);
+    assert_eq!(document.blocks.len(), 1);
+    let url = Url::parse("scorpion://example.org/").unwrap();
+    assert_eq!(document.to_text(&url), "Kept\n");
+}
+
+#[test]
+fn scorpion_dangling_graphics_escape() {
+    // 0x10 starts a graphics character in the PC encoding, but here nothing follows it
+    let response = scorpion_block(0x10, "", "abc\u{10}");
+    let document = parse_content(&response, false, Protocol::Scorpion);
+    assert_eq!(document.blocks.len(), 1);
+}
+
+#[test]
+fn gopher_malformed_lines() {
+    let menu = b"1Missing fields\r\n\
+                 1Only\tselector\r\n\
+                 \r\n\
+                 i\xff\xfe not utf-8\t\t\t0\r\n\
+                 0Good\t/good.txt\texample.org\t70\r\n";
+    let document = parse_content(menu, false, Protocol::Gopher(false));
+    assert_eq!(document.blocks.len(), 5);
+    let url = Url::parse("gopher://example.org/").unwrap();
+    let text = document.to_text(&url);
+    assert!(
+        text.ends_with("=> Good <gopher://example.org/good.txt>\n"),
+        "{}",
+        text
+    );
+}
+
+#[test]
+fn gemtext_edge_cases() {
+    let page = "=>\n=>   \n```unterminated\n=> /not-a-link\n# not a heading";
+    let document = parse_content(page.as_bytes(), false, Protocol::Gemini);
+    assert_eq!(document.blocks.len(), 3);
+    let Block::Preformatted { text, alt_text } = &document.blocks[2] else {
+        panic!("Expected a preformatted block");
+    };
+    assert_eq!(text, "=> /not-a-link\n# not a heading");
+    assert_eq!(alt_text.as_deref(), Some("unterminated"));
+}
+
+#[test]
+fn nex_and_finger_control_characters() {
+    let page = "\u{1b}[31mred\u{1b}[0m\n\0\n=> \n";
+    for protocol in [Protocol::Nex, Protocol::Finger] {
+        let document = parse_content(page.as_bytes(), false, protocol);
+        assert_eq!(document.blocks.len(), 3);
+    }
+}
+
+// Text right before a URL may be any character, not just a single byte one
+#[test]
+fn urls_after_multibyte_text() {
+    for line in [
+        "see →gemini://example.org/",
+        "日本gemini://example.org/ ok",
+        "→://",
+    ] {
+        let segments = segment_urls(line);
+        let text: String = segments.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(text, line);
+    }
+    let segments = segment_urls("see →gemini://example.org/");
+    assert_eq!(segments[0].text, "see →");
+    assert_eq!(
+        segments[1].url.as_ref().map(Url::as_str),
+        Some("gemini://example.org/")
+    );
+}
+
+#[test]
+fn guppy_duplicate_and_early_packets() {
+    let server = serve_udp(vec![
+        b"6 text/plain\r\none ".to_vec(),
+        // Sent again as if our acknowledgement was lost
+        b"6 text/plain\r\none ".to_vec(),
+        // From too far ahead, this should be ignored until it comes round again
+        b"8\r\n".to_vec(),
+        b"7\r\ntwo".to_vec(),
+        b"8\r\n".to_vec(),
+    ]);
+    let response = fetch_from(&server.url("guppy", "/"), Protocol::Guppy).unwrap();
+    assert_eq!(response.content, b"one two");
+    server.request();
+}
+
+#[test]
+fn guppy_missing_content_type() {
+    let server = serve_udp(vec![b"6\r\nno type".to_vec()]);
+    assert!(fetch_from(&server.url("guppy", "/"), Protocol::Guppy).is_err());
+    server.request();
+}
+
+#[test]
+fn guppy_garbage_packet() {
+    let server = serve_udp(vec![b"not a guppy packet".to_vec()]);
+    assert!(fetch_from(&server.url("guppy", "/"), Protocol::Guppy).is_err());
+    server.request();
+}
+
+#[test]
+fn connection_refused() {
+    // Bind then drop a listener so the port is very likely closed
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let url = Url::parse(&format!("nex://127.0.0.1:{}/", port)).unwrap();
+    assert!(fetch_from(&url, Protocol::Nex).is_err());
+}
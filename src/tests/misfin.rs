@@ -0,0 +1,70 @@
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use url::Url;
+
+use crate::handlers::Protocol;
+use crate::misfin::{parse_request, MailServer};
+use crate::networking::{fetch, GeminiStatus, ServerStatus};
+use crate::profile::{fingerprint, self_signed, KeyType, Profile};
+
+#[test]
+fn request_parsing() {
+    assert_eq!(
+        parse_request("misfin://alice@example.org Hello there\r\n"),
+        Ok(("alice@example.org".to_string(), "Hello there".to_string()))
+    );
+    // Only the first space ends the address
+    assert_eq!(
+        parse_request("misfin://bob@example.org # Subject\n\nBody text\r\n")
+            .unwrap()
+            .1,
+        "# Subject\n\nBody text"
+    );
+    assert!(parse_request("misfin://alice@example.org Hello").is_err());
+    assert!(parse_request("gemini://alice@example.org Hello\r\n").is_err());
+    assert!(parse_request("misfin://example.org Hello\r\n").is_err());
+    assert!(parse_request("misfin://alice@ Hello\r\n").is_err());
+}
+
+#[test]
+fn receives_mail() {
+    let (cert, key) = self_signed("me", KeyType::EcdsaP256, 1).unwrap();
+    let expected_fingerprint = fingerprint(&cert).unwrap();
+    let profile = Profile::from_pem("me".to_string(), cert, key, false).unwrap();
+    let (sender, received) = channel();
+    let sender = Mutex::new(sender);
+    let server = MailServer::start(
+        0,
+        profile.identity,
+        expected_fingerprint.clone(),
+        Arc::new(move |address: &str, message: &str| {
+            sender
+                .lock()
+                .unwrap()
+                .send((address.to_string(), message.to_string()))
+                .map_err(|e| e.to_string())
+        }),
+    )
+    .unwrap();
+
+    let url = Url::parse(&format!("misfin://me@127.0.0.1:{}", server.port)).unwrap();
+    let response = fetch(
+        &url,
+        &format!("{} # Hi\nHello from a test", url),
+        true,
+        Protocol::Misfin,
+    )
+    .unwrap();
+    assert!(matches!(
+        response.status,
+        ServerStatus::Gemini(GeminiStatus::Success(fingerprint)) if fingerprint == expected_fingerprint
+    ));
+    let address = format!("me@127.0.0.1:{}", server.port);
+    assert_eq!(
+        received.recv_timeout(Duration::from_secs(5)).unwrap(),
+        (address.clone(), "# Hi\nHello from a test".to_string())
+    );
+    assert_eq!(server.poll(), [address]);
+}
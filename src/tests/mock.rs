@@ -0,0 +1,172 @@
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, UdpSocket};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use native_tls::{Identity, TlsAcceptor};
+use rcgen::{CertificateParams, KeyPair};
+use url::Url;
+
+/// A stand-in server on a local port that answers a single request with a canned response
+pub struct MockServer {
+    pub port: u16,
+    request: JoinHandle<String>,
+}
+
+impl MockServer {
+    pub fn url(&self, scheme: &str, path: &str) -> Url {
+        Url::parse(&format!("{}://127.0.0.1:{}{}", scheme, self.port, path)).unwrap()
+    }
+
+    /// Waits for the exchange to finish and returns the request line the client sent
+    pub fn request(self) -> String {
+        self.request.join().expect("Mock server panicked")
+    }
+}
+
+// Reads up to and including the first CRLF, which ends the request line in every protocol
+fn read_request_line(stream: &mut impl Read) -> String {
+    let mut line = Vec::new();
+    let mut byte = [0];
+    while !line.ends_with(b"\r\n") && stream.read(&mut byte).unwrap_or(0) == 1 {
+        line.push(byte[0]);
+    }
+    String::from_utf8_lossy(&line).trim_end().to_string()
+}
+
+/// Serves the response over plain TCP, as Spartan, Gopher, Nex, Finger, Scorpion and the Text
+/// protocol do
+pub fn serve_tcp(response: impl Into<Vec<u8>>) -> MockServer {
+    let response = response.into();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let request = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let request = read_request_line(&mut stream);
+        stream.write_all(&response).unwrap();
+        // Closing with data such as a Spartan query still unread would reset the connection
+        stream.shutdown(Shutdown::Write).unwrap();
+        let _ = stream.read_to_end(&mut Vec::new());
+        request
+    });
+    MockServer { port, request }
+}
+
+fn self_signed_identity() -> Identity {
+    let key_pair = KeyPair::generate().unwrap();
+    let certificate = CertificateParams::new(vec!["localhost".to_string()])
+        .unwrap()
+        .self_signed(&key_pair)
+        .unwrap();
+    Identity::from_pkcs8(
+        certificate.pem().as_bytes(),
+        key_pair.serialize_pem().as_bytes(),
+    )
+    .unwrap()
+}
+
+/// Serves the response over TLS with a freshly made self-signed certificate, as Gemini and
+/// gophers do
+pub fn serve_tls(response: impl Into<Vec<u8>>) -> MockServer {
+    let response = response.into();
+    let acceptor = TlsAcceptor::new(self_signed_identity()).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let request = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut stream = acceptor.accept(stream).unwrap();
+        let request = read_request_line(&mut stream);
+        stream.write_all(&response).unwrap();
+        stream.shutdown().unwrap();
+        request
+    });
+    MockServer { port, request }
+}
+
+/// Serves each packet over UDP as Guppy does, waiting briefly for an acknowledgement after each
+pub fn serve_udp(packets: Vec<Vec<u8>>) -> MockServer {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let port = socket.local_addr().unwrap().port();
+    let request = thread::spawn(move || {
+        let mut buf = [0; 2048];
+        let (len, client) = socket.recv_from(&mut buf).unwrap();
+        let request = String::from_utf8_lossy(&buf[..len]).trim_end().to_string();
+        socket
+            .set_read_timeout(Some(Duration::from_millis(500)))
+            .unwrap();
+        for packet in packets {
+            socket.send_to(&packet, client).unwrap();
+            let _ = socket.recv_from(&mut buf);
+        }
+        request
+    });
+    MockServer { port, request }
+}
+
+/// Acts as a SOCKS5 proxy that answers for whatever host it is asked to connect to, returning
+/// the requested destination followed by the request line
+pub fn serve_socks5(response: impl Into<Vec<u8>>) -> MockServer {
+    let response = response.into();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let request = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut greeting = [0; 3];
+        stream.read_exact(&mut greeting).unwrap();
+        assert_eq!(greeting, [5, 1, 0]);
+        stream.write_all(&[5, 0]).unwrap();
+
+        let mut header = [0; 5];
+        stream.read_exact(&mut header).unwrap();
+        assert_eq!(
+            header[..4],
+            [5, 1, 0, 3],
+            "Expected a connection by host name"
+        );
+        let mut host = vec![0; header[4] as usize];
+        stream.read_exact(&mut host).unwrap();
+        let mut port = [0; 2];
+        stream.read_exact(&mut port).unwrap();
+        stream.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0]).unwrap();
+
+        let request = read_request_line(&mut stream);
+        stream.write_all(&response).unwrap();
+        stream.shutdown(Shutdown::Write).unwrap();
+        format!(
+            "{}:{} {}",
+            String::from_utf8_lossy(&host),
+            u16::from_be_bytes(port),
+            request
+        )
+    });
+    MockServer { port, request }
+}
+
+/// Plays the server side of an IRC session, answering each line the client sends with the
+/// lines `reply` gives for it, and returns everything the client sent once it quits or hangs up
+pub fn serve_irc(reply: impl Fn(&str) -> Vec<String> + Send + 'static) -> MockServer {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let request = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut sent = Vec::new();
+        loop {
+            let line = read_request_line(&mut stream);
+            if line.is_empty() {
+                break;
+            }
+            for response in reply(&line) {
+                stream
+                    .write_all(format!("{}\r\n", response).as_bytes())
+                    .unwrap();
+            }
+            let quit = line.starts_with("QUIT");
+            sent.push(line);
+            if quit {
+                break;
+            }
+        }
+        sent.join("\n")
+    });
+    MockServer { port, request }
+}
@@ -0,0 +1,14 @@
+//! Integration tests that fetch from in-process stand-ins for each protocol's servers
+
+mod certificates;
+mod database;
+mod dns;
+mod irc;
+mod malformed;
+mod misfin;
+mod mock;
+mod protocols;
+mod proxy;
+mod ratelimit;
+mod redirects;
+mod settings;
@@ -0,0 +1,301 @@
+use url::Url;
+
+use crate::handlers::{parse_content, parse_content_type, Protocol};
+use crate::networking::{
+    fetch, request_for, GeminiStatus, GuppyStatus, ScorpionStatus, ServerResponse, ServerStatus,
+    SpartanStatus, TextProtocolStatus,
+};
+
+use super::mock::{serve_tcp, serve_tls, serve_udp};
+
+pub fn fetch_from(url: &Url, protocol: Protocol) -> Result<ServerResponse, String> {
+    let (request, ssl) = request_for(url, protocol);
+    fetch(url, &request, ssl, protocol)
+}
+
+// Fetches a page from the server and renders it as plain text
+fn fetch_text(url: &Url, protocol: Protocol) -> (ServerStatus, String) {
+    let response = fetch_from(url, protocol).unwrap();
+    let document = parse_content(&response.content, false, protocol);
+    (response.status, document.to_text(url))
+}
+
+/// Builds a Scorpion document block
+pub fn scorpion_block(block_type: u8, attribute: &str, body: &str) -> Vec<u8> {
+    let mut block = vec![block_type];
+    block.extend_from_slice(&(attribute.len() as u16).to_be_bytes());
+    block.extend_from_slice(attribute.as_bytes());
+    block.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+    block.extend_from_slice(body.as_bytes());
+    block
+}
+
+#[test]
+fn gemini_success() {
+    let server = serve_tls("20 text/gemini\r\n# Welcome\n=> /next Next page\n* item\n");
+    let url = server.url("gemini", "/index.gmi");
+    let (status, text) = fetch_text(&url, Protocol::Gemini);
+    assert!(
+        matches!(status, ServerStatus::Gemini(GeminiStatus::Success(ct)) if ct == "text/gemini")
+    );
+    let next = url.join("/next").unwrap();
+    assert_eq!(
+        text,
+        format!("Welcome\n=======\n=> Next page <{}>\n* item\n", next)
+    );
+    assert_eq!(server.request(), url.as_str());
+}
+
+#[test]
+fn gemini_statuses() {
+    type Check = fn(&GeminiStatus) -> bool;
+    let cases: [(&str, Check); 8] = [
+        (
+            "10 Name?",
+            |s| matches!(s, GeminiStatus::InputExpected(p, false) if p == "Name?"),
+        ),
+        ("11 Password", |s| {
+            matches!(s, GeminiStatus::InputExpected(_, true))
+        }),
+        (
+            "30 /moved",
+            |s| matches!(s, GeminiStatus::TemporaryRedirect(u) if u == "/moved"),
+        ),
+        ("31 gemini://elsewhere/", |s| {
+            matches!(s, GeminiStatus::PermanentRedirect(_))
+        }),
+        (
+            "44 30",
+            |s| matches!(s, GeminiStatus::SlowDown(d) if d == "30"),
+        ),
+        (
+            "51 Not here",
+            |s| matches!(s, GeminiStatus::NotFound(m) if m == "Not here"),
+        ),
+        ("60 Certificate needed", |s| {
+            matches!(s, GeminiStatus::RequiresClientCertificate)
+        }),
+        // Meta is optional for some statuses
+        ("62", |s| matches!(s, GeminiStatus::CertificateNotValid)),
+    ];
+    for (status_line, expected) in cases {
+        let server = serve_tls(format!("{}\r\n", status_line));
+        let response = fetch_from(&server.url("gemini", "/"), Protocol::Gemini).unwrap();
+        match &response.status {
+            ServerStatus::Gemini(status) => {
+                assert!(expected(status), "{}: {:?}", status_line, status)
+            }
+            status => panic!("{}: unexpected status {:?}", status_line, status),
+        }
+        server.request();
+    }
+}
+
+#[test]
+fn spartan_success_and_query() {
+    let server = serve_tcp("2 text/gemini\r\n=: /search Search\nHello\n");
+    let url = server.url("spartan", "/");
+    let (status, text) = fetch_text(&url, Protocol::Spartan);
+    assert!(matches!(
+        status,
+        ServerStatus::Spartan(SpartanStatus::Success(_))
+    ));
+    let search = url.join("/search").unwrap();
+    assert_eq!(text, format!("=> [input] Search <{}>\nHello\n", search));
+    assert_eq!(server.request(), "127.0.0.1 / 0");
+
+    // Queries are sent as data after the request line
+    let server = serve_tcp("3 /elsewhere\r\n");
+    let url = server.url("spartan", "/search?hello");
+    let response = fetch_from(&url, Protocol::Spartan).unwrap();
+    assert!(
+        matches!(response.status, ServerStatus::Spartan(SpartanStatus::Redirect(u)) if u == "/elsewhere")
+    );
+    assert_eq!(server.request(), "127.0.0.1 /search 5");
+}
+
+#[test]
+fn gopher_menu() {
+    let menu = "iWelcome\tfake\t(NULL)\t0\r\n\
+                1Sub menu\t/sub\t127.0.0.1\t7070\r\n\
+                0About\t/about.txt\texample.org\t70\r\n\
+                7Search\t/search\texample.org\t70\r\n\
+                .\r\n";
+    let server = serve_tcp(menu);
+    let url = server.url("gopher", "/menu");
+    let (status, text) = fetch_text(&url, Protocol::Gopher(false));
+    assert!(matches!(status, ServerStatus::_Success(_)));
+    assert_eq!(
+        text,
+        "Welcome\n\
+         => Sub menu <gopher://127.0.0.1:7070/sub>\n\
+         => About <gopher://example.org/about.txt>\n\
+         => [input] Search <gopher://example.org/search>\n"
+    );
+    assert_eq!(server.request(), "/menu");
+}
+
+#[test]
+fn gophers_menu() {
+    let server = serve_tls("1Secure\t/secure\texample.org\t70\r\n.\r\n");
+    let url = server.url("gophers", "/");
+    let (_, text) = fetch_text(&url, Protocol::Gopher(true));
+    // Items on a secure menu stay on gophers
+    assert_eq!(text, "=> Secure <gophers://example.org/secure>\n");
+    assert_eq!(server.request(), "/");
+}
+
+#[test]
+fn gopher_search_query() {
+    let server = serve_tcp("iNo results\t\t\t0\r\n.\r\n");
+    let url = server.url("gopher", "/search?rust");
+    fetch_text(&url, Protocol::Gopher(false));
+    assert_eq!(server.request(), "/search\trust");
+}
+
+#[test]
+fn nex_directory() {
+    let server = serve_tcp("Notes\n=> notes/\n=> gemini://example.org/\n");
+    let url = server.url("nex", "/dir/");
+    let (status, text) = fetch_text(&url, Protocol::Nex);
+    assert!(matches!(status, ServerStatus::_Success(_)));
+    let notes = url.join("notes/").unwrap();
+    assert_eq!(
+        text,
+        format!("Notes\n=> {}\n=> gemini://example.org/\n", notes)
+    );
+    assert_eq!(server.request(), "/dir/");
+}
+
+#[test]
+fn finger_user() {
+    let server = serve_tcp("Login: alice\nPlan: see gemini://example.org/plan.\n");
+    let url = server.url("finger", "/alice");
+    let response = fetch_from(&url, Protocol::Finger).unwrap();
+    let document = parse_content(&response.content, false, Protocol::Finger);
+    assert_eq!(
+        document.to_text(&url),
+        "Login: alice\nPlan: see gemini://example.org/plan.\n"
+    );
+    assert_eq!(server.request(), "alice");
+}
+
+#[test]
+fn finger_verbose() {
+    let server = serve_tcp("Verbose\n");
+    fetch_from(&server.url("finger", "/w/alice"), Protocol::Finger).unwrap();
+    assert_eq!(server.request(), "/W alice");
+}
+
+#[test]
+fn scorpion_document() {
+    let mut response = b"20 \r\n".to_vec();
+    response.extend(scorpion_block(0x01, "", "Title"));
+    response.extend(scorpion_block(0x00, "", "Some text"));
+    response.extend(scorpion_block(0x08, "/next", "Next"));
+    response.extend(scorpion_block(0x0C, "", "Quoted"));
+    let server = serve_tcp(response);
+    let url = server.url("scorpion", "/");
+    let (status, text) = fetch_text(&url, Protocol::Scorpion);
+    assert!(matches!(status, ServerStatus::Scorpion(ScorpionStatus::OK)));
+    let next = url.join("/next").unwrap();
+    assert_eq!(
+        text,
+        format!("Title\n=====\nSome text\n=> Next <{}>\n> Quoted\n", next)
+    );
+    assert_eq!(server.request(), format!("R {}", url));
+}
+
+#[test]
+fn text_protocol_document() {
+    let server = serve_tcp("20 text/plain\r\nHello\n=> /about About\n");
+    let url = server.url("text", "/");
+    let response = fetch_from(&url, Protocol::TextProtocol).unwrap();
+    let ServerStatus::TextProtocol(TextProtocolStatus::OK(content_type)) = &response.status else {
+        panic!("Unexpected status {:?}", response.status);
+    };
+    let document = parse_content_type(
+        &response.content,
+        content_type,
+        false,
+        Protocol::TextProtocol,
+    );
+    let about = url.join("/about").unwrap();
+    assert_eq!(
+        document.to_text(&url),
+        format!("Hello\n=> About <{}>\n", about)
+    );
+    assert_eq!(server.request(), url.as_str());
+}
+
+#[test]
+fn text_protocol_redirect_and_failure() {
+    let server = serve_tcp("30 text://example.org/\r\n");
+    let response = fetch_from(&server.url("text", "/old"), Protocol::TextProtocol).unwrap();
+    assert!(matches!(
+        response.status,
+        ServerStatus::TextProtocol(TextProtocolStatus::Redirect(u)) if u == "text://example.org/"
+    ));
+    server.request();
+
+    let server = serve_tcp("40 Not found\r\n");
+    let response = fetch_from(&server.url("text", "/missing"), Protocol::TextProtocol).unwrap();
+    assert!(matches!(
+        response.status,
+        ServerStatus::TextProtocol(TextProtocolStatus::NOK(m)) if m == "Not found"
+    ));
+    server.request();
+}
+
+#[test]
+fn guppy_packets() {
+    let server = serve_udp(vec![
+        b"6 text/gemini\r\n# Guppy\n".to_vec(),
+        b"7\r\n=> /next Next\n".to_vec(),
+        b"8\r\n".to_vec(),
+    ]);
+    let url = server.url("guppy", "/");
+    let (status, text) = fetch_text(&url, Protocol::Guppy);
+    assert!(matches!(status, ServerStatus::Guppy(GuppyStatus::Success(ct)) if ct == "text/gemini"));
+    let next = url.join("/next").unwrap();
+    assert_eq!(text, format!("Guppy\n=====\n=> Next <{}>\n", next));
+    assert_eq!(server.request(), url.as_str());
+}
+
+#[test]
+fn guppy_single_packet() {
+    // A page that fits in one packet has no trailing newline to rely on
+    let server = serve_udp(vec![b"6 text/plain\r\nshort".to_vec(), b"7\r\n".to_vec()]);
+    let response = fetch_from(&server.url("guppy", "/"), Protocol::Guppy).unwrap();
+    assert_eq!(response.content, b"short");
+    server.request();
+}
+
+#[test]
+fn guppy_status_packets() {
+    let server = serve_udp(vec![b"1 Your name?\r\n".to_vec()]);
+    let response = fetch_from(&server.url("guppy", "/greet"), Protocol::Guppy).unwrap();
+    assert!(
+        matches!(response.status, ServerStatus::Guppy(GuppyStatus::Input(p)) if p == "Your name?")
+    );
+    server.request();
+
+    let server = serve_udp(vec![b"3 /moved\r\n".to_vec()]);
+    let response = fetch_from(&server.url("guppy", "/old"), Protocol::Guppy).unwrap();
+    assert!(
+        matches!(response.status, ServerStatus::Guppy(GuppyStatus::Redirect(u)) if u == "/moved")
+    );
+    server.request();
+
+    let server = serve_udp(vec![b"4 No such page\r\n".to_vec()]);
+    let response = fetch_from(&server.url("guppy", "/missing"), Protocol::Guppy).unwrap();
+    assert!(
+        matches!(response.status, ServerStatus::Guppy(GuppyStatus::Error(e)) if e == "No such page")
+    );
+    assert!(response.content.is_empty());
+    server.request();
+
+    let server = serve_udp(vec![b"2 text/gemini\r\n".to_vec()]);
+    assert!(fetch_from(&server.url("guppy", "/"), Protocol::Guppy).is_err());
+    server.request();
+}
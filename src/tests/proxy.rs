@@ -0,0 +1,99 @@
+use std::io::{Read, Write};
+
+use url::Url;
+
+use crate::handlers::Protocol;
+use crate::networking::{connect_through, fetch_via, request_for, GeminiStatus, ServerStatus};
+use crate::proxy::{split_address, Proxy};
+
+use super::mock::{serve_socks5, serve_tls};
+
+#[test]
+fn proxy_addresses() {
+    let cases = [
+        ("127.0.0.1:9050", ("127.0.0.1", 9050)),
+        ("proxy.example.org", ("proxy.example.org", 1965)),
+        (" [::1]:1080 ", ("[::1]", 1080)),
+        ("[::1]", ("[::1]", 1965)),
+    ];
+    for (address, (host, port)) in cases {
+        assert_eq!(
+            split_address(address, 1965).unwrap(),
+            (host.to_string(), port)
+        );
+    }
+    assert!(split_address("", 1080).is_err());
+    assert!(split_address("example.org:port", 1080).is_err());
+}
+
+// Host names are passed to the proxy to look up, so onion services work over Tor
+#[test]
+fn socks5_onion_service() {
+    let proxy = serve_socks5("=> /about About\n");
+    let url = Url::parse("nex://example.onion/docs/").unwrap();
+    let (request, ssl) = request_for(&url, Protocol::Nex);
+    let socks5 = Proxy::Socks5 {
+        address: format!("127.0.0.1:{}", proxy.port),
+    };
+    let response = fetch_via(&url, &request, ssl, Protocol::Nex, Some(&socks5)).unwrap();
+    assert_eq!(response.content, b"=> /about About\n");
+    assert_eq!(proxy.request(), "example.onion:1900 /docs/");
+}
+
+#[test]
+fn onion_services_need_a_proxy() {
+    let url = Url::parse("gemini://example.onion/").unwrap();
+    let error = fetch_via(&url, url.as_str(), true, Protocol::Gemini, None).unwrap_err();
+    assert!(error.contains("Tor"), "{}", error);
+}
+
+#[test]
+fn gemini_proxy_request() {
+    let server = serve_tls("20 application/gopher-menu\r\niAbout\t\t\t\r\n");
+    let url = Url::parse("gopher://example.org/1/menu").unwrap();
+    let (request, ssl) = request_for(&url, Protocol::Gopher(false));
+    let gemini = Proxy::Gemini {
+        address: format!("127.0.0.1:{}", server.port),
+    };
+    let response = fetch_via(&url, &request, ssl, Protocol::Gopher(false), Some(&gemini)).unwrap();
+    assert!(matches!(
+        response.status,
+        ServerStatus::Gemini(GeminiStatus::Success(ct)) if ct == "application/gopher-menu"
+    ));
+    // The proxy gets the full URL rather than the Gopher selector
+    assert_eq!(server.request(), url.as_str());
+}
+
+#[test]
+fn gemini_proxy_refusal() {
+    let server = serve_tls("53 Not a proxy\r\n");
+    let url = Url::parse("spartan://example.org/").unwrap();
+    let gemini = Proxy::Gemini {
+        address: format!("127.0.0.1:{}", server.port),
+    };
+    let response = fetch_via(&url, "", false, Protocol::Spartan, Some(&gemini)).unwrap();
+    assert!(matches!(
+        response.status,
+        ServerStatus::Gemini(GeminiStatus::ProxyRequestRefused(m)) if m == "Not a proxy"
+    ));
+}
+
+// Chat connections are tunnelled too, for networks only reachable over Tor
+#[test]
+fn socks5_chat_connection() {
+    let proxy = serve_socks5(":irc.example.onion 001 breeze :Welcome\r\n");
+    let socks5 = Proxy::Socks5 {
+        address: format!("127.0.0.1:{}", proxy.port),
+    };
+    let mut stream = connect_through("irc.example.onion", 6697, Some(&socks5)).unwrap();
+    stream.write_all(b"NICK breeze\r\n").unwrap();
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply).unwrap();
+    assert_eq!(reply, ":irc.example.onion 001 breeze :Welcome\r\n");
+    assert_eq!(proxy.request(), "irc.example.onion:6697 NICK breeze");
+
+    let gemini = Proxy::Gemini {
+        address: "127.0.0.1:1965".to_string(),
+    };
+    assert!(connect_through("irc.example.org", 6697, Some(&gemini)).is_err());
+}
@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use crate::handlers::Protocol;
+use crate::networking::{GeminiStatus, ServerStatus};
+use crate::ratelimit::{back_off, reserve, slow_down_duration, DEFAULT_SLOW_DOWN};
+
+use super::mock::serve_tls;
+use super::protocols::fetch_from;
+
+#[test]
+fn slow_down_meta() {
+    assert_eq!(slow_down_duration("30"), Duration::from_secs(30));
+    assert_eq!(slow_down_duration(" 5 seconds"), Duration::from_secs(5));
+    assert_eq!(slow_down_duration("0"), Duration::from_secs(1));
+    assert_eq!(slow_down_duration("99999"), Duration::from_secs(600));
+    assert_eq!(slow_down_duration(""), DEFAULT_SLOW_DOWN);
+    assert_eq!(slow_down_duration("soon"), DEFAULT_SLOW_DOWN);
+}
+
+#[test]
+fn requests_are_spaced_out() {
+    let server = "spaced.example:1965";
+    assert_eq!(reserve(server), Duration::ZERO);
+    let second = reserve(server);
+    let third = reserve(server);
+    assert!(second > Duration::from_millis(200), "{:?}", second);
+    assert!(third > second + Duration::from_millis(200), "{:?}", third);
+    // Other servers aren't held up
+    assert_eq!(reserve("other.example:1965"), Duration::ZERO);
+}
+
+#[test]
+fn slow_down_holds_off_the_server() {
+    let server = serve_tls("44 3\r\n");
+    let url = server.url("gemini", "/");
+    let response = fetch_from(&url, Protocol::Gemini).unwrap();
+    assert!(matches!(
+        response.status,
+        ServerStatus::Gemini(GeminiStatus::SlowDown(meta)) if meta == "3"
+    ));
+    server.request();
+    let wait = reserve(&format!("127.0.0.1:{}", url.port().unwrap()));
+    assert!(wait > Duration::from_secs(2), "{:?}", wait);
+
+    // Asking again only ever extends the wait
+    back_off("patient.example:1965", Duration::from_secs(60));
+    back_off("patient.example:1965", Duration::from_secs(1));
+    assert!(reserve("patient.example:1965") > Duration::from_secs(50));
+}
@@ -0,0 +1,64 @@
+use url::Url;
+
+use crate::redirect::{check_chain, confirmation_reason, forget, remember, remembered, resolve};
+
+fn url(url: &str) -> Url {
+    Url::parse(url).unwrap()
+}
+
+#[test]
+fn resolve_targets() {
+    let current = url("gemini://example.org/dir/page.gmi");
+    let cases = [
+        ("other.gmi", "gemini://example.org/dir/other.gmi"),
+        ("../up.gmi", "gemini://example.org/up.gmi"),
+        ("/root.gmi?q", "gemini://example.org/root.gmi?q"),
+        ("//elsewhere.org/", "gemini://elsewhere.org/"),
+        (" spartan://example.org/ ", "spartan://example.org/"),
+    ];
+    for (target, expected) in cases {
+        assert_eq!(resolve(&current, target).unwrap().as_str(), expected);
+    }
+    assert!(resolve(&current, "  ").is_err());
+    assert!(resolve(&current, "gemini://[bad").is_err());
+}
+
+#[test]
+fn chain_limits() {
+    let pages: Vec<Url> = (0..4)
+        .map(|i| url(&format!("gemini://example.org/{}", i)))
+        .collect();
+    assert!(check_chain(&pages[..1], &pages[1], 5).is_ok());
+    // Three redirects so far, a fourth would go over the limit of three
+    assert!(check_chain(&pages[..3], &pages[3], 3).is_ok());
+    let error = check_chain(&pages, &url("gemini://example.org/4"), 3).unwrap_err();
+    assert!(error.starts_with("Stopped after 3 redirects"), "{}", error);
+    let error = check_chain(&pages[..3], &pages[1], 5).unwrap_err();
+    assert!(error.starts_with("Redirect loop detected"), "{}", error);
+}
+
+#[test]
+fn cross_site_redirects_need_confirming() {
+    let from = url("gemini://example.org/a");
+    assert_eq!(
+        confirmation_reason(&from, &url("gemini://example.org/b")),
+        None
+    );
+    assert!(confirmation_reason(&from, &url("gemini://other.org/")).is_some());
+    assert!(confirmation_reason(&from, &url("https://example.org/a")).is_some());
+}
+
+#[test]
+fn forgotten_moves() {
+    let old = url("gemini://example.org/forgotten/old");
+    let new = url("gemini://example.org/forgotten/new");
+    let newer = url("gemini://example.org/forgotten/newer");
+    remember(&old, &new);
+    remember(&new, &newer);
+    assert_eq!(remembered(&old), Some(newer.clone()));
+    // The page moved to failing drops only the last move
+    forget(&newer);
+    assert_eq!(remembered(&old), Some(new.clone()));
+    forget(&new);
+    assert_eq!(remembered(&old), None);
+}
@@ -0,0 +1,55 @@
+use url::Url;
+
+use crate::download_name;
+use crate::proxy::Proxy;
+use crate::settings::{parse, Settings};
+
+#[test]
+fn missing_settings_use_defaults() {
+    let settings = parse(
+        r#"
+home_page = "gemini://example.org/"
+languages = ["fr", "en"]
+
+[ports]
+gemini = 1966
+"#,
+    )
+    .unwrap();
+    assert_eq!(settings.home_page, "gemini://example.org/");
+    assert_eq!(settings.languages, ["fr", "en"]);
+    assert_eq!(settings.port("gemini"), Some(1966));
+    assert_eq!(settings.port("spartan"), None);
+    assert_eq!(settings.connect_timeout_secs, 10);
+    assert_eq!(settings.cache, Settings::default().cache);
+
+    assert_eq!(parse("").unwrap(), Settings::default());
+    assert!(parse("window_width = \"wide\"").is_err());
+}
+
+#[test]
+fn settings_round_trip() {
+    let mut settings = Settings::default();
+    settings.ports.insert("gopher".to_string(), 7070);
+    let tor = Proxy::Socks5 {
+        address: "127.0.0.1:9050".to_string(),
+    };
+    settings.proxies.insert("gemini".to_string(), tor);
+    settings.cache.size_mb = 8;
+    settings.monospace_font = "/usr/share/fonts/mono.ttf".to_string();
+    let text = toml::to_string(&settings).unwrap();
+    assert_eq!(parse(&text).unwrap(), settings);
+}
+
+#[test]
+fn download_names() {
+    let cases = [
+        ("gemini://example.org/dir/page.gmi", "page.gmi"),
+        ("gemini://example.org/dir/", "dir"),
+        ("gemini://example.org/", "example.org"),
+        ("gopher://example.org/0/a%20file.txt", "a file.txt"),
+    ];
+    for (url, expected) in cases {
+        assert_eq!(download_name(&Url::parse(url).unwrap()), expected);
+    }
+}
@@ -0,0 +1,191 @@
+use std::fs;
+
+use eframe::egui::{Color32, RichText, Visuals};
+use serde::{Deserialize, Deserializer};
+
+/// Fonts, colours and layout used when rendering pages
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    pub name: String,
+    /// Whether to start from egui's dark visuals rather than its light ones
+    pub dark: bool,
+    pub body_size: f32,
+    pub monospace_size: f32,
+    /// Heading sizes for levels 1 to 6, as multiples of the body size
+    pub heading_scales: [f32; 6],
+    #[serde(deserialize_with = "deserialize_optional_color")]
+    pub text_color: Option<Color32>,
+    #[serde(deserialize_with = "deserialize_optional_color")]
+    pub background_color: Option<Color32>,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub link_color: Color32,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub visited_color: Color32,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub quote_color: Color32,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub preformat_color: Color32,
+    /// Maximum width of page content in points, or 0 to fill the window
+    pub content_width: f32,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::light()
+    }
+}
+
+impl Theme {
+    pub fn light() -> Self {
+        Self {
+            name: "Light".to_string(),
+            dark: false,
+            body_size: 14.0,
+            monospace_size: 14.0,
+            heading_scales: [
+                24.0 / 14.0,
+                22.0 / 14.0,
+                20.0 / 14.0,
+                18.0 / 14.0,
+                16.0 / 14.0,
+                1.0,
+            ],
+            text_color: None,
+            background_color: None,
+            link_color: Color32::from_rgb(0x1f, 0x4f, 0xd1),
+            visited_color: Color32::from_rgb(0x7b, 0x3f, 0xb5),
+            quote_color: Color32::from_rgb(0x55, 0x55, 0x55),
+            preformat_color: Color32::from_rgb(0x30, 0x30, 0x30),
+            content_width: 0.0,
+        }
+    }
+
+    pub fn dark() -> Self {
+        Self {
+            name: "Dark".to_string(),
+            dark: true,
+            link_color: Color32::from_rgb(0x8a, 0xb4, 0xf8),
+            visited_color: Color32::from_rgb(0xc5, 0x8a, 0xf9),
+            quote_color: Color32::from_rgb(0xa0, 0xa0, 0xa0),
+            preformat_color: Color32::from_rgb(0xdc, 0xdc, 0xdc),
+            ..Self::light()
+        }
+    }
+
+    pub fn high_contrast() -> Self {
+        Self {
+            name: "High Contrast".to_string(),
+            dark: true,
+            body_size: 16.0,
+            monospace_size: 16.0,
+            text_color: Some(Color32::WHITE),
+            background_color: Some(Color32::BLACK),
+            link_color: Color32::YELLOW,
+            visited_color: Color32::from_rgb(0x00, 0xff, 0xff),
+            quote_color: Color32::WHITE,
+            preformat_color: Color32::WHITE,
+            ..Self::light()
+        }
+    }
+
+    /// The built-in themes followed by any found in the user's theme directory
+    pub fn all() -> Vec<Theme> {
+        let mut themes = vec![Theme::light(), Theme::dark(), Theme::high_contrast()];
+        let Some(dir) = dirs::config_dir().map(|dir| dir.join("breeze").join("themes")) else {
+            return themes;
+        };
+        let Ok(entries) = fs::read_dir(&dir) else {
+            return themes;
+        };
+        for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+            if path.extension().is_none_or(|ext| ext != "toml") {
+                continue;
+            }
+            match fs::read_to_string(&path).map(|text| toml::from_str::<Theme>(&text)) {
+                Ok(Ok(mut theme)) => {
+                    if theme.name == Theme::light().name {
+                        theme.name = path.file_stem().unwrap().to_string_lossy().to_string();
+                    }
+                    themes.push(theme);
+                }
+                Ok(Err(e)) => println!("Failed to parse theme {}: {}", path.display(), e),
+                Err(e) => println!("Failed to read theme {}: {}", path.display(), e),
+            }
+        }
+        themes
+    }
+
+    pub fn visuals(&self) -> Visuals {
+        let mut visuals = if self.dark {
+            Visuals::dark()
+        } else {
+            Visuals::light()
+        };
+        visuals.hyperlink_color = self.link_color;
+        visuals.override_text_color = self.text_color;
+        if let Some(background) = self.background_color {
+            visuals.panel_fill = background;
+            visuals.window_fill = background;
+            visuals.extreme_bg_color = background;
+        }
+        visuals
+    }
+
+    pub fn text(&self, text: impl Into<String>) -> RichText {
+        RichText::new(text).size(self.body_size)
+    }
+
+    pub fn monospace(&self, text: impl Into<String>) -> RichText {
+        RichText::new(text).monospace().size(self.monospace_size)
+    }
+
+    pub fn heading(&self, level: usize, text: impl Into<String>) -> RichText {
+        let scale = self.heading_scales[level.clamp(1, 6) - 1];
+        RichText::new(text).size(self.body_size * scale)
+    }
+
+    pub fn link(&self, text: RichText, visited: bool) -> RichText {
+        let color = if visited {
+            self.visited_color
+        } else {
+            self.link_color
+        };
+        text.color(color).underline()
+    }
+
+    pub fn quote(&self, text: impl Into<String>) -> RichText {
+        self.text(text).italics().color(self.quote_color)
+    }
+
+    pub fn preformatted(&self, text: impl Into<String>) -> RichText {
+        self.monospace(text).color(self.preformat_color)
+    }
+}
+
+fn parse_hex_color(hex: &str) -> Option<Color32> {
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    match hex.len() {
+        6 => Some(Color32::from_rgb(channel(0)?, channel(2)?, channel(4)?)),
+        8 => Some(Color32::from_rgba_unmultiplied(
+            channel(0)?,
+            channel(2)?,
+            channel(4)?,
+            channel(6)?,
+        )),
+        _ => None,
+    }
+}
+
+// Theme files write colours as "#RRGGBB" or "#RRGGBBAA"
+fn deserialize_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color32, D::Error> {
+    let hex = String::deserialize(deserializer)?;
+    parse_hex_color(&hex).ok_or_else(|| serde::de::Error::custom(format!("invalid colour {}", hex)))
+}
+
+fn deserialize_optional_color<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Color32>, D::Error> {
+    deserialize_color(deserializer).map(Some)
+}